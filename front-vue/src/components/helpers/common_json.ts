@@ -10,9 +10,16 @@ export interface Category {
   id: number;
   name: string;
   share_with: number[];
+  visible_to: Visibility | null;
+  event?: EventKind | null;
   gifts: Gift[];
 }
 
+export interface Visibility {
+  groups: number[];
+  friends: number[];
+}
+
 export interface Gift {
   id: number;
   name: string;
@@ -20,7 +27,9 @@ export interface Gift {
   price: string | null;
   where_to_buy: string | null;
   picture: string | null;
+  pictures: string[];
   heart: boolean;
+  event?: EventKind | null;
 }
 
 export interface FriendWishlist {
@@ -30,6 +39,7 @@ export interface FriendWishlist {
 export interface FriendCategory {
   id: number;
   name: string;
+  event?: EventKind | null;
   gifts: FriendGift[];
 }
 
@@ -40,9 +50,11 @@ export interface FriendGift {
   price: string | null;
   where_to_buy: string | null;
   picture: string | null;
+  pictures: string[];
   heart: boolean;
   secret: boolean;
   reserved_by: number | null;
+  event?: EventKind | null;
 }
 
 export interface Friends {
@@ -56,6 +68,22 @@ export interface Friend {
   date_of_birth: number | null;
 }
 
+export interface LinkedAccounts {
+  linked: Friend[];
+  sent: Friend[];
+  received: Friend[];
+}
+
+export interface FriendGroups {
+  groups: FriendGroup[];
+}
+
+export interface FriendGroup {
+  id: number;
+  name: string;
+  members: number[];
+}
+
 export interface PendingFriendRequests {
   sent: FriendRequest[];
   received: FriendRequest[];
@@ -81,4 +109,73 @@ export interface EventJson {
   name: string | null;
   picture: string | null;
   birth: number | null;
+  gifts: EventGift[];
+}
+
+export interface EventGift {
+  id: number;
+  friend_id: number;
+  name: string;
+  free: boolean;
+}
+
+export interface UserSettings {
+  time_zone: string | null;
+  reminders: boolean;
+  reminder_days: number;
+  discoverable: boolean;
+}
+
+export enum Relation {
+  NONE = "none",
+  FRIEND = "friend",
+  REQUEST_SENT = "request_sent",
+  REQUEST_RECEIVED = "request_received",
+}
+
+export interface ProfileField {
+  value: string;
+  visible: boolean;
+}
+
+export interface Profile {
+  display_name: string | null;
+  bio: ProfileField | null;
+  clothing_size: ProfileField | null;
+  shoe_size: ProfileField | null;
+  ring_size: ProfileField | null;
+  favourite_colors: ProfileField | null;
+  favourite_brands: ProfileField | null;
+  please_no: ProfileField | null;
+}
+
+export interface FoundUser {
+  id: number;
+  name: string;
+  display_name: string | null;
+  picture: string | null;
+  mutual_friends: number;
+  relation: Relation;
+}
+
+export interface Suggestion {
+  id: number;
+  name: string;
+  display_name: string | null;
+  picture: string | null;
+  mutual_friends: number;
+}
+
+export interface Digest {
+  kind: EventKind;
+  date: number;
+  friend_id: number | null;
+  name: string | null;
+  gifts: EventGift[];
+}
+
+export interface NotificationJson {
+  id: number;
+  created_at: number;
+  digest: Digest;
 }
@@ -7,6 +7,11 @@ export enum Languages {
   English = "English",
 }
 
+/** As understood by the server, for the texts it generates **/
+export function languageCode(language: Languages): string {
+  return language === Languages.Francais ? "fr" : "en";
+}
+
 interface State {
   language: Languages;
   messages: Messages;
@@ -99,18 +99,21 @@ where
         };
         debug!("Processing user {}", claims.id);
 
-        let Some(current_session) = self.layer.session_manager.get_session(claims.id) else {
-            error!("No session available for user {}", claims.id);
-            return Self::unauthorized();
+        let signed_in = if self.layer.configuration.debug {
+            self.layer.session_manager.is_signed_in_anywhere(claims.id)
+        } else {
+            self.layer.session_manager.is_signed_in(&session, claims.id)
         };
-
-        if !self.layer.configuration.debug && current_session != session {
-            error!("Session mismatch: received {session} while expecting {current_session}");
+        if !signed_in {
+            error!("User {} is not signed in session {session}", claims.id);
             return Self::unauthorized();
         }
 
         let mut request = Request::from_parts(parts, body);
-        request.extensions_mut().insert(AuthUser { id: claims.id });
+        request.extensions_mut().insert(AuthUser {
+            id: claims.id,
+            session,
+        });
         let future = self.inner.call(request);
         Box::pin(async move {
             let response: Response = future.await?;
@@ -132,6 +135,8 @@ where
 #[derive(Clone)]
 pub struct AuthUser {
     pub id: i64,
+    /** Browser session the user is signed in **/
+    pub session: String,
 }
 
 impl<S> FromRequestParts<S> for AuthUser
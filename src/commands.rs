@@ -15,7 +15,12 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Commands {
     Server,
-    CleanPictures,
+    /// Compare the stored files with the pictures in use
+    CheckFiles {
+        /// Register, fix and delete what is wrong instead of only reporting it
+        #[arg(short, long)]
+        repair: bool,
+    },
     ResetPassword {
         #[arg(short, long)]
         name: String,
@@ -1,4 +1,5 @@
 use serde::Deserialize;
+use std::num::NonZeroU64;
 
 #[derive(Clone, Deserialize)]
 pub struct Configuration {
@@ -8,7 +9,92 @@ pub struct Configuration {
     pub database: String,
     pub upload_file_storage: String,
     pub front_dir: String,
+    pub storage: Option<StorageConfiguration>,
+    #[serde(default)]
+    pub uploads: UploadsConfiguration,
+    /** Sign-up is only allowed with an invitation **/
+    #[serde(default)]
+    pub invite_only: bool,
+    /** Searches allowed per user in each window **/
+    #[serde(default)]
+    pub search_rate_limit: RateLimitConfiguration,
 
     pub cert_pem: Option<String>,
     pub key_pem: Option<String>,
+
+    pub reminders: Option<RemindersConfiguration>,
+}
+
+/**
+Pictures bigger than `max_width` x `max_height` are refused, the others being resized once
+uploaded so that neither side is longer than `max_stored_side`
+**/
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct UploadsConfiguration {
+    /** Size of the whole upload request, in bytes **/
+    pub max_size: usize,
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_stored_side: u32,
+}
+
+impl Default for UploadsConfiguration {
+    fn default() -> Self {
+        Self {
+            max_size: 20 * 1024 * 1024,
+            max_width: 10000,
+            max_height: 10000,
+            max_stored_side: 1760,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfiguration {
+    pub max_requests: u32,
+    pub window_seconds: i64,
+}
+
+impl Default for RateLimitConfiguration {
+    fn default() -> Self {
+        Self {
+            max_requests: 30,
+            window_seconds: 60,
+        }
+    }
+}
+
+/**
+Uploads are stored in `upload_file_storage` when not set
+**/
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StorageConfiguration {
+    Local,
+    S3 {
+        endpoint: String,
+        region: String,
+        bucket: String,
+        access_key_id: String,
+        secret_access_key: String,
+        /** Only for local test endpoints, credentials being sent in clear otherwise **/
+        #[serde(default)]
+        allow_http: bool,
+    },
+}
+
+#[derive(Clone, Deserialize)]
+pub struct RemindersConfiguration {
+    pub notifier: NotifierConfiguration,
+    /** Loading the configuration fails on 0, the reminders task needing a period **/
+    pub interval_minutes: NonZeroU64,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotifierConfiguration {
+    InApp,
+    Webhook { url: String },
 }
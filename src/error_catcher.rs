@@ -1,8 +1,13 @@
 use crate::managers::events_manager::EventsManagerError;
+use crate::managers::files_manager::FilesManagerError;
 use crate::managers::friends_manager::FriendsManagerError;
+use crate::managers::invitations_manager::InvitationsManagerError;
 use crate::managers::jwt_manager::JwtManagerError;
+use crate::managers::notifications_manager::NotificationsManagerError;
+use crate::managers::pdf_generator::PdfError;
 use crate::managers::users_manager::UsersManagerError;
 use crate::managers::wishlist_manager::WishlistManagerError;
+use axum::extract::multipart::MultipartError;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
@@ -16,11 +21,22 @@ pub(crate) enum AppError {
     JwtManager(#[from] JwtManagerError),
     EventsManager(#[from] EventsManagerError),
     FriendsManager(#[from] FriendsManagerError),
+    InvitationsManager(#[from] InvitationsManagerError),
     WishlistManager(#[from] WishlistManagerError),
+    NotificationsManager(#[from] NotificationsManagerError),
+    FilesManager(#[from] FilesManagerError),
+    Multipart(#[from] MultipartError),
+    Pdf(#[from] PdfError),
     #[error("Unauthorized")]
     Unauthorized,
     #[error("Conflict")]
     Conflict,
+    #[error("Bad request")]
+    BadRequest,
+    #[error("Not found")]
+    NotFound,
+    #[error("Too many requests")]
+    TooManyRequests,
 }
 
 #[derive(Serialize)]
@@ -35,13 +51,41 @@ impl IntoResponse for AppError {
             | AppError::UsersManager(UsersManagerError::UnknownUser(_))
             | AppError::Unauthorized => StatusCode::UNAUTHORIZED.into_response(),
             AppError::UsersManager(UsersManagerError::UserAlreadyExist(_))
+            | AppError::UsersManager(UsersManagerError::DependentAccount(_))
+            | AppError::UsersManager(UsersManagerError::LastGuardian(_, _))
+            | AppError::UsersManager(UsersManagerError::CannotLinkYourself(_))
             | AppError::FriendsManager(FriendsManagerError::FriendRequestAlreadyExists(_, _))
             | AppError::FriendsManager(FriendsManagerError::CannotAskYourself(_))
+            | AppError::FriendsManager(FriendsManagerError::CannotBlockYourself(_))
+            | AppError::FriendsManager(FriendsManagerError::UserBlocked(_, _))
+            | AppError::FriendsManager(FriendsManagerError::DerivedFriendship(_, _))
+            | AppError::InvitationsManager(InvitationsManagerError::CannotRedeemYourInvitation(
+                _,
+            ))
+            | AppError::InvitationsManager(InvitationsManagerError::UsersManager(
+                UsersManagerError::UserAlreadyExist(_),
+            ))
+            | AppError::InvitationsManager(InvitationsManagerError::UsersManager(
+                UsersManagerError::DependentAccount(_),
+            ))
             | AppError::Conflict => StatusCode::CONFLICT.into_response(),
             AppError::FriendsManager(FriendsManagerError::UnknownUser(_))
-            | AppError::FriendsManager(FriendsManagerError::FriendRequestDoesNotExists(_, _)) => {
-                StatusCode::NOT_FOUND.into_response()
+            | AppError::FriendsManager(FriendsManagerError::FriendRequestDoesNotExists(_, _))
+            | AppError::EventsManager(EventsManagerError::UnknownCalendarToken)
+            | AppError::InvitationsManager(InvitationsManagerError::UnknownInvitation)
+            | AppError::NotificationsManager(NotificationsManagerError::UnknownNotification(_))
+            | AppError::NotFound => StatusCode::NOT_FOUND.into_response(),
+            AppError::UsersManager(UsersManagerError::UnknownTimeZone(_))
+            | AppError::UsersManager(UsersManagerError::InvalidReminderDays(_))
+            | AppError::UsersManager(UsersManagerError::ProfileFieldTooLong(_))
+            | AppError::WishlistManager(WishlistManagerError::PicturesMismatch(_))
+            | AppError::FilesManager(FilesManagerError::InvalidPicture(_))
+            | AppError::BadRequest => StatusCode::BAD_REQUEST.into_response(),
+            AppError::FilesManager(FilesManagerError::PictureTooLarge) => {
+                StatusCode::PAYLOAD_TOO_LARGE.into_response()
             }
+            AppError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS.into_response(),
+            AppError::Multipart(error) => (error.status(), error.body_text()).into_response(),
             _ => {
                 error!("Got an error {self}");
                 (
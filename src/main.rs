@@ -1,39 +1,48 @@
+use crate::clock::{Clock, SystemClock};
 use crate::commands::{Cli, Commands};
-use crate::configuration::Configuration;
+use crate::configuration::{Configuration, NotifierConfiguration};
+use crate::managers::deletions_manager::DeletionsManager;
 use crate::managers::events_manager::EventsManager;
+use crate::managers::files_manager::FilesManager;
 use crate::managers::friends_manager::FriendsManager;
+use crate::managers::invitations_manager::InvitationsManager;
 use crate::managers::jwt_manager::JwtManager;
+use crate::managers::notifications_manager::NotificationsManager;
+use crate::managers::reminders_manager::{Notifier, RemindersManager, WebhookNotifier};
 use crate::managers::session_manager::SessionManager;
 use crate::managers::users_manager::UsersManager;
 use crate::managers::wishlist_manager::WishlistManager;
-use crate::routes::files::resize_file;
+use crate::rate_limiter::RateLimiter;
 use crate::routes::{create_api_routes, AppState};
-use axum::extract::DefaultBodyLimit;
+use crate::storage::create_storages;
+use axum::body::Body;
 use axum::http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION, CONTENT_TYPE};
-use axum::http::Method;
+use axum::http::{Method, Request};
 use axum::Router;
 use axum_server::tls_rustls::RustlsConfig;
 use clap::Parser;
-use rusqlite::params;
-use std::collections::HashSet;
 use std::fs;
 use std::net::SocketAddr;
-use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
+use std::time::Duration;
 use tower_http::cors::CorsLayer;
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
-use tracing::{debug, info};
+use tracing::{debug, debug_span, error, info, Span};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
 mod auth_middleware;
+mod clock;
 mod commands;
 mod configuration;
 mod error_catcher;
 mod managers;
+mod rate_limiter;
 mod routes;
+mod storage;
+
+const DELETIONS_INTERVAL_SECONDS: u64 = 60 * 60;
 
 #[tokio::main()]
 async fn main() {
@@ -51,59 +60,42 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    match cli.command {
-        Some(Commands::CleanPictures) => {
-            debug!("Cleaning pictures");
-            let connection = rusqlite::Connection::open(&configuration.database).unwrap();
-            let mut pictures = HashSet::new();
-            let mut statement = connection
-                .prepare("SELECT picture FROM users WHERE picture IS NOT NULL")
-                .unwrap();
-            let mut rows = statement.query(params![]).unwrap();
-            while let Some(row) = rows.next().unwrap() {
-                pictures.insert(row.get::<_, String>(0).unwrap());
-            }
-            let mut statement = connection
-                .prepare("SELECT picture FROM gifts WHERE picture IS NOT NULL")
-                .unwrap();
-            let mut rows = statement.query(params![]).unwrap();
-            while let Some(row) = rows.next().unwrap() {
-                pictures.insert(row.get::<_, String>(0).unwrap());
-            }
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
 
-            debug!("All used pictures:({}) {pictures:?}", pictures.len());
-            let mut to_delete = Vec::new();
-            for file in PathBuf::from(&configuration.upload_file_storage)
-                .read_dir()
-                .unwrap()
-            {
-                let file = file.unwrap();
-                let file_path = file.path();
-                let file_name = file_path.file_name().unwrap().to_str().unwrap();
-                if !pictures.remove(file_name) {
-                    to_delete.push(file_path);
-                }
-            }
-            debug!("All missing pictures: ({}) {pictures:?}", pictures.len());
-            debug!(
-                "All to delete pictures: ({}) {to_delete:?}",
-                to_delete.len()
+    match cli.command {
+        Some(Commands::CheckFiles { repair }) => {
+            debug!("Checking files");
+            let connection = Arc::new(Mutex::new(
+                rusqlite::Connection::open(&configuration.database).unwrap(),
+            ));
+            //Tables referenced by the files triggers, for databases not migrated yet
+            UsersManager::new(connection.clone(), clock.clone()).unwrap();
+            WishlistManager::new(connection.clone()).unwrap();
+            let storages = create_storages(&configuration).unwrap();
+            let files_manager = FilesManager::new(connection, storages, clock).unwrap();
+            let report = files_manager.check(repair).await.unwrap();
+            info!(
+                "Missing files: ({}) {:?}",
+                report.missing.len(),
+                report.missing
+            );
+            info!(
+                "Unregistered files: ({}) {:?}",
+                report.unregistered.len(),
+                report.unregistered
+            );
+            info!(
+                "Wrong reference counts: ({}) {:?}",
+                report.wrong_counts.len(),
+                report.wrong_counts
+            );
+            info!(
+                "Orphan files: ({}) {:?}",
+                report.orphans.len(),
+                report.orphans
             );
-            let tmp_path = PathBuf::from(configuration.upload_file_storage)
-                .parent()
-                .unwrap()
-                .join("tmp_rs");
-            for file in to_delete {
-                let stem = file.file_stem().unwrap().to_str().unwrap().to_lowercase();
-                let extension = file.extension().unwrap().to_str().unwrap().to_lowercase();
-                if extension == "jpeg" || extension == "jpg" {
-                    fs::remove_file(tmp_path.join(format!("{stem}.jpg"))).unwrap();
-                } else if extension == "png" || extension == "webp" {
-                    fs::remove_file(tmp_path.join(format!("{stem}.webp"))).unwrap();
-                } else {
-                    fs::remove_file(tmp_path.join(format!("{stem}.{extension}"))).unwrap();
-                }
-                fs::remove_file(file).unwrap()
+            if repair {
+                info!("Files repaired, missing files have to be fixed manually");
             }
             return;
         }
@@ -112,7 +104,7 @@ async fn main() {
             let connection = Arc::new(Mutex::new(
                 rusqlite::Connection::open(&configuration.database).unwrap(),
             ));
-            let users_manager = UsersManager::new(connection).unwrap();
+            let users_manager = UsersManager::new(connection, clock).unwrap();
             let uuid = users_manager.create_password_reset_request(&name).unwrap();
             info!("Request have been created: {uuid}");
             return;
@@ -162,36 +154,79 @@ async fn main() {
         return;
     }*/
 
-    //Should it be done at starts ?
-    let root_path = PathBuf::from(&configuration.upload_file_storage);
-    let root_output_path = root_path.parent().unwrap().to_path_buf().join("tmp_rs");
-    fs::create_dir_all(&root_output_path).unwrap();
-    let dir = root_path.read_dir().unwrap();
-
-    let grand_total = SystemTime::now();
-    for file in dir {
-        let file = file.unwrap();
-        let file_path = file.path();
-        resize_file(&file_path, &root_output_path);
-        debug!("File resized");
-    }
-    debug!(
-        "Process all in {} ms",
-        grand_total.elapsed().unwrap().as_millis()
-    );
-
     let connection = Arc::new(Mutex::new(
         rusqlite::Connection::open(&configuration.database).unwrap(),
     ));
 
-    let users_manager = UsersManager::new(connection.clone()).unwrap();
+    let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
     let jwt_manager = Arc::new(JwtManager::default());
-    let session_manager = SessionManager::default();
+    let session_manager = SessionManager::new(clock.clone());
     let friends_manager = FriendsManager::new(connection.clone()).unwrap();
-    let events_manager = EventsManager {
-        friends_manager: friends_manager.clone(),
-    };
     let wishlist_manager = WishlistManager::new(connection.clone()).unwrap();
+    let invitations_manager = InvitationsManager::new(connection.clone(), clock.clone()).unwrap();
+    let events_manager = EventsManager::new(
+        connection.clone(),
+        friends_manager.clone(),
+        wishlist_manager.clone(),
+        clock.clone(),
+    )
+    .unwrap();
+    let storages = create_storages(&configuration).unwrap();
+    let files_manager =
+        FilesManager::new(connection.clone(), storages.clone(), clock.clone()).unwrap();
+
+    tokio::spawn(files_manager.clone().run_image_worker());
+    {
+        let files_manager = files_manager.clone();
+        tokio::spawn(async move {
+            if let Err(e) = files_manager.queue_missing_variants().await {
+                error!("Cannot queue missing image variants: {e}");
+            }
+        });
+    }
+    let notifications_manager =
+        NotificationsManager::new(connection.clone(), clock.clone()).unwrap();
+
+    let reminders_manager = configuration.reminders.as_ref().map(|reminders| {
+        let notifier: Arc<dyn Notifier> = match &reminders.notifier {
+            NotifierConfiguration::InApp => Arc::new(notifications_manager.clone()),
+            NotifierConfiguration::Webhook { url } => Arc::new(WebhookNotifier::new(url.clone())),
+        };
+        let reminders_manager = RemindersManager::new(
+            connection.clone(),
+            users_manager.clone(),
+            events_manager.clone(),
+            notifier,
+            clock.clone(),
+        )
+        .unwrap();
+        tokio::spawn(
+            reminders_manager
+                .clone()
+                .run(Duration::from_secs(reminders.interval_minutes.get() * 60)),
+        );
+        reminders_manager
+    });
+
+    let deletions_manager = DeletionsManager::new(
+        users_manager.clone(),
+        friends_manager.clone(),
+        wishlist_manager.clone(),
+        invitations_manager.clone(),
+        notifications_manager.clone(),
+        events_manager.clone(),
+        files_manager.clone(),
+        session_manager.clone(),
+        reminders_manager,
+    );
+    tokio::spawn(deletions_manager.run(Duration::from_secs(DELETIONS_INTERVAL_SECONDS)));
+
+    let search_rate_limiter = RateLimiter::new(
+        configuration.search_rate_limit.max_requests,
+        chrono::Duration::seconds(configuration.search_rate_limit.window_seconds),
+        clock.clone(),
+    );
+
     let serve_dir = ServeDir::new(&configuration.front_dir);
     let configuration = Arc::new(configuration);
 
@@ -201,8 +236,13 @@ async fn main() {
         session_manager: session_manager.clone(),
         events_manager,
         friends_manager,
+        invitations_manager,
         wishlist_manager,
+        notifications_manager,
+        files_manager,
+        search_rate_limiter,
         configuration: configuration.clone(),
+        clock,
     };
 
     let cors_layer = if configuration.debug {
@@ -227,9 +267,8 @@ async fn main() {
             create_api_routes(session_manager, jwt_manager, configuration.clone()),
         )
         .with_state(app_state)
-        .layer(TraceLayer::new_for_http())
+        .layer(TraceLayer::new_for_http().make_span_with(make_request_span))
         .layer(cors_layer)
-        .layer(DefaultBodyLimit::disable())
         .nest_service("/signin", serve_dir.clone())
         .nest_service("/signup", serve_dir.clone())
         .nest_service("/mywishlist", serve_dir.clone())
@@ -256,3 +295,16 @@ async fn main() {
             .unwrap()
     }
 }
+
+/**
+The default span of the trace layer, without the secret token of calendar feeds
+**/
+fn make_request_span(request: &Request<Body>) -> Span {
+    let path = request.uri().path();
+    let uri = if path.starts_with("/api/events/calendar/") {
+        "/api/events/calendar/[redacted]".to_string()
+    } else {
+        request.uri().to_string()
+    };
+    debug_span!("request", method = %request.method(), uri, version = ?request.version())
+}
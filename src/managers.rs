@@ -1,10 +1,36 @@
+pub(crate) mod deletions_manager;
 pub(crate) mod events_manager;
+pub(crate) mod files_manager;
 pub(crate) mod friends_manager;
+pub(crate) mod invitations_manager;
 pub(crate) mod jwt_manager;
+pub(crate) mod notifications_manager;
 pub(crate) mod pdf_generator;
+pub(crate) mod reminders_manager;
 pub(crate) mod session_manager;
 pub(crate) mod users_manager;
 pub(crate) mod wishlist_manager;
 
 #[cfg(test)]
-mod test_helper;
+pub(crate) mod test_helper;
+
+use rusqlite::{params, Connection};
+
+/**
+Tables are created with `CREATE TABLE IF NOT EXISTS`, so columns added afterward have to be added
+to already existing databases.
+**/
+pub(crate) fn add_column_if_missing(
+    connection: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let mut statement = connection.prepare("SELECT 1 FROM pragma_table_info(?) WHERE name=?")?;
+    if !statement.exists(params![table, column])? {
+        connection.execute_batch(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+        ))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,260 @@
+use crate::managers::events_manager::{EventsManager, EventsManagerError};
+use crate::managers::files_manager::{FilesManager, FilesManagerError};
+use crate::managers::friends_manager::{FriendsManager, FriendsManagerError};
+use crate::managers::invitations_manager::{InvitationsManager, InvitationsManagerError};
+use crate::managers::notifications_manager::{NotificationsManager, NotificationsManagerError};
+use crate::managers::reminders_manager::{RemindersManager, RemindersManagerError};
+use crate::managers::session_manager::SessionManager;
+use crate::managers::users_manager::{UsersManager, UsersManagerError};
+use crate::managers::wishlist_manager::{WishlistManager, WishlistManagerError};
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+/**
+Delete the accounts whose grace period is over, with everything referring to them
+**/
+#[derive(Clone)]
+pub struct DeletionsManager {
+    users_manager: UsersManager,
+    friends_manager: FriendsManager,
+    wishlist_manager: WishlistManager,
+    invitations_manager: InvitationsManager,
+    notifications_manager: NotificationsManager,
+    events_manager: EventsManager,
+    files_manager: FilesManager,
+    session_manager: SessionManager,
+    reminders_manager: Option<RemindersManager>,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub(crate) enum DeletionsManagerError {
+    Users(#[from] UsersManagerError),
+    Friends(#[from] FriendsManagerError),
+    Wishlist(#[from] WishlistManagerError),
+    Invitations(#[from] InvitationsManagerError),
+    Notifications(#[from] NotificationsManagerError),
+    Events(#[from] EventsManagerError),
+    Files(#[from] FilesManagerError),
+    Reminders(#[from] RemindersManagerError),
+}
+
+impl DeletionsManager {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        users_manager: UsersManager,
+        friends_manager: FriendsManager,
+        wishlist_manager: WishlistManager,
+        invitations_manager: InvitationsManager,
+        notifications_manager: NotificationsManager,
+        events_manager: EventsManager,
+        files_manager: FilesManager,
+        session_manager: SessionManager,
+        reminders_manager: Option<RemindersManager>,
+    ) -> Self {
+        Self {
+            users_manager,
+            friends_manager,
+            wishlist_manager,
+            invitations_manager,
+            notifications_manager,
+            events_manager,
+            files_manager,
+            session_manager,
+            reminders_manager,
+        }
+    }
+
+    pub async fn run(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match self.delete_expired_accounts().await {
+                Ok(deleted) => debug!("{deleted} accounts deleted"),
+                Err(e) => error!("Cannot delete accounts: {e}"),
+            }
+        }
+    }
+
+    pub async fn delete_expired_accounts(&self) -> Result<usize, DeletionsManagerError> {
+        let mut deleted = 0;
+        for user_id in self.users_manager.get_accounts_to_delete()? {
+            deleted += self.delete_account(user_id)?;
+            info!("Account {user_id} deleted");
+        }
+        if deleted > 0 {
+            self.files_manager.delete_released().await?;
+        }
+        Ok(deleted)
+    }
+
+    /**
+    Dependents left without guardian are deleted too. Return how many accounts were deleted.
+    **/
+    fn delete_account(&self, user_id: i64) -> Result<usize, DeletionsManagerError> {
+        let mut deleted = 1;
+        for dependent_id in self.users_manager.get_sole_dependents(user_id)? {
+            deleted += self.delete_account(dependent_id)?;
+        }
+
+        self.wishlist_manager.delete_user(user_id)?;
+        self.friends_manager.delete_user(user_id)?;
+        self.invitations_manager.delete_invitations(user_id)?;
+        self.notifications_manager.delete_notifications(user_id)?;
+        self.events_manager.revoke_calendar_token(user_id)?;
+        if let Some(reminders_manager) = &self.reminders_manager {
+            reminders_manager.forget_user(user_id)?;
+        }
+        self.files_manager.release_owned_files(user_id)?;
+        self.users_manager.delete_user(user_id)?;
+        self.session_manager.sign_out_everywhere(user_id);
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::TestClock;
+    use crate::managers::deletions_manager::DeletionsManager;
+    use crate::managers::events_manager::EventsManager;
+    use crate::managers::files_manager::FilesManager;
+    use crate::managers::friends_manager::{FriendsManager, RequestStatus};
+    use crate::managers::invitations_manager::InvitationsManager;
+    use crate::managers::notifications_manager::NotificationsManager;
+    use crate::managers::session_manager::SessionManager;
+    use crate::managers::test_helper::{create_test_database, create_test_storage};
+    use crate::managers::users_manager::{UsersManager, UsersManagerError};
+    use crate::managers::wishlist_manager::WishlistManager;
+    use crate::storage::{LocalStorage, Storages};
+    use chrono::{Duration, TimeZone, Utc};
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn test_delete_account() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_delete_account")));
+        let storage = create_test_storage("test_delete_account");
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let three = users_manager.add_user("three", "pwd").unwrap();
+        users_manager.add_dependent(one, "kid", None).unwrap();
+        let shared_kid = users_manager
+            .add_dependent(one, "shared_kid", None)
+            .unwrap();
+        users_manager.add_guardian(shared_kid, "two").unwrap();
+
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+        let wishlist_manager = WishlistManager::new(connection.clone()).unwrap();
+        let invitations_manager =
+            InvitationsManager::new(connection.clone(), clock.clone()).unwrap();
+        let notifications_manager =
+            NotificationsManager::new(connection.clone(), clock.clone()).unwrap();
+        let events_manager = EventsManager::new(
+            connection.clone(),
+            friends_manager.clone(),
+            wishlist_manager.clone(),
+            clock.clone(),
+        )
+        .unwrap();
+        let storages = Storages {
+            uploads: Arc::new(LocalStorage::new(storage.clone())),
+            cache: Arc::new(LocalStorage::new(storage.parent().unwrap().join("tmp_rs"))),
+        };
+        let files_manager = FilesManager::new(connection.clone(), storages, clock.clone()).unwrap();
+        let session_manager = SessionManager::new(clock.clone());
+        let deletions_manager = DeletionsManager::new(
+            users_manager.clone(),
+            friends_manager.clone(),
+            wishlist_manager.clone(),
+            invitations_manager.clone(),
+            notifications_manager,
+            events_manager.clone(),
+            files_manager.clone(),
+            session_manager.clone(),
+            None,
+        );
+        let session = session_manager.sign_in(None, one);
+        session_manager.switch(&session, one, shared_kid);
+        let other_session = session_manager.sign_in(None, two);
+
+        friends_manager.create_friend_request(one, "two").unwrap();
+        friends_manager
+            .update_received_request(1, two, RequestStatus::Accepted)
+            .unwrap();
+        friends_manager
+            .add_group(two, "Family", HashSet::from([one]))
+            .unwrap();
+        invitations_manager.create_invitation(one, 7, None).unwrap();
+        events_manager.create_calendar_token(one).unwrap();
+        let picture = files_manager
+            .store(one, b"picture".to_vec(), "png")
+            .await
+            .unwrap();
+        //One's own category, one shared with two and one of two with a gift reserved by one
+        wishlist_manager
+            .add_category("Mine", HashSet::from([one]))
+            .unwrap();
+        wishlist_manager
+            .add_gift("Gift", None, None, None, Some(picture.clone()), None, 1)
+            .unwrap();
+        wishlist_manager
+            .add_category("Shared", HashSet::from([one, two]))
+            .unwrap();
+        wishlist_manager
+            .add_category("Two's", HashSet::from([two]))
+            .unwrap();
+        wishlist_manager
+            .add_gift("Reserved", None, None, None, None, None, 3)
+            .unwrap();
+        wishlist_manager.reserve_gift(2, Some(one)).unwrap();
+
+        users_manager.request_deletion(one).unwrap();
+        users_manager.request_deletion(three).unwrap();
+        users_manager.cancel_deletion(three).unwrap();
+        clock.advance(Duration::days(29));
+        assert_eq!(
+            deletions_manager.delete_expired_accounts().await.unwrap(),
+            0
+        );
+
+        clock.advance(Duration::days(1));
+        assert_eq!(
+            deletions_manager.delete_expired_accounts().await.unwrap(),
+            2
+        );
+        for name in ["one", "kid"] {
+            let error = users_manager.get_user(name).unwrap_err();
+            assert!(matches!(error, UsersManagerError::UnknownUser(_)));
+        }
+        assert!(users_manager.get_user("three").is_ok());
+        assert!(!session_manager.is_signed_in_anywhere(one));
+        assert!(!session_manager.is_signed_in(&session, shared_kid));
+        assert!(session_manager.is_signed_in(&other_session, two));
+        assert_eq!(
+            users_manager
+                .get_guardians(shared_kid)
+                .unwrap()
+                .into_iter()
+                .map(|guardian| guardian.id)
+                .collect::<Vec<_>>(),
+            vec![two]
+        );
+        assert_eq!(
+            friends_manager
+                .get_friends(two)
+                .unwrap()
+                .into_iter()
+                .map(|friend| friend.id)
+                .collect::<Vec<_>>(),
+            vec![shared_kid]
+        );
+        assert!(!wishlist_manager.is_gift_reserved(2).unwrap());
+        assert!(wishlist_manager.is_my_category(two, 2).unwrap());
+        assert!(invitations_manager.get_invitations(one).unwrap().is_empty());
+        assert!(!storage.join(&picture).exists());
+    }
+}
@@ -1,65 +1,188 @@
+use crate::clock::Clock;
 use crate::managers::friends_manager::{FriendsManager, FriendsManagerError};
-use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
-use serde::Serialize;
-use std::time::SystemTime;
+use crate::managers::users_manager::{UsersManager, UsersManagerError};
+use crate::managers::wishlist_manager::{FriendWishList, WishlistManager, WishlistManagerError};
+use chrono::{DateTime, Datelike, Months, NaiveDate, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, ToSql};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
 pub struct EventsManager {
+    connection: Arc<Mutex<Connection>>,
     pub friends_manager: FriendsManager,
+    pub wishlist_manager: WishlistManager,
+    clock: Arc<dyn Clock>,
 }
 
 #[derive(thiserror::Error, Debug)]
 #[error(transparent)]
 pub(crate) enum EventsManagerError {
+    Sqlite(#[from] rusqlite::Error),
+    UsersManager(#[from] UsersManagerError),
     FriendsManager(#[from] FriendsManagerError),
+    WishlistManager(#[from] WishlistManagerError),
+    #[error("Unknown calendar token")]
+    UnknownCalendarToken,
 }
 
 impl EventsManager {
-    pub fn get_events(&self, user_id: i64) -> Result<Vec<Event>, EventsManagerError> {
+    pub fn new(
+        connection: Arc<Mutex<Connection>>,
+        friends_manager: FriendsManager,
+        wishlist_manager: WishlistManager,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, EventsManagerError> {
+        Self::init_database(&connection)?;
+        Ok(Self {
+            connection,
+            friends_manager,
+            wishlist_manager,
+            clock,
+        })
+    }
+
+    fn init_database(connection: &Arc<Mutex<Connection>>) -> Result<(), EventsManagerError> {
+        let connection = connection.lock().unwrap();
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS calendarTokens (userId INTEGER PRIMARY KEY, token TEXT UNIQUE NOT NULL, \
+            FOREIGN KEY(userId) REFERENCES users(id))")?;
+        Ok(())
+    }
+
+    /**
+    Create a new calendar token for the user, the previous one (if any) stops working
+    **/
+    pub fn create_calendar_token(&self, user_id: i64) -> Result<String, EventsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let token = rand::thread_rng()
+            .sample_iter(Alphanumeric)
+            .take(64)
+            .map(char::from)
+            .collect::<String>();
+        connection.execute(
+            "INSERT INTO calendarTokens(userId, token) VALUES (?,?) \
+            ON CONFLICT(userId) DO UPDATE SET token=excluded.token",
+            params![user_id, &token],
+        )?;
+        Ok(token)
+    }
+
+    pub fn revoke_calendar_token(&self, user_id: i64) -> Result<(), EventsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "DELETE FROM calendarTokens WHERE userId=?",
+            params![user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_calendar_user(&self, token: &str) -> Result<i64, EventsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "SELECT userId FROM calendarTokens WHERE token=?",
+                params![token],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?
+            .ok_or(EventsManagerError::UnknownCalendarToken)
+    }
+
+    /**
+    Build an iCalendar document with the birthdays of the friends and Christmas, each of them
+    being a yearly all-day event with a reminder one week before.
+    **/
+    pub fn get_calendar(&self, user_id: i64) -> Result<String, EventsManagerError> {
         let friends = self.friends_manager.get_friends(user_id)?;
+        let now = self.clock.now();
+        let stamp = now.format("%Y%m%dT%H%M%SZ").to_string();
 
-        let now: DateTime<Utc> = SystemTime::now().into();
-        let in_six_month = now.checked_add_months(Months::new(6)).unwrap();
+        let mut calendar = String::new();
+        add_ics_line(&mut calendar, "BEGIN:VCALENDAR");
+        add_ics_line(&mut calendar, "VERSION:2.0");
+        add_ics_line(&mut calendar, "PRODID:-//MyGift//Events//EN");
+        add_ics_line(&mut calendar, "CALSCALE:GREGORIAN");
+        add_ics_line(&mut calendar, "X-WR-CALNAME:MyGift");
+        for friend in friends {
+            let Some(date_of_birth) = friend.date_of_birth else {
+                continue;
+            };
+            let date_of_birth = DateTime::from_timestamp(date_of_birth, 0).unwrap();
+            add_ics_event(
+                &mut calendar,
+                &format!("birthday-{}@mygift", friend.id),
+                &stamp,
+                date_of_birth.date_naive(),
+                &format!("Birthday of {}", friend.name),
+            );
+        }
+        add_ics_event(
+            &mut calendar,
+            "christmas@mygift",
+            &stamp,
+            NaiveDate::from_ymd_opt(1970, 12, 25).unwrap(),
+            "Christmas",
+        );
+        add_ics_line(&mut calendar, "END:VCALENDAR");
+
+        Ok(calendar)
+    }
+
+    pub fn get_events(&self, user_id: i64, months: u32) -> Result<Vec<Event>, EventsManagerError> {
+        let time_zone = {
+            let connection = self.connection.lock().unwrap();
+            UsersManager::static_get_time_zone(&connection, user_id)?
+        };
+        let friends = self.friends_manager.get_friends(user_id)?;
+
+        let today = self.clock.now().with_timezone(&time_zone).date_naive();
+        let horizon = today
+            .checked_add_months(Months::new(months))
+            .unwrap_or(NaiveDate::MAX);
 
         let mut events = Vec::new();
+        let mut christmas_gifts = Vec::new();
         for friend in friends {
+            let wishlist = self
+                .wishlist_manager
+                .get_friend_wishlist(user_id, friend.id)?;
+            christmas_gifts.extend(Self::get_event_gifts(
+                friend.id,
+                &wishlist,
+                EventKind::Christmas,
+            ));
+
             if let Some(date_of_birth) = friend.date_of_birth {
                 let date_of_birth = DateTime::from_timestamp(date_of_birth, 0).unwrap();
-                let mut birthday: DateTime<Utc> = DateTime::from_naive_utc_and_offset(
-                    NaiveDate::from_ymd_opt(now.year(), date_of_birth.month(), date_of_birth.day())
-                        .unwrap()
-                        .into(),
-                    *now.offset(),
-                );
-                if birthday < now {
-                    birthday = birthday.checked_add_months(Months::new(12)).unwrap();
-                }
-                if birthday < in_six_month {
+                let birthday = next_anniversary(date_of_birth.month(), date_of_birth.day(), today);
+                if birthday < horizon {
                     events.push(Event {
                         kind: EventKind::Birthday,
-                        date: birthday.timestamp(),
+                        date: to_timestamp(birthday, &time_zone),
+                        friend_id: Some(friend.id),
                         name: Some(friend.name),
                         picture: friend.picture,
                         birth: friend.date_of_birth,
+                        gifts: Self::get_event_gifts(friend.id, &wishlist, EventKind::Birthday),
                     })
                 }
             }
         }
 
-        let mut christmas: DateTime<Utc> = DateTime::from_naive_utc_and_offset(
-            NaiveDate::from_ymd_opt(now.year(), 12, 25).unwrap().into(),
-            *now.offset(),
-        );
-        if christmas < now {
-            christmas = christmas.checked_add_months(Months::new(12)).unwrap();
-        }
-        if christmas < in_six_month {
+        let christmas = next_anniversary(12, 25, today);
+        if christmas < horizon {
             events.push(Event {
                 kind: EventKind::Christmas,
-                date: christmas.timestamp(),
+                date: to_timestamp(christmas, &time_zone),
+                friend_id: None,
                 name: None,
                 picture: None,
                 birth: None,
+                gifts: christmas_gifts,
             })
         }
 
@@ -67,19 +190,592 @@ impl EventsManager {
 
         Ok(events)
     }
+
+    /**
+    A gift is for an event if it has been attached to it directly, or if its category has been
+    attached to it and the gift itself has not been attached to another event.
+    **/
+    fn get_event_gifts(
+        friend_id: i64,
+        wishlist: &FriendWishList,
+        kind: EventKind,
+    ) -> Vec<EventGift> {
+        wishlist
+            .categories
+            .iter()
+            .flat_map(|category| {
+                category
+                    .gifts
+                    .iter()
+                    .filter(move |gift| gift.event.or(category.event) == Some(kind))
+            })
+            .map(|gift| EventGift {
+                id: gift.id,
+                friend_id,
+                name: gift.name.clone(),
+                free: gift.reserved_by.is_none(),
+            })
+            .collect()
+    }
 }
 
-#[derive(Serialize)]
+/**
+Next occurrence of the day, today included. The 29th of February is celebrated on the 28th on
+non leap years.
+**/
+fn next_anniversary(month: u32, day: u32, today: NaiveDate) -> NaiveDate {
+    let in_year = |year: i32| {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .or_else(|| NaiveDate::from_ymd_opt(year, month, day - 1))
+            .unwrap()
+    };
+    let date = in_year(today.year());
+    if date < today {
+        in_year(today.year() + 1)
+    } else {
+        date
+    }
+}
+
+/**
+Timestamp of the start of the day in the given time zone
+**/
+fn to_timestamp(date: NaiveDate, time_zone: &Tz) -> i64 {
+    let midnight = date.and_time(NaiveTime::MIN);
+    time_zone
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|d| d.timestamp())
+        .unwrap_or_else(|| midnight.and_utc().timestamp())
+}
+
+fn add_ics_event(calendar: &mut String, uid: &str, stamp: &str, date: NaiveDate, summary: &str) {
+    add_ics_line(calendar, "BEGIN:VEVENT");
+    add_ics_line(calendar, &format!("UID:{uid}"));
+    add_ics_line(calendar, &format!("DTSTAMP:{stamp}"));
+    add_ics_line(
+        calendar,
+        &format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")),
+    );
+    if date.month() == 2 && date.day() == 29 {
+        //Otherwise it would only happen on leap years
+        add_ics_line(calendar, "RRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1");
+    } else {
+        add_ics_line(calendar, "RRULE:FREQ=YEARLY");
+    }
+    add_ics_line(calendar, &format!("SUMMARY:{}", escape_ics_text(summary)));
+    add_ics_line(calendar, "TRANSP:TRANSPARENT");
+    add_ics_line(calendar, "BEGIN:VALARM");
+    add_ics_line(calendar, "ACTION:DISPLAY");
+    add_ics_line(
+        calendar,
+        &format!("DESCRIPTION:{}", escape_ics_text(summary)),
+    );
+    add_ics_line(calendar, "TRIGGER:-P7D");
+    add_ics_line(calendar, "END:VALARM");
+    add_ics_line(calendar, "END:VEVENT");
+}
+
+/**
+Line breaks, including bare carriage returns, are escaped so a name cannot add lines to the feed
+**/
+fn escape_ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\n', '\r'], "\\n")
+}
+
+/**
+Lines longer than 75 bytes have to be folded, the continuation starting with a space
+**/
+fn add_ics_line(calendar: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            calendar.push_str("\r\n ");
+            length = 1;
+        }
+        calendar.push(c);
+        length += c.len_utf8();
+    }
+    calendar.push_str("\r\n");
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(test, derive(Debug))]
 pub enum EventKind {
     Birthday,
     Christmas,
 }
 
+impl ToSql for EventKind {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match self {
+            EventKind::Birthday => Ok(ToSqlOutput::from("BIRTHDAY")),
+            EventKind::Christmas => Ok(ToSqlOutput::from("CHRISTMAS")),
+        }
+    }
+}
+
+impl FromSql for EventKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "BIRTHDAY" => Ok(EventKind::Birthday),
+            "CHRISTMAS" => Ok(EventKind::Christmas),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct Event {
-    kind: EventKind,
-    date: i64,
-    name: Option<String>,
+    pub kind: EventKind,
+    pub date: i64,
+    pub friend_id: Option<i64>,
+    pub name: Option<String>,
     picture: Option<String>,
     birth: Option<i64>,
+    pub gifts: Vec<EventGift>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct EventGift {
+    pub id: i64,
+    pub friend_id: i64,
+    pub name: String,
+    pub free: bool,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::TestClock;
+    use crate::managers::events_manager::{
+        escape_ics_text, Event, EventGift, EventKind, EventsManager, EventsManagerError,
+    };
+    use crate::managers::friends_manager::{FriendsManager, RequestStatus};
+    use crate::managers::test_helper::create_test_database;
+    use crate::managers::users_manager::{EditSettings, EditUser, UsersManager};
+    use crate::managers::wishlist_manager::WishlistManager;
+    use chrono::{Duration, TimeZone, Utc};
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_birthday_gifts() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_birthday_gifts")));
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        //1990-06-15
+        users_manager
+            .edit_user(
+                two,
+                &EditUser {
+                    date_of_birth: Some(Some(645408000)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+        friends_manager.create_friend_request(one, "two").unwrap();
+        friends_manager
+            .update_received_request(1, two, RequestStatus::Accepted)
+            .unwrap();
+
+        let wishlist_manager = WishlistManager::new(connection.clone()).unwrap();
+        wishlist_manager
+            .add_category("Birthday", HashSet::from([two]))
+            .unwrap();
+        wishlist_manager
+            .add_category("Other", HashSet::from([two]))
+            .unwrap();
+        wishlist_manager
+            .add_gift("Gift", None, None, None, None, None, 1)
+            .unwrap();
+        wishlist_manager
+            .add_gift("Reserved", None, None, None, None, None, 1)
+            .unwrap();
+        wishlist_manager
+            .add_gift("ForChristmas", None, None, None, None, None, 1)
+            .unwrap();
+        wishlist_manager
+            .add_gift("OtherGift", None, None, None, None, None, 2)
+            .unwrap();
+        wishlist_manager
+            .add_gift("OtherBirthdayGift", None, None, None, None, None, 2)
+            .unwrap();
+        wishlist_manager
+            .set_category_event(two, 1, Some(EventKind::Birthday))
+            .unwrap();
+        wishlist_manager
+            .set_gift_event(two, 3, Some(EventKind::Christmas))
+            .unwrap();
+        wishlist_manager
+            .set_gift_event(two, 5, Some(EventKind::Birthday))
+            .unwrap();
+        wishlist_manager.reserve_gift(2, Some(one)).unwrap();
+
+        let events_manager =
+            EventsManager::new(connection, friends_manager, wishlist_manager, clock.clone())
+                .unwrap();
+        let events = events_manager.get_events(one, 6).unwrap();
+        let birthday = events
+            .iter()
+            .find(|e| e.kind == EventKind::Birthday)
+            .unwrap();
+        assert_eq!(
+            birthday.gifts,
+            vec![
+                EventGift {
+                    id: 1,
+                    friend_id: two,
+                    name: "Gift".to_string(),
+                    free: true
+                },
+                EventGift {
+                    id: 2,
+                    friend_id: two,
+                    name: "Reserved".to_string(),
+                    free: false
+                },
+                EventGift {
+                    id: 5,
+                    friend_id: two,
+                    name: "OtherBirthdayGift".to_string(),
+                    free: true
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_events_window() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_events_window")));
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2025, 2, 1, 12, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let three = users_manager.add_user("three", "pwd").unwrap();
+        //2000-02-29 and 1990-06-15
+        users_manager
+            .edit_user(
+                two,
+                &EditUser {
+                    date_of_birth: Some(Some(951782400)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        users_manager
+            .edit_user(
+                three,
+                &EditUser {
+                    date_of_birth: Some(Some(645408000)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+        friends_manager.create_friend_request(one, "two").unwrap();
+        friends_manager.create_friend_request(one, "three").unwrap();
+        friends_manager
+            .update_received_request(1, two, RequestStatus::Accepted)
+            .unwrap();
+        friends_manager
+            .update_received_request(2, three, RequestStatus::Accepted)
+            .unwrap();
+        let wishlist_manager = WishlistManager::new(connection.clone()).unwrap();
+        let events_manager =
+            EventsManager::new(connection, friends_manager, wishlist_manager, clock.clone())
+                .unwrap();
+
+        let summary = |events: Vec<Event>| {
+            events
+                .into_iter()
+                .map(|e| (e.kind, e.date, e.name))
+                .collect::<Vec<_>>()
+        };
+        let midnight = |year, month, day| {
+            Utc.with_ymd_and_hms(year, month, day, 0, 0, 0)
+                .unwrap()
+                .timestamp()
+        };
+
+        //Not a leap year, the birthday is moved to the 28th
+        let events = events_manager.get_events(one, 1).unwrap();
+        assert_eq!(
+            summary(events),
+            vec![(
+                EventKind::Birthday,
+                midnight(2025, 2, 28),
+                Some("two".to_string())
+            )]
+        );
+        let events = events_manager.get_events(one, 6).unwrap();
+        assert_eq!(
+            summary(events),
+            vec![
+                (
+                    EventKind::Birthday,
+                    midnight(2025, 2, 28),
+                    Some("two".to_string())
+                ),
+                (
+                    EventKind::Birthday,
+                    midnight(2025, 6, 15),
+                    Some("three".to_string())
+                )
+            ]
+        );
+
+        //Leap year and birthday of the day is still coming
+        clock.set(Utc.with_ymd_and_hms(2028, 2, 29, 20, 0, 0).unwrap());
+        let events = events_manager.get_events(one, 1).unwrap();
+        assert_eq!(
+            summary(events),
+            vec![(
+                EventKind::Birthday,
+                midnight(2028, 2, 29),
+                Some("two".to_string())
+            )]
+        );
+
+        //Already the 26th in Tokyo
+        clock.set(Utc.with_ymd_and_hms(2024, 12, 25, 20, 0, 0).unwrap());
+        let events = events_manager.get_events(one, 3).unwrap();
+        assert_eq!(
+            summary(events),
+            vec![
+                (EventKind::Christmas, midnight(2024, 12, 25), None),
+                (
+                    EventKind::Birthday,
+                    midnight(2025, 2, 28),
+                    Some("two".to_string())
+                )
+            ]
+        );
+        users_manager
+            .edit_settings(
+                one,
+                &EditSettings {
+                    time_zone: Some("Asia/Tokyo".to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let events = events_manager.get_events(one, 3).unwrap();
+        assert_eq!(
+            summary(events),
+            vec![(
+                EventKind::Birthday,
+                midnight(2025, 2, 28) - 9 * 60 * 60,
+                Some("two".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_events_new_year() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_events_new_year")));
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 12, 31, 12, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        //1990-01-01
+        users_manager
+            .edit_user(
+                two,
+                &EditUser {
+                    date_of_birth: Some(Some(631152000)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+        friends_manager.create_friend_request(one, "two").unwrap();
+        friends_manager
+            .update_received_request(1, two, RequestStatus::Accepted)
+            .unwrap();
+        let wishlist_manager = WishlistManager::new(connection.clone()).unwrap();
+        let events_manager =
+            EventsManager::new(connection, friends_manager, wishlist_manager, clock.clone())
+                .unwrap();
+
+        let summary = |events: Vec<Event>| {
+            events
+                .into_iter()
+                .map(|e| (e.kind, e.date))
+                .collect::<Vec<_>>()
+        };
+        let midnight = |year, month, day| {
+            Utc.with_ymd_and_hms(year, month, day, 0, 0, 0)
+                .unwrap()
+                .timestamp()
+        };
+
+        //Birthday is next year, Christmas is passed
+        let events = events_manager.get_events(one, 6).unwrap();
+        assert_eq!(
+            summary(events),
+            vec![(EventKind::Birthday, midnight(2025, 1, 1))]
+        );
+
+        //Still the birthday until the end of the day
+        clock.set(Utc.with_ymd_and_hms(2025, 1, 1, 23, 59, 59).unwrap());
+        let events = events_manager.get_events(one, 6).unwrap();
+        assert_eq!(
+            summary(events),
+            vec![(EventKind::Birthday, midnight(2025, 1, 1))]
+        );
+
+        //Then it rolls over to the next year
+        clock.advance(Duration::seconds(1));
+        let events = events_manager.get_events(one, 6).unwrap();
+        assert_eq!(summary(events), vec![]);
+        let events = events_manager.get_events(one, 12).unwrap();
+        assert_eq!(
+            summary(events),
+            vec![
+                (EventKind::Christmas, midnight(2025, 12, 25)),
+                (EventKind::Birthday, midnight(2026, 1, 1))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escape_ics_text() {
+        assert_eq!(
+            escape_ics_text("a;b,c\\d\r\ne\nf\rEND:VCALENDAR"),
+            "a\\;b\\,c\\\\d\\ne\\nf\\nEND:VCALENDAR"
+        );
+    }
+
+    #[test]
+    fn test_calendar() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_calendar")));
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let three = users_manager.add_user("three", "pwd").unwrap();
+        //2000-02-29 and 1990-06-15
+        users_manager
+            .edit_user(
+                two,
+                &EditUser {
+                    date_of_birth: Some(Some(951782400)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        users_manager
+            .edit_user(
+                three,
+                &EditUser {
+                    name: Some("three, the third".to_string()),
+                    date_of_birth: Some(Some(645408000)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+        friends_manager.create_friend_request(one, "two").unwrap();
+        friends_manager
+            .create_friend_request(one, "three, the third")
+            .unwrap();
+        friends_manager
+            .update_received_request(1, two, RequestStatus::Accepted)
+            .unwrap();
+        friends_manager
+            .update_received_request(2, three, RequestStatus::Accepted)
+            .unwrap();
+        let wishlist_manager = WishlistManager::new(connection.clone()).unwrap();
+        let events_manager =
+            EventsManager::new(connection, friends_manager, wishlist_manager, clock.clone())
+                .unwrap();
+
+        let token = events_manager.create_calendar_token(one).unwrap();
+        assert_eq!(events_manager.get_calendar_user(&token).unwrap(), one);
+        let new_token = events_manager.create_calendar_token(one).unwrap();
+        assert!(matches!(
+            events_manager.get_calendar_user(&token).unwrap_err(),
+            EventsManagerError::UnknownCalendarToken
+        ));
+        assert_eq!(events_manager.get_calendar_user(&new_token).unwrap(), one);
+        events_manager.revoke_calendar_token(one).unwrap();
+        assert!(matches!(
+            events_manager.get_calendar_user(&new_token).unwrap_err(),
+            EventsManagerError::UnknownCalendarToken
+        ));
+
+        let calendar = events_manager.get_calendar(one).unwrap();
+        let lines = calendar
+            .split("\r\n")
+            .filter(|l| !l.starts_with("DTSTAMP"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                "BEGIN:VCALENDAR",
+                "VERSION:2.0",
+                "PRODID:-//MyGift//Events//EN",
+                "CALSCALE:GREGORIAN",
+                "X-WR-CALNAME:MyGift",
+                "BEGIN:VEVENT",
+                "UID:birthday-2@mygift",
+                "DTSTART;VALUE=DATE:20000229",
+                "RRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1",
+                "SUMMARY:Birthday of two",
+                "TRANSP:TRANSPARENT",
+                "BEGIN:VALARM",
+                "ACTION:DISPLAY",
+                "DESCRIPTION:Birthday of two",
+                "TRIGGER:-P7D",
+                "END:VALARM",
+                "END:VEVENT",
+                "BEGIN:VEVENT",
+                "UID:birthday-3@mygift",
+                "DTSTART;VALUE=DATE:19900615",
+                "RRULE:FREQ=YEARLY",
+                "SUMMARY:Birthday of three\\, the third",
+                "TRANSP:TRANSPARENT",
+                "BEGIN:VALARM",
+                "ACTION:DISPLAY",
+                "DESCRIPTION:Birthday of three\\, the third",
+                "TRIGGER:-P7D",
+                "END:VALARM",
+                "END:VEVENT",
+                "BEGIN:VEVENT",
+                "UID:christmas@mygift",
+                "DTSTART;VALUE=DATE:19701225",
+                "RRULE:FREQ=YEARLY",
+                "SUMMARY:Christmas",
+                "TRANSP:TRANSPARENT",
+                "BEGIN:VALARM",
+                "ACTION:DISPLAY",
+                "DESCRIPTION:Christmas",
+                "TRIGGER:-P7D",
+                "END:VALARM",
+                "END:VEVENT",
+                "END:VCALENDAR",
+                ""
+            ]
+        );
+    }
 }
@@ -0,0 +1,1566 @@
+use crate::clock::Clock;
+use crate::configuration::UploadsConfiguration;
+use crate::managers::friends_manager::friends_of;
+use crate::managers::wishlist_manager::VISIBLE_CATEGORY;
+use crate::storage::{Storage, StorageError, Storages};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::error::ImageError;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/**
+An upload that has never been used by a user or a gift is only considered an orphan after
+this delay, to let the time to the client to save the gift it was uploaded for.
+**/
+const UNUSED_UPLOAD_GRACE_SECONDS: i64 = 24 * 3600;
+
+/**
+Variants waiting to be created, uploads being rejected from the queue once full
+**/
+const IMAGE_QUEUE_CAPACITY: usize = 1024;
+
+/**
+Uploads are stored under the hash of their content, so the same picture uploaded twice is only
+stored once. The references from `users.picture` and `giftPictures.picture` are counted by
+triggers, and a file is released once its last reference goes.
+**/
+#[derive(Clone)]
+pub struct FilesManager {
+    connection: Arc<Mutex<Connection>>,
+    uploads: Arc<dyn Storage>,
+    cache: Arc<dyn Storage>,
+    /** Storing and deleting must not interleave, or a file uploaded again could be deleted **/
+    storage_lock: Arc<tokio::sync::Mutex<()>>,
+    image_queue: Arc<ImageQueue>,
+    clock: Arc<dyn Clock>,
+}
+
+/**
+Variants are created in the background by a single worker
+**/
+struct ImageQueue {
+    sender: mpsc::Sender<VariantJob>,
+    receiver: tokio::sync::Mutex<mpsc::Receiver<VariantJob>>,
+    /** Variants queued or being created, not to queue them twice **/
+    pending: Mutex<HashSet<String>>,
+    processed: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+struct VariantJob {
+    name: String,
+    size: ImageSize,
+    format: VariantFormat,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub(crate) enum FilesManagerError {
+    Sqlite(#[from] rusqlite::Error),
+    Storage(#[from] StorageError),
+    Image(#[from] ImageError),
+    Task(#[from] tokio::task::JoinError),
+    #[error("Invalid picture: {0}")]
+    InvalidPicture(ImageError),
+    #[error("Picture too large")]
+    PictureTooLarge,
+}
+
+impl FilesManager {
+    pub fn new(
+        connection: Arc<Mutex<Connection>>,
+        storages: Storages,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, FilesManagerError> {
+        Self::init_database(&connection)?;
+        Ok(Self {
+            connection,
+            uploads: storages.uploads,
+            cache: storages.cache,
+            storage_lock: Arc::new(tokio::sync::Mutex::new(())),
+            image_queue: Arc::new(ImageQueue::new()),
+            clock,
+        })
+    }
+
+    /**
+    Users and giftPictures tables have to exist before, for the triggers
+    **/
+    fn init_database(connection: &Arc<Mutex<Connection>>) -> Result<(), FilesManagerError> {
+        let connection = connection.lock().unwrap();
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS files (name TEXT PRIMARY KEY, ownerId INTEGER, size INTEGER NOT NULL, mimeType TEXT NOT NULL, \
+            refCount INTEGER NOT NULL DEFAULT 0, released INTEGER NOT NULL DEFAULT FALSE, createdAt INTEGER NOT NULL, FOREIGN KEY(ownerId) REFERENCES users(id))")?;
+        //Pictures that cannot be decoded, their variants are never tried again
+        connection
+            .execute_batch("CREATE TABLE IF NOT EXISTS failedPictures (name TEXT PRIMARY KEY)")?;
+        //Gift pictures used to be counted from gifts.picture
+        connection.execute_batch(
+            "DROP TRIGGER IF EXISTS giftsPictureInsert; \
+            DROP TRIGGER IF EXISTS giftsPictureUpdate; \
+            DROP TRIGGER IF EXISTS giftsPictureDelete;",
+        )?;
+        //Triggers are created again so their definition is always the latest one
+        for table in ["users", "giftPictures"] {
+            connection.execute_batch(&format!(
+                "DROP TRIGGER IF EXISTS {table}PictureInsert; \
+                CREATE TRIGGER {table}PictureInsert AFTER INSERT ON {table} WHEN NEW.picture IS NOT NULL BEGIN \
+                    UPDATE files SET refCount=refCount+1, released=FALSE WHERE name=NEW.picture; \
+                END; \
+                DROP TRIGGER IF EXISTS {table}PictureUpdate; \
+                CREATE TRIGGER {table}PictureUpdate AFTER UPDATE OF picture ON {table} WHEN OLD.picture IS NOT NEW.picture BEGIN \
+                    UPDATE files SET refCount=refCount-1, released=(refCount<=1) WHERE name=OLD.picture; \
+                    UPDATE files SET refCount=refCount+1, released=FALSE WHERE name=NEW.picture; \
+                END; \
+                DROP TRIGGER IF EXISTS {table}PictureDelete; \
+                CREATE TRIGGER {table}PictureDelete AFTER DELETE ON {table} WHEN OLD.picture IS NOT NULL BEGIN \
+                    UPDATE files SET refCount=refCount-1, released=(refCount<=1) WHERE name=OLD.picture; \
+                END;"
+            ))?;
+        }
+        Ok(())
+    }
+
+    /**
+    Store an uploaded picture, once checked and normalized, and return its name
+    **/
+    pub async fn store_picture(
+        &self,
+        owner_id: i64,
+        content: Vec<u8>,
+        uploads: &UploadsConfiguration,
+    ) -> Result<String, FilesManagerError> {
+        let uploads = uploads.clone();
+        let (content, format) =
+            tokio::task::spawn_blocking(move || prepare_picture(&content, &uploads)).await??;
+        self.store(owner_id, content, format.extension()).await
+    }
+
+    /**
+    Store the content if not already there and return its name
+    **/
+    pub async fn store(
+        &self,
+        owner_id: i64,
+        content: Vec<u8>,
+        extension: &str,
+    ) -> Result<String, FilesManagerError> {
+        let name = format!("{:x}.{extension}", Sha256::digest(&content));
+        let size = content.len();
+
+        let _guard = self.storage_lock.lock().await;
+        let updated = {
+            let connection = self.connection.lock().unwrap();
+            connection.execute(
+                "UPDATE files SET released=FALSE WHERE name=?",
+                params![name],
+            )?
+        };
+        if updated == 0 {
+            self.uploads.put(&name, content).await?;
+            let connection = self.connection.lock().unwrap();
+            connection.execute(
+                "INSERT INTO files(name, ownerId, size, mimeType, createdAt) VALUES (?,?,?,?,?)",
+                params![
+                    name,
+                    owner_id,
+                    size,
+                    mime_type(&name),
+                    self.clock.now().timestamp()
+                ],
+            )?;
+            drop(connection);
+            for (size, format) in default_variants(&name) {
+                self.queue_variant(&name, size, format);
+            }
+        }
+        Ok(name)
+    }
+
+    /**
+    The file in the requested size and format. While the variant is being created, the
+    original picture is returned, marked as pending. Other files are returned as is.
+    **/
+    pub async fn get_variant(
+        &self,
+        name: &str,
+        size: ImageSize,
+        format: Option<VariantFormat>,
+    ) -> Result<Option<Variant>, FilesManagerError> {
+        let created_at = self.get_created_at(name)?;
+        let Some(original_format) = VariantFormat::from_name(name) else {
+            warn!("Format not supported {name} - send the file as is");
+            return Ok(self.uploads.get(name).await?.map(|content| Variant {
+                name: name.to_string(),
+                content,
+                mime_type: mime_type(name),
+                created_at,
+                pending: false,
+            }));
+        };
+        if self.is_failed(name)? {
+            return Ok(self.uploads.get(name).await?.map(|content| Variant {
+                name: name.to_string(),
+                content,
+                mime_type: original_format.mime_type(),
+                created_at,
+                pending: false,
+            }));
+        }
+        let format = format.unwrap_or(original_format.fallback());
+        let variant_name = variant_name(name, size, format);
+        if let Some(content) = self.cache.get(&variant_name).await? {
+            return Ok(Some(Variant {
+                name: variant_name,
+                content,
+                mime_type: format.mime_type(),
+                created_at,
+                pending: false,
+            }));
+        }
+
+        let Some(original) = self.uploads.get(name).await? else {
+            return Ok(None);
+        };
+        self.queue_variant(name, size, format);
+        Ok(Some(Variant {
+            name: name.to_string(),
+            content: original,
+            mime_type: original_format.mime_type(),
+            created_at,
+            pending: true,
+        }))
+    }
+
+    /**
+    Same as `get_variant` but creating the variant right away when missing, for the pictures
+    that are needed immediately
+    **/
+    pub async fn create_variant(
+        &self,
+        name: &str,
+        size: ImageSize,
+    ) -> Result<Option<Vec<u8>>, FilesManagerError> {
+        let Some(format) = VariantFormat::from_name(name).map(|f| f.fallback()) else {
+            return Ok(None);
+        };
+        let variant_name = variant_name(name, size, format);
+        if let Some(content) = self.cache.get(&variant_name).await? {
+            return Ok(Some(content));
+        }
+        if self.is_failed(name)? {
+            return Ok(None);
+        }
+        let Some(original) = self.uploads.get(name).await? else {
+            return Ok(None);
+        };
+        let Some(content) = resize_in_background(original, size, format).await else {
+            self.mark_failed(name)?;
+            return Ok(None);
+        };
+        self.put_variant(name, &variant_name, content.clone())
+            .await?;
+        Ok(Some(content))
+    }
+
+    fn is_failed(&self, name: &str) -> Result<bool, FilesManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare_cached("SELECT 1 FROM failedPictures WHERE name=?")?;
+        Ok(statement.exists(params![name])?)
+    }
+
+    fn mark_failed(&self, name: &str) -> Result<(), FilesManagerError> {
+        warn!("Picture {name} cannot be decoded, its variants will not be created");
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT OR IGNORE INTO failedPictures (name) VALUES (?)",
+            params![name],
+        )?;
+        Ok(())
+    }
+
+    /**
+    The variant is only written while its picture is still stored, `delete_released` holding the
+    same lock while removing pictures and their variants
+    **/
+    async fn put_variant(
+        &self,
+        name: &str,
+        variant_name: &str,
+        content: Vec<u8>,
+    ) -> Result<bool, FilesManagerError> {
+        let _guard = self.storage_lock.lock().await;
+        if !self.uploads.exists(name).await? {
+            return Ok(false);
+        }
+        self.cache.put(variant_name, content).await?;
+        Ok(true)
+    }
+
+    /**
+    A file can be seen by its owner, and by the users allowed to see a user or a gift with it
+    as picture: the user, their friends and, unless one blocked the other, anyone when the user
+    is discoverable or the users with a pending friend request to or from them; or the users
+    sharing the gift category, unless it is secret, and their friends allowed to see it.
+    **/
+    pub fn can_access(&self, user_id: i64, name: &str) -> Result<bool, FilesManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let friends = friends_of("?2");
+        let mut statement = connection.prepare(&format!(
+            "SELECT 1 FROM files WHERE name=?1 AND ownerId=?2 \
+            UNION ALL \
+            SELECT 1 FROM users WHERE picture=?1 AND (id=?2 \
+                OR id IN ({friends}) \
+                OR ((discoverable=TRUE \
+                    OR id IN (SELECT userTwo FROM friendRequests WHERE userOne=?2 AND status IN ('PENDING', 'ACCEPTED') \
+                    UNION SELECT userOne FROM friendRequests WHERE userTwo=?2 AND status IN ('PENDING', 'ACCEPTED'))) \
+                AND NOT EXISTS (SELECT 1 FROM blockedUsers b WHERE (b.userId=?2 AND b.blockedId=users.id) \
+                    OR (b.userId=users.id AND b.blockedId=?2)))) \
+            UNION ALL \
+            SELECT 1 FROM giftPictures p JOIN gifts g ON g.id=p.giftId \
+            JOIN joinUserAndCategory j ON j.categoryId=g.categoryId \
+            WHERE p.picture=?1 AND ((j.userId=?2 AND g.secret=FALSE) \
+                OR (j.userId IN ({friends}) \
+                AND g.categoryId NOT IN (SELECT categoryId FROM joinUserAndCategory WHERE userId=?2) \
+                AND {VISIBLE_CATEGORY}))"
+        ))?;
+        Ok(statement.exists(params![name, user_id])?)
+    }
+
+    fn get_created_at(&self, name: &str) -> Result<Option<i64>, FilesManagerError> {
+        let connection = self.connection.lock().unwrap();
+        Ok(connection
+            .query_row(
+                "SELECT createdAt FROM files WHERE name=?",
+                params![name],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?)
+    }
+
+    /**
+    Queue the variant unless already queued, dropping it if the queue is full as it will be
+    queued again on the next request
+    **/
+    fn queue_variant(&self, name: &str, size: ImageSize, format: VariantFormat) {
+        let variant_name = variant_name(name, size, format);
+        if !self
+            .image_queue
+            .pending
+            .lock()
+            .unwrap()
+            .insert(variant_name.clone())
+        {
+            return;
+        }
+        let job = VariantJob {
+            name: name.to_string(),
+            size,
+            format,
+        };
+        if self.image_queue.sender.try_send(job).is_err() {
+            warn!("Image queue is full, {variant_name} not queued");
+            self.image_queue
+                .pending
+                .lock()
+                .unwrap()
+                .remove(&variant_name);
+        }
+    }
+
+    /**
+    Queue the default variants missing from the cache, waiting for room in the queue
+    **/
+    pub async fn queue_missing_variants(&self) -> Result<usize, FilesManagerError> {
+        let cached = self.cache.list().await?.into_iter().collect::<HashSet<_>>();
+        let failed = {
+            let connection = self.connection.lock().unwrap();
+            let mut statement = connection.prepare("SELECT name FROM failedPictures")?;
+            let failed = statement
+                .query_map(params![], |row| row.get::<_, String>(0))?
+                .collect::<Result<HashSet<_>, _>>()?;
+            failed
+        };
+        let mut queued = 0;
+        for name in self.uploads.list().await? {
+            if failed.contains(&name) {
+                continue;
+            }
+            for (size, format) in default_variants(&name) {
+                let variant_name = variant_name(&name, size, format);
+                if cached.contains(&variant_name)
+                    || !self
+                        .image_queue
+                        .pending
+                        .lock()
+                        .unwrap()
+                        .insert(variant_name.clone())
+                {
+                    continue;
+                }
+                let job = VariantJob {
+                    name: name.clone(),
+                    size,
+                    format,
+                };
+                if self.image_queue.sender.send(job).await.is_err() {
+                    self.image_queue
+                        .pending
+                        .lock()
+                        .unwrap()
+                        .remove(&variant_name);
+                    continue;
+                }
+                queued += 1;
+            }
+        }
+        info!("{queued} image variants queued");
+        Ok(queued)
+    }
+
+    /**
+    Create the queued variants, forever
+    **/
+    pub async fn run_image_worker(self) {
+        let mut receiver = self.image_queue.receiver.lock().await;
+        while let Some(job) = receiver.recv().await {
+            self.process_variant(job).await;
+            let processed = self.image_queue.processed.load(Ordering::Relaxed);
+            if receiver.is_empty() || processed.is_multiple_of(100) {
+                info!(
+                    "Image variants: {processed} created, {} failed, {} waiting",
+                    self.image_queue.failed.load(Ordering::Relaxed),
+                    receiver.len()
+                );
+            }
+        }
+    }
+
+    /**
+    Create the queued variants until the queue is empty
+    **/
+    #[cfg(test)]
+    pub async fn process_queued_variants(&self) -> usize {
+        let mut receiver = self.image_queue.receiver.lock().await;
+        let mut count = 0;
+        while let Ok(job) = receiver.try_recv() {
+            self.process_variant(job).await;
+            count += 1;
+        }
+        count
+    }
+
+    async fn process_variant(&self, job: VariantJob) {
+        let variant_name = variant_name(&job.name, job.size, job.format);
+        match self.create_queued_variant(&job, &variant_name).await {
+            Ok(true) => {
+                self.image_queue.processed.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(false) => {
+                self.image_queue.failed.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                warn!("Could not create {variant_name}: {e}");
+                self.image_queue.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.image_queue
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&variant_name);
+    }
+
+    async fn create_queued_variant(
+        &self,
+        job: &VariantJob,
+        variant_name: &str,
+    ) -> Result<bool, FilesManagerError> {
+        if self.cache.get(variant_name).await?.is_some() {
+            return Ok(true);
+        }
+        let Some(original) = self.uploads.get(&job.name).await? else {
+            return Ok(false);
+        };
+        let Some(content) = resize_in_background(original, job.size, job.format).await else {
+            self.mark_failed(&job.name)?;
+            return Ok(false);
+        };
+        self.put_variant(&job.name, variant_name, content).await
+    }
+
+    pub fn get_owned_files(&self, user_id: i64) -> Result<Vec<OwnedFile>, FilesManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT name, size, mimeType, createdAt FROM files WHERE ownerId=? AND released=FALSE ORDER BY createdAt, name",
+        )?;
+        let rows = statement.query_map(params![user_id], |row| {
+            Ok(OwnedFile {
+                name: row.get(0)?,
+                size: row.get(1)?,
+                mime_type: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /**
+    Files of a deleted user are kept while still used, for instance in a shared category
+    **/
+    pub fn release_owned_files(&self, user_id: i64) -> Result<(), FilesManagerError> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "UPDATE files SET ownerId=NULL, released=(released OR refCount<=0) WHERE ownerId=?",
+            params![user_id],
+        )?;
+        Ok(())
+    }
+
+    /**
+    Delete the files that lost their last reference. Each file is checked again right before
+    being removed, under the lock `store` takes, so a file uploaded again or referenced again in
+    the meantime is kept.
+    **/
+    pub async fn delete_released(&self) -> Result<usize, FilesManagerError> {
+        let _guard = self.storage_lock.lock().await;
+        let names = {
+            let connection = self.connection.lock().unwrap();
+            let mut statement =
+                connection.prepare("SELECT name FROM files WHERE released=TRUE AND refCount<=0")?;
+            let names = statement
+                .query_map(params![], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            names
+        };
+        let mut deleted = 0;
+        for name in &names {
+            let still_released = {
+                let connection = self.connection.lock().unwrap();
+                connection.execute(
+                    "DELETE FROM files WHERE name=? AND released=TRUE AND refCount<=0",
+                    params![name],
+                )? == 1
+            };
+            if still_released {
+                self.remove_file(name).await?;
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+
+    /**
+    Compare the storage, the files table and the pictures really used. When repairing, referenced
+    files stored before the files table are registered, reference counts are fixed and orphans
+    are deleted. Missing files cannot be repaired.
+    **/
+    pub async fn check(&self, repair: bool) -> Result<FilesReport, FilesManagerError> {
+        let _guard = self.storage_lock.lock().await;
+        let (referenced, registered) = {
+            let connection = self.connection.lock().unwrap();
+            let mut statement = connection.prepare(
+                "SELECT picture, COUNT(*) FROM (SELECT picture FROM users WHERE picture IS NOT NULL \
+                UNION ALL SELECT picture FROM giftPictures) GROUP BY picture",
+            )?;
+            let referenced = statement
+                .query_map(params![], |row| <(String, i64)>::try_from(row))?
+                .collect::<Result<HashMap<_, _>, _>>()?;
+            let mut statement =
+                connection.prepare("SELECT name, refCount, createdAt FROM files")?;
+            let registered = statement
+                .query_map(params![], |row| {
+                    Ok((row.get::<_, String>(0)?, (row.get(1)?, row.get(2)?)))
+                })?
+                .collect::<Result<HashMap<String, (i64, i64)>, _>>()?;
+            (referenced, registered)
+        };
+        let stored = self
+            .uploads
+            .list()
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let now = self.clock.now().timestamp();
+        let mut report = FilesReport::default();
+        for (name, count) in &referenced {
+            if !stored.contains(name) {
+                report.missing.push(name.clone());
+            } else if !registered.contains_key(name) {
+                report.unregistered.push(name.clone());
+                if repair {
+                    let size = self.uploads.get(name).await?.unwrap_or_default().len();
+                    let connection = self.connection.lock().unwrap();
+                    connection.execute(
+                        "INSERT INTO files(name, size, mimeType, refCount, createdAt) VALUES (?,?,?,?,?)",
+                        params![name, size, mime_type(name), count, now],
+                    )?;
+                }
+            }
+        }
+        for (name, (ref_count, created_at)) in &registered {
+            let count = referenced.get(name).copied().unwrap_or(0);
+            if count == 0
+                && (!stored.contains(name) || created_at + UNUSED_UPLOAD_GRACE_SECONDS < now)
+            {
+                report.orphans.push(name.clone());
+                if repair {
+                    self.remove_file(name).await?;
+                    let connection = self.connection.lock().unwrap();
+                    connection.execute("DELETE FROM files WHERE name=?", params![name])?;
+                }
+            } else if *ref_count != count {
+                report.wrong_counts.push((name.clone(), *ref_count, count));
+                if repair {
+                    let connection = self.connection.lock().unwrap();
+                    connection.execute(
+                        "UPDATE files SET refCount=?, released=FALSE WHERE name=?",
+                        params![count, name],
+                    )?;
+                }
+            }
+        }
+        for name in &stored {
+            if !referenced.contains_key(name) && !registered.contains_key(name) {
+                report.orphans.push(name.clone());
+                if repair {
+                    self.remove_file(name).await?;
+                }
+            }
+        }
+
+        report.missing.sort();
+        report.unregistered.sort();
+        report.wrong_counts.sort();
+        report.orphans.sort();
+        Ok(report)
+    }
+
+    /**
+    Remove the file and its resized versions
+    **/
+    async fn remove_file(&self, name: &str) -> Result<(), FilesManagerError> {
+        debug!("Deleting file {name}");
+        {
+            let connection = self.connection.lock().unwrap();
+            connection.execute("DELETE FROM failedPictures WHERE name=?", params![name])?;
+        }
+        self.uploads.delete(name).await?;
+        for size in ImageSize::ALL {
+            for format in VariantFormat::ALL {
+                self.cache.delete(&variant_name(name, size, format)).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum ImageSize {
+    Thumb,
+    #[default]
+    Card,
+    Print,
+}
+
+impl ImageSize {
+    const ALL: [ImageSize; 3] = [ImageSize::Thumb, ImageSize::Card, ImageSize::Print];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ImageSize::Thumb => "thumb",
+            ImageSize::Card => "card",
+            ImageSize::Print => "print",
+        }
+    }
+
+    /**
+    Largest side, pictures being never enlarged
+    **/
+    fn pixels(&self) -> u32 {
+        match self {
+            ImageSize::Thumb => 150,
+            ImageSize::Card => 300,
+            ImageSize::Print => 880,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(test, derive(Debug))]
+pub enum VariantFormat {
+    Avif,
+    Webp,
+    Png,
+    Jpeg,
+}
+
+impl VariantFormat {
+    const ALL: [VariantFormat; 4] = [
+        VariantFormat::Avif,
+        VariantFormat::Webp,
+        VariantFormat::Png,
+        VariantFormat::Jpeg,
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        let extension = Path::new(name)
+            .extension()?
+            .to_string_lossy()
+            .to_lowercase();
+        match extension.as_str() {
+            "avif" => Some(VariantFormat::Avif),
+            "webp" => Some(VariantFormat::Webp),
+            "png" => Some(VariantFormat::Png),
+            "jpg" | "jpeg" => Some(VariantFormat::Jpeg),
+            _ => None,
+        }
+    }
+
+    /**
+    The most efficient format accepted by the client, if any of them is. Quality values are
+    only used to exclude a format.
+    **/
+    pub fn from_accept(accept: Option<&str>) -> Option<Self> {
+        let accepted = accept
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|media_range| {
+                let mut parameters = media_range.split(';').map(str::trim);
+                let media_type = parameters.next()?;
+                let excluded = parameters.any(|p| {
+                    p.strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .is_some_and(|q| q <= 0.0)
+                });
+                (!excluded).then_some(media_type)
+            })
+            .collect::<Vec<_>>();
+        [VariantFormat::Avif, VariantFormat::Webp]
+            .into_iter()
+            .find(|format| accepted.contains(&format.mime_type()))
+    }
+
+    /**
+    Format understood by every client, keeping photos in JPEG
+    **/
+    fn fallback(&self) -> Self {
+        match self {
+            VariantFormat::Jpeg => VariantFormat::Jpeg,
+            _ => VariantFormat::Png,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            VariantFormat::Avif => "avif",
+            VariantFormat::Webp => "webp",
+            VariantFormat::Png => "png",
+            VariantFormat::Jpeg => "jpg",
+        }
+    }
+
+    fn mime_type(&self) -> &'static str {
+        match self {
+            VariantFormat::Avif => "image/avif",
+            VariantFormat::Webp => "image/webp",
+            VariantFormat::Png => "image/png",
+            VariantFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    fn image_format(&self) -> ImageFormat {
+        match self {
+            VariantFormat::Avif => ImageFormat::Avif,
+            VariantFormat::Webp => ImageFormat::WebP,
+            VariantFormat::Png => ImageFormat::Png,
+            VariantFormat::Jpeg => ImageFormat::Jpeg,
+        }
+    }
+}
+
+pub struct Variant {
+    /** Name in the cache, unique for a given content **/
+    pub name: String,
+    pub content: Vec<u8>,
+    pub mime_type: &'static str,
+    /** Unknown for files uploaded before the files table **/
+    pub created_at: Option<i64>,
+    /** The original is returned while the variant is being created **/
+    pub pending: bool,
+}
+
+impl ImageQueue {
+    fn new() -> Self {
+        let (sender, receiver) = mpsc::channel(IMAGE_QUEUE_CAPACITY);
+        Self {
+            sender,
+            receiver: tokio::sync::Mutex::new(receiver),
+            pending: Mutex::new(HashSet::new()),
+            processed: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+        }
+    }
+}
+
+fn variant_name(name: &str, size: ImageSize, format: VariantFormat) -> String {
+    let stem = Path::new(name)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    format!("{stem}-{}.{}", size.name(), format.extension())
+}
+
+/**
+Variants created ahead of the requests for the pictures
+**/
+fn default_variants(name: &str) -> Vec<(ImageSize, VariantFormat)> {
+    let Some(original_format) = VariantFormat::from_name(name) else {
+        return Vec::new();
+    };
+    ImageSize::ALL
+        .into_iter()
+        .flat_map(|size| {
+            [
+                original_format.fallback(),
+                VariantFormat::Webp,
+                VariantFormat::Avif,
+            ]
+            .map(|format| (size, format))
+        })
+        .collect()
+}
+
+/**
+Resizing is too slow to be done on the async runtime threads
+**/
+async fn resize_in_background(
+    content: Vec<u8>,
+    size: ImageSize,
+    format: VariantFormat,
+) -> Option<Vec<u8>> {
+    tokio::task::spawn_blocking(move || resize(&content, size.pixels(), format))
+        .await
+        .ok()
+        .flatten()
+}
+
+fn resize(content: &[u8], pixels: u32, format: VariantFormat) -> Option<Vec<u8>> {
+    let image = match ImageReader::new(Cursor::new(content)).with_guessed_format() {
+        Ok(image_reader) => image_reader.decode(),
+        Err(error) => {
+            debug!("Could not read picture: {error}");
+            return None;
+        }
+    };
+    let image = match image {
+        Ok(image) => image,
+        Err(error) => {
+            debug!("Could not decode picture: {error}");
+            return None;
+        }
+    };
+    let image = if image.width() > pixels || image.height() > pixels {
+        image.resize(pixels, pixels, FilterType::Triangle)
+    } else {
+        image
+    };
+    //JPEG has no alpha channel
+    let image = if format == VariantFormat::Jpeg {
+        image.into_rgb8().into()
+    } else {
+        image
+    };
+    let mut resized = Vec::new();
+    let result = if format == VariantFormat::Avif {
+        //The default speed is far too slow to encode every upload
+        image.write_with_encoder(AvifEncoder::new_with_speed_quality(&mut resized, 8, 75))
+    } else {
+        image.write_to(&mut Cursor::new(&mut resized), format.image_format())
+    };
+    if let Err(error) = result {
+        debug!("Could not encode picture: {error}");
+        return None;
+    }
+    Some(resized)
+}
+
+/**
+Decode an uploaded picture within the configured limits, so a small file cannot expand into a
+huge image, and turn it upright. The picture is encoded again without any of the EXIF metadata
+of the upload (location, device...), photos staying in JPEG and the other formats becoming PNG.
+**/
+fn prepare_picture(
+    content: &[u8],
+    uploads: &UploadsConfiguration,
+) -> Result<(Vec<u8>, VariantFormat), FilesManagerError> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(uploads.max_width);
+    limits.max_image_height = Some(uploads.max_height);
+    let decode = || -> Result<(DynamicImage, VariantFormat), ImageError> {
+        let mut image_reader = ImageReader::new(Cursor::new(content)).with_guessed_format()?;
+        let format = match image_reader.format() {
+            Some(ImageFormat::Jpeg) => VariantFormat::Jpeg,
+            _ => VariantFormat::Png,
+        };
+        image_reader.limits(limits);
+        let mut decoder = image_reader.into_decoder()?;
+        let orientation = decoder.orientation()?;
+        let mut image = DynamicImage::from_decoder(decoder)?;
+        image.apply_orientation(orientation);
+        Ok((image, format))
+    };
+    let (image, format) = decode().map_err(|error| match error {
+        ImageError::Limits(_) => FilesManagerError::PictureTooLarge,
+        error => FilesManagerError::InvalidPicture(error),
+    })?;
+
+    let side = uploads.max_stored_side;
+    let image = if image.width() > side || image.height() > side {
+        image.resize(side, side, FilterType::Triangle)
+    } else {
+        image
+    };
+    let mut prepared = Vec::new();
+    if format == VariantFormat::Jpeg {
+        //A high quality, as the variants are encoded again from this picture
+        image
+            .into_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut prepared, 90))?;
+    } else {
+        image.write_to(&mut Cursor::new(&mut prepared), ImageFormat::Png)?;
+    }
+    Ok((prepared, format))
+}
+
+/**
+Names are only made of a stem and an extension, so they cannot leave the storage directory
+**/
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    let Some((stem, extension)) = name.split_once('.') else {
+        return false;
+    };
+    name.len() <= 128
+        && !stem.is_empty()
+        && !extension.is_empty()
+        && stem
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && extension.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+pub(crate) fn mime_type(name: &str) -> &'static str {
+    let extension = Path::new(name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "gif" => "image/gif",
+        _ => "application/octet-stream",
+    }
+}
+
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct OwnedFile {
+    name: String,
+    size: i64,
+    mime_type: String,
+    created_at: i64,
+}
+
+#[derive(Default)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct FilesReport {
+    /** Referenced but not in the storage **/
+    pub missing: Vec<String>,
+    /** Referenced and stored but not in the files table **/
+    pub unregistered: Vec<String>,
+    /** Name, counted and real number of references **/
+    pub wrong_counts: Vec<(String, i64, i64)>,
+    /** Not referenced anymore **/
+    pub orphans: Vec<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::TestClock;
+    use crate::configuration::UploadsConfiguration;
+    use crate::managers::files_manager::{
+        is_valid_name, prepare_picture, FilesManager, FilesManagerError, FilesReport, ImageSize,
+        VariantFormat,
+    };
+    use crate::managers::friends_manager::{FriendsManager, RequestStatus};
+    use crate::managers::test_helper::{create_test_database, create_test_storage};
+    use crate::managers::users_manager::{EditSettings, EditUser, UsersManager};
+    use crate::managers::wishlist_manager::WishlistManager;
+    use crate::storage::{LocalStorage, Storages};
+    use chrono::{Duration, TimeZone, Utc};
+    use image::codecs::jpeg::{JpegDecoder, JpegEncoder};
+    use image::codecs::png::PngDecoder;
+    use image::codecs::webp::WebPEncoder;
+    use image::{GenericImageView, ImageDecoder, ImageEncoder, ImageFormat, RgbImage, RgbaImage};
+    use rusqlite::{params, Connection};
+    use std::collections::HashSet;
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    fn create_files_manager(
+        connection: Arc<Mutex<Connection>>,
+        storage: &Path,
+        clock: Arc<TestClock>,
+    ) -> FilesManager {
+        let storages = Storages {
+            uploads: Arc::new(LocalStorage::new(storage.to_path_buf())),
+            cache: Arc::new(LocalStorage::new(storage.parent().unwrap().join("tmp_rs"))),
+        };
+        FilesManager::new(connection, storages, clock).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reference_counting() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_reference_counting")));
+        let storage = create_test_storage("test_reference_counting");
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let wishlist_manager = WishlistManager::new(connection.clone()).unwrap();
+        let files_manager = create_files_manager(connection.clone(), &storage, clock);
+
+        let name = files_manager
+            .store(one, b"picture".to_vec(), "png")
+            .await
+            .unwrap();
+        assert_eq!(
+            name,
+            "2cea274d0bedc39ec4ab6ba9e59ec889e3ed6fb56a1cf088a64d9b383378dc97.png"
+        );
+        assert_eq!(
+            files_manager
+                .store(one, b"picture".to_vec(), "png")
+                .await
+                .unwrap(),
+            name
+        );
+        assert_eq!(fs::read(storage.join(&name)).unwrap(), b"picture");
+        fs::write(
+            storage
+                .parent()
+                .unwrap()
+                .join("tmp_rs")
+                .join(name.replace(".png", "-card.webp")),
+            b"resized",
+        )
+        .unwrap();
+        let ref_count = || {
+            connection
+                .lock()
+                .unwrap()
+                .query_row(
+                    "SELECT refCount FROM files WHERE name=?",
+                    params![name],
+                    |row| row.get::<_, i64>(0),
+                )
+                .unwrap()
+        };
+
+        wishlist_manager
+            .add_category("Category", HashSet::from([one]))
+            .unwrap();
+        wishlist_manager
+            .add_gift("Gift", None, None, None, Some(name.clone()), None, 1)
+            .unwrap();
+        users_manager
+            .edit_user(
+                one,
+                &EditUser {
+                    picture: Some(Some(name.clone())),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(ref_count(), 2);
+
+        wishlist_manager
+            .edit_gift(1, "Gift", None, None, None, None, 1)
+            .unwrap();
+        assert_eq!(ref_count(), 1);
+        assert_eq!(files_manager.delete_released().await.unwrap(), 0);
+
+        users_manager
+            .edit_user(
+                one,
+                &EditUser {
+                    picture: Some(None),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(ref_count(), 0);
+        //Uploaded again before being deleted, the file is kept
+        files_manager
+            .store(one, b"picture".to_vec(), "png")
+            .await
+            .unwrap();
+        assert_eq!(files_manager.delete_released().await.unwrap(), 0);
+        assert!(storage.join(&name).exists());
+
+        users_manager
+            .edit_user(
+                one,
+                &EditUser {
+                    picture: Some(Some(name.clone())),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        users_manager
+            .edit_user(
+                one,
+                &EditUser {
+                    picture: Some(None),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(files_manager.delete_released().await.unwrap(), 1);
+        assert!(!storage.join(&name).exists());
+        assert!(!storage
+            .parent()
+            .unwrap()
+            .join("tmp_rs")
+            .join(name.replace(".png", "-card.webp"))
+            .exists());
+    }
+
+    #[tokio::test]
+    async fn test_can_access() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_can_access")));
+        let storage = create_test_storage("test_can_access");
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let three = users_manager.add_user("three", "pwd").unwrap();
+        let four = users_manager.add_user("four", "pwd").unwrap();
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+        friends_manager.create_friend_request(one, "two").unwrap();
+        friends_manager
+            .update_received_request(1, two, RequestStatus::Accepted)
+            .unwrap();
+        friends_manager.create_friend_request(three, "one").unwrap();
+        let wishlist_manager = WishlistManager::new(connection.clone()).unwrap();
+        let files_manager = create_files_manager(connection.clone(), &storage, clock);
+
+        let avatar = files_manager
+            .store(one, b"avatar".to_vec(), "png")
+            .await
+            .unwrap();
+        let gift = files_manager
+            .store(one, b"gift".to_vec(), "png")
+            .await
+            .unwrap();
+        let draft = files_manager
+            .store(one, b"draft".to_vec(), "png")
+            .await
+            .unwrap();
+        let secret = files_manager
+            .store(two, b"secret".to_vec(), "png")
+            .await
+            .unwrap();
+        users_manager
+            .edit_user(
+                one,
+                &EditUser {
+                    picture: Some(Some(avatar.clone())),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        wishlist_manager
+            .add_category("Category", HashSet::from([one]))
+            .unwrap();
+        wishlist_manager
+            .add_gift("Gift", None, None, None, Some(gift.clone()), None, 1)
+            .unwrap();
+        wishlist_manager
+            .add_gift(
+                "Secret",
+                None,
+                None,
+                None,
+                Some(secret.clone()),
+                Some(two),
+                1,
+            )
+            .unwrap();
+
+        let access = |user_id| {
+            [&avatar, &gift, &draft, &secret]
+                .map(|name| files_manager.can_access(user_id, name).unwrap())
+        };
+        assert_eq!(access(one), [true, true, true, false]);
+        assert_eq!(access(two), [true, true, false, true]);
+        assert_eq!(access(three), [true, false, false, false]);
+        assert_eq!(access(four), [false, false, false, false]);
+
+        //Declined and blocked requests do not give access to the avatar
+        friends_manager.create_friend_request(four, "one").unwrap();
+        assert_eq!(access(four), [true, false, false, false]);
+        friends_manager
+            .update_received_request(3, one, RequestStatus::Declined)
+            .unwrap();
+        assert_eq!(access(four), [false, false, false, false]);
+        friends_manager.block_user(one, "three").unwrap();
+        assert_eq!(access(three), [false, false, false, false]);
+
+        //Anyone can see the avatar of a discoverable user, as found by searching
+        let discoverable = EditSettings {
+            discoverable: Some(true),
+            ..Default::default()
+        };
+        users_manager.edit_settings(one, &discoverable).unwrap();
+        assert_eq!(access(four), [true, false, false, false]);
+        assert_eq!(access(three), [false, false, false, false]);
+
+        //As are the avatars of the suggested friends of friends
+        let five = users_manager.add_user("five", "pwd").unwrap();
+        let five_avatar = files_manager
+            .store(five, b"five".to_vec(), "png")
+            .await
+            .unwrap();
+        users_manager
+            .edit_user(
+                five,
+                &EditUser {
+                    picture: Some(Some(five_avatar.clone())),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        users_manager.edit_settings(five, &discoverable).unwrap();
+        friends_manager.create_friend_request(two, "five").unwrap();
+        friends_manager
+            .update_received_request(4, five, RequestStatus::Accepted)
+            .unwrap();
+        assert_eq!(friends_manager.get_suggestions(one, 10).unwrap().len(), 1);
+        assert!(files_manager.can_access(one, &five_avatar).unwrap());
+    }
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(is_valid_name("2cea274d.png"));
+        assert!(is_valid_name("2cea274d-card.webp"));
+        assert!(!is_valid_name("../configuration.json"));
+        assert!(!is_valid_name("..png"));
+        assert!(!is_valid_name("folder/picture.png"));
+        assert!(!is_valid_name("picture.png/.."));
+        assert!(!is_valid_name("picture"));
+        assert!(!is_valid_name(".png"));
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_check")));
+        let storage = create_test_storage("test_check");
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let wishlist_manager = WishlistManager::new(connection.clone()).unwrap();
+        let files_manager = create_files_manager(connection.clone(), &storage, clock.clone());
+
+        fs::write(storage.join("upload-1.png"), b"legacy").unwrap();
+        fs::write(storage.join("upload-2.png"), b"orphan").unwrap();
+        let unused = files_manager
+            .store(one, b"unused".to_vec(), "png")
+            .await
+            .unwrap();
+        wishlist_manager
+            .add_category("Category", HashSet::from([one]))
+            .unwrap();
+        wishlist_manager
+            .add_gift(
+                "Legacy",
+                None,
+                None,
+                None,
+                Some("upload-1.png".to_string()),
+                None,
+                1,
+            )
+            .unwrap();
+        wishlist_manager
+            .add_gift(
+                "Missing",
+                None,
+                None,
+                None,
+                Some("missing.png".to_string()),
+                None,
+                1,
+            )
+            .unwrap();
+        users_manager
+            .edit_user(
+                one,
+                &EditUser {
+                    picture: Some(Some("upload-1.png".to_string())),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let used = files_manager
+            .store(one, b"used".to_vec(), "png")
+            .await
+            .unwrap();
+        wishlist_manager
+            .add_gift("Used", None, None, None, Some(used.clone()), None, 1)
+            .unwrap();
+        connection
+            .lock()
+            .unwrap()
+            .execute("UPDATE files SET refCount=5 WHERE name=?", params![used])
+            .unwrap();
+
+        let report = FilesReport {
+            missing: vec!["missing.png".to_string()],
+            unregistered: vec!["upload-1.png".to_string()],
+            wrong_counts: vec![(used.clone(), 5, 1)],
+            orphans: vec!["upload-2.png".to_string()],
+        };
+        assert_eq!(files_manager.check(false).await.unwrap(), report);
+        assert_eq!(files_manager.check(true).await.unwrap(), report);
+        assert!(!storage.join("upload-2.png").exists());
+
+        clock.advance(Duration::days(2));
+        assert_eq!(
+            files_manager.check(true).await.unwrap(),
+            FilesReport {
+                missing: vec!["missing.png".to_string()],
+                orphans: vec![unused.clone()],
+                ..Default::default()
+            }
+        );
+        assert!(!storage.join(&unused).exists());
+        assert_eq!(
+            files_manager.check(false).await.unwrap(),
+            FilesReport {
+                missing: vec!["missing.png".to_string()],
+                ..Default::default()
+            }
+        );
+
+        wishlist_manager.delete_gift(1).unwrap();
+        users_manager
+            .edit_user(
+                one,
+                &EditUser {
+                    picture: Some(None),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(files_manager.delete_released().await.unwrap(), 1);
+        assert!(!storage.join("upload-1.png").exists());
+    }
+
+    #[tokio::test]
+    async fn test_variants() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_variants")));
+        let storage = create_test_storage("test_variants");
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        WishlistManager::new(connection.clone()).unwrap();
+        let files_manager = create_files_manager(connection, &storage, clock);
+
+        let mut content = Vec::new();
+        RgbImage::new(600, 400)
+            .write_to(&mut Cursor::new(&mut content), ImageFormat::Png)
+            .unwrap();
+        let name = files_manager
+            .store(one, content.clone(), "png")
+            .await
+            .unwrap();
+        let stem = name.trim_end_matches(".png");
+
+        let pending = files_manager
+            .get_variant(&name, ImageSize::Card, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(pending.pending);
+        assert_eq!(pending.name, name);
+        assert_eq!(pending.content, content);
+        assert_eq!(files_manager.process_queued_variants().await, 9);
+        assert_eq!(files_manager.process_queued_variants().await, 0);
+        assert_eq!(files_manager.queue_missing_variants().await.unwrap(), 0);
+
+        let card = files_manager
+            .get_variant(&name, ImageSize::Card, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(card.name, format!("{stem}-card.png"));
+        assert_eq!(card.mime_type, "image/png");
+        assert_eq!(card.created_at, Some(1717243200));
+        assert!(!card.pending);
+        let image = image::load_from_memory(&card.content).unwrap();
+        assert_eq!(image.dimensions(), (300, 200));
+        assert!(storage
+            .parent()
+            .unwrap()
+            .join("tmp_rs")
+            .join(&card.name)
+            .exists());
+
+        let print = files_manager
+            .get_variant(&name, ImageSize::Print, Some(VariantFormat::Webp))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(print.name, format!("{stem}-print.webp"));
+        assert_eq!(print.mime_type, "image/webp");
+        let image = image::load_from_memory(&print.content).unwrap();
+        assert_eq!(image.dimensions(), (600, 400));
+
+        let thumb = files_manager
+            .get_variant(&name, ImageSize::Thumb, Some(VariantFormat::Avif))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(thumb.name, format!("{stem}-thumb.avif"));
+        assert_eq!(thumb.mime_type, "image/avif");
+        assert_eq!(&thumb.content[4..12], b"ftypavif");
+
+        //Broken pictures are tried once, then sent as is
+        fs::write(storage.join("legacy.png"), b"broken").unwrap();
+        assert_eq!(files_manager.queue_missing_variants().await.unwrap(), 9);
+        assert_eq!(files_manager.process_queued_variants().await, 9);
+        assert_eq!(files_manager.queue_missing_variants().await.unwrap(), 0);
+        let legacy = files_manager
+            .get_variant("legacy.png", ImageSize::Card, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!legacy.pending);
+        assert_eq!(legacy.name, "legacy.png");
+        assert_eq!(legacy.content, b"broken");
+        assert_eq!(files_manager.process_queued_variants().await, 0);
+
+        fs::write(storage.join("notes.txt"), b"notes").unwrap();
+        let notes = files_manager
+            .get_variant("notes.txt", ImageSize::Thumb, Some(VariantFormat::Avif))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(notes.name, "notes.txt");
+        assert_eq!(notes.mime_type, "application/octet-stream");
+        assert_eq!(notes.content, b"notes");
+        assert_eq!(notes.created_at, None);
+
+        assert!(files_manager
+            .get_variant("unknown.png", ImageSize::Card, None)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_accept() {
+        assert_eq!(VariantFormat::from_accept(None), None);
+        assert_eq!(VariantFormat::from_accept(Some("*/*")), None);
+        assert_eq!(
+            VariantFormat::from_accept(Some("image/avif,image/webp,*/*")),
+            Some(VariantFormat::Avif)
+        );
+        assert_eq!(
+            VariantFormat::from_accept(Some("image/webp,*/*;q=0.8")),
+            Some(VariantFormat::Webp)
+        );
+        assert_eq!(
+            VariantFormat::from_accept(Some("image/avif;q=0, image/webp")),
+            Some(VariantFormat::Webp)
+        );
+    }
+
+    #[test]
+    fn test_prepare_picture() {
+        let uploads = UploadsConfiguration {
+            max_size: 1024 * 1024,
+            max_width: 100,
+            max_height: 100,
+            max_stored_side: 30,
+        };
+        //Big endian TIFF header, a camera model and an orientation rotated by 90°
+        let exif = [
+            b"MM\0\x2a\0\0\0\x08\0\x02".as_slice(),
+            b"\x01\x10\0\x02\0\0\0\x04SPY\0",
+            b"\x01\x12\0\x03\0\0\0\x01\0\x06\0\0",
+            b"\0\0\0\0",
+        ]
+        .concat();
+        let mut jpeg = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut jpeg);
+        encoder.set_exif_metadata(exif).unwrap();
+        RgbImage::new(40, 20).write_with_encoder(encoder).unwrap();
+
+        let (prepared, format) = prepare_picture(&jpeg, &uploads).unwrap();
+        assert_eq!(format, VariantFormat::Jpeg);
+        assert_eq!(image::guess_format(&prepared).unwrap(), ImageFormat::Jpeg);
+        let mut decoder = JpegDecoder::new(Cursor::new(&prepared)).unwrap();
+        assert_eq!(decoder.dimensions(), (15, 30));
+        assert_eq!(decoder.exif_metadata().unwrap(), None);
+        assert!(!prepared.windows(3).any(|w| w == b"SPY"));
+
+        let mut webp = Vec::new();
+        RgbaImage::new(20, 10)
+            .write_with_encoder(WebPEncoder::new_lossless(&mut webp))
+            .unwrap();
+        let (prepared, format) = prepare_picture(&webp, &uploads).unwrap();
+        assert_eq!(format, VariantFormat::Png);
+        let decoder = PngDecoder::new(Cursor::new(&prepared)).unwrap();
+        assert_eq!(decoder.dimensions(), (20, 10));
+
+        let mut too_wide = Vec::new();
+        RgbImage::new(101, 10)
+            .write_to(&mut Cursor::new(&mut too_wide), ImageFormat::Png)
+            .unwrap();
+        assert!(matches!(
+            prepare_picture(&too_wide, &uploads),
+            Err(FilesManagerError::PictureTooLarge)
+        ));
+        assert!(matches!(
+            prepare_picture(b"not a picture", &uploads),
+            Err(FilesManagerError::InvalidPicture(_))
+        ));
+    }
+}
@@ -1,9 +1,29 @@
 use crate::managers::users_manager::{CleanUser, UsersManager, UsersManagerError};
-use rusqlite::types::ToSqlOutput;
-use rusqlite::{params, Connection, ToSql};
+use crate::managers::wishlist_manager::{ReservationPolicy, WishlistManager};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, ToSql, Transaction};
 use serde::Serialize;
+use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 
+/**
+Ids of the friends of `user`, an SQL expression. Dependent accounts share the friends of their
+guardians: a user, their dependents and their guardians are friends of each other, and of the
+friends of the guardians and their dependents. A dependent does not count their own guardians as
+friends though, as the guardians would see the surprises of their own wishlist through it, and
+users who blocked each other are never friends.
+**/
+pub(crate) fn friends_of(user: &str) -> String {
+    format!(
+        "SELECT s.personId FROM representatives r JOIN representatives s ON s.repId=r.repId \
+            OR s.repId IN (SELECT friendId FROM acceptedFriends WHERE userId=r.repId) \
+        WHERE r.personId={user} AND s.personId!={user} \
+        AND s.personId NOT IN (SELECT guardianId FROM guardians WHERE dependentId={user}) \
+        AND NOT EXISTS (SELECT 1 FROM blockedUsers b WHERE (b.userId={user} AND b.blockedId=s.personId) \
+            OR (b.userId=s.personId AND b.blockedId={user}))"
+    )
+}
+
 #[derive(Clone)]
 pub struct FriendsManager {
     connection: Arc<Mutex<Connection>>,
@@ -21,6 +41,12 @@ pub(crate) enum FriendsManagerError {
     FriendRequestAlreadyExists(i64, i64),
     #[error("Friend request with id {0} for user {1} does not exist")]
     FriendRequestDoesNotExists(i64, i64),
+    #[error("{0} tried to block himself")]
+    CannotBlockYourself(i64),
+    #[error("{0} blocked {1}")]
+    UserBlocked(i64, i64),
+    #[error("{0} is a friend of {1} through a guardian")]
+    DerivedFriendship(i64, i64),
 }
 
 impl From<UsersManagerError> for FriendsManagerError {
@@ -28,7 +54,14 @@ impl From<UsersManagerError> for FriendsManagerError {
         match value {
             UsersManagerError::Sqlite(e) => FriendsManagerError::Sqlite(e),
             UsersManagerError::UnknownUser(u) => FriendsManagerError::UnknownUser(u),
-            UsersManagerError::UserAlreadyExist(_) | UsersManagerError::PasswordMismatch => {
+            UsersManagerError::UserAlreadyExist(_)
+            | UsersManagerError::PasswordMismatch
+            | UsersManagerError::UnknownTimeZone(_)
+            | UsersManagerError::InvalidReminderDays(_)
+            | UsersManagerError::DependentAccount(_)
+            | UsersManagerError::LastGuardian(_, _)
+            | UsersManagerError::CannotLinkYourself(_)
+            | UsersManagerError::ProfileFieldTooLong(_) => {
                 panic!("Should never happen")
             }
         }
@@ -45,9 +78,26 @@ impl FriendsManager {
         let connection = connection.lock().unwrap();
         connection.execute_batch("CREATE TABLE IF NOT EXISTS friendRequests (id INTEGER PRIMARY KEY AUTOINCREMENT, userOne INTEGER NOT NULL, userTwo INTEGER NOT NULL,
         status TEXT NOT NULL, FOREIGN KEY(userOne) REFERENCES users(id), FOREIGN KEY(userTwo) REFERENCES users(id))")?;
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS blockedUsers (userId INTEGER NOT NULL, blockedId INTEGER NOT NULL, UNIQUE(userId, blockedId), \
+        FOREIGN KEY(userId) REFERENCES users(id), FOREIGN KEY(blockedId) REFERENCES users(id))")?;
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS friendGroups (id INTEGER PRIMARY KEY AUTOINCREMENT, userId INTEGER NOT NULL, name TEXT NOT NULL, \
+        FOREIGN KEY(userId) REFERENCES users(id))")?;
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS friendGroupMembers (groupId INTEGER NOT NULL, friendId INTEGER NOT NULL, UNIQUE(groupId, friendId), \
+        FOREIGN KEY(groupId) REFERENCES friendGroups(id), FOREIGN KEY(friendId) REFERENCES users(id))")?;
+        connection.execute_batch("CREATE VIEW IF NOT EXISTS acceptedFriends AS SELECT userOne AS userId, userTwo AS friendId FROM friendRequests WHERE status='ACCEPTED' \
+        UNION SELECT userTwo, userOne FROM friendRequests WHERE status='ACCEPTED'")?;
+        //Whoever acts for a user: themselves, and their guardians if they are a dependent
+        connection.execute_batch("CREATE VIEW IF NOT EXISTS representatives AS SELECT id AS personId, id AS repId FROM users \
+        UNION SELECT dependentId, guardianId FROM guardians")?;
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS dismissedSuggestions (userId INTEGER NOT NULL, dismissedId INTEGER NOT NULL, UNIQUE(userId, dismissedId), \
+        FOREIGN KEY(userId) REFERENCES users(id), FOREIGN KEY(dismissedId) REFERENCES users(id))")?;
         Ok(())
     }
 
+    /**
+    A declined request can be asked again. A request to a user who blocked us is created but
+    never shown to them.
+    **/
     pub fn create_friend_request(
         &self,
         from_user_id: i64,
@@ -59,14 +109,31 @@ impl FriendsManager {
             return Err(FriendsManagerError::CannotAskYourself(from_user_id));
         }
 
-        let mut statement = connection.prepare("SELECT 1 FROM friendRequests WHERE userOne=? AND userTwo=? UNION ALL SELECT id FROM friendRequests WHERE userOne=? AND userTwo=?")?;
-        if statement.exists(params![from_user_id, to_user.id, to_user.id, from_user_id])? {
+        let mut statement =
+            connection.prepare("SELECT 1 FROM blockedUsers WHERE userId=? AND blockedId=?")?;
+        if statement.exists(params![from_user_id, to_user.id])? {
+            return Err(FriendsManagerError::UserBlocked(from_user_id, to_user.id));
+        }
+
+        let mut statement = connection.prepare("SELECT 1 FROM friendRequests WHERE userOne=? AND userTwo=? AND status!=? UNION ALL SELECT id FROM friendRequests WHERE userOne=? AND userTwo=? AND status!=?")?;
+        if statement.exists(params![
+            from_user_id,
+            to_user.id,
+            RequestStatus::Declined,
+            to_user.id,
+            from_user_id,
+            RequestStatus::Declined
+        ])? {
             return Err(FriendsManagerError::FriendRequestAlreadyExists(
                 from_user_id,
                 to_user.id,
             ));
         }
 
+        connection.execute(
+            "DELETE FROM friendRequests WHERE (userOne=? AND userTwo=?) OR (userOne=? AND userTwo=?)",
+            params![from_user_id, to_user.id, to_user.id, from_user_id],
+        )?;
         connection.execute(
             "INSERT INTO friendRequests(userOne,userTwo,status) VALUES (?, ?, ?)",
             params![from_user_id, to_user.id, "PENDING"],
@@ -77,21 +144,12 @@ impl FriendsManager {
     pub fn get_friends(&self, user_id: i64) -> Result<Vec<CleanUser>, FriendsManagerError> {
         let connection = self.connection.lock().unwrap();
 
-        let mut statement = connection.prepare(
-            "WITH friends AS (SELECT userTwo as user_id FROM friendRequests WHERE userOne=? and status=?
-            UNION ALL 
-            SELECT userOne as user_id FROM friendRequests WHERE userTwo=? and status=?)
-            SELECT id, name, picture, dateOfBirth FROM users WHERE id IN (SELECT user_id FROM friends)")?;
+        let mut statement = connection.prepare(&format!(
+            "SELECT id, name, picture, dateOfBirth FROM users WHERE id IN ({})",
+            friends_of("?1")
+        ))?;
 
-        let rows = statement.query_map(
-            params![
-                user_id,
-                RequestStatus::Accepted,
-                user_id,
-                RequestStatus::Accepted
-            ],
-            |row| CleanUser::try_from(row),
-        )?;
+        let rows = statement.query_map(params![user_id], |row| CleanUser::try_from(row))?;
 
         let mut friends = Vec::new();
         for row in rows {
@@ -122,7 +180,8 @@ impl FriendsManager {
         let connection = self.connection.lock().unwrap();
 
         let sent = Self::get_requests_internal(&connection, "SELECT f.id, u.id, u.name, u.picture, u.dateOfBirth FROM friendRequests f LEFT JOIN users u ON u.id=f.userTwo WHERE userOne=? and status=?", user_id)?;
-        let received = Self::get_requests_internal(&connection, "SELECT f.id, u.id, u.name, u.picture, u.dateOfBirth FROM friendRequests f LEFT JOIN users u ON u.id=f.userOne WHERE userTwo=? and status=?", user_id)?;
+        let received = Self::get_requests_internal(&connection, "SELECT f.id, u.id, u.name, u.picture, u.dateOfBirth FROM friendRequests f LEFT JOIN users u ON u.id=f.userOne WHERE userTwo=? and status=? \
+            AND NOT EXISTS (SELECT 1 FROM blockedUsers b WHERE b.userId=f.userTwo AND b.blockedId=f.userOne)", user_id)?;
 
         Ok(Requests { sent, received })
     }
@@ -160,8 +219,10 @@ impl FriendsManager {
     ) -> Result<(), FriendsManagerError> {
         let connection = self.connection.lock().unwrap();
 
-        let mut statement = connection
-            .prepare("SELECT 1 FROM friendRequests where id=? AND userTwo=? AND status=?")?;
+        let mut statement = connection.prepare(
+            "SELECT 1 FROM friendRequests f where id=? AND userTwo=? AND status=? \
+            AND NOT EXISTS (SELECT 1 FROM blockedUsers b WHERE b.userId=f.userTwo AND b.blockedId=f.userOne)",
+        )?;
         if !statement.exists(params![request_id, user_id, RequestStatus::Pending])? {
             return Err(FriendsManagerError::FriendRequestDoesNotExists(
                 request_id, user_id,
@@ -196,24 +257,511 @@ impl FriendsManager {
         Ok(())
     }
 
-    pub fn is_my_friend(&self, user_id: i64, friend_id: i64) -> Result<bool, FriendsManagerError> {
+    /**
+    The friend also leaves the categories we share, in the same transaction. Friends through a
+    guardian cannot be removed on their own, only by removing the guardian or blocking them.
+    **/
+    pub fn remove_friend(
+        &self,
+        user_id: i64,
+        friend_id: i64,
+        reservations: ReservationPolicy,
+    ) -> Result<(), FriendsManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let count = transaction.execute(
+            "DELETE FROM friendRequests WHERE ((userOne=? AND userTwo=?) OR (userOne=? AND userTwo=?)) AND status=?",
+            params![
+                user_id,
+                friend_id,
+                friend_id,
+                user_id,
+                RequestStatus::Accepted
+            ],
+        )?;
+        if count == 0 {
+            let mut statement =
+                transaction.prepare(&format!("SELECT 1 WHERE ?2 IN ({})", friends_of("?1")))?;
+            if statement.exists(params![user_id, friend_id])? {
+                return Err(FriendsManagerError::DerivedFriendship(friend_id, user_id));
+            }
+            return Err(FriendsManagerError::UnknownUser(friend_id.to_string()));
+        }
+        Self::remove_from_groups(&transaction, user_id, friend_id)?;
+        WishlistManager::static_remove_friend(&transaction, user_id, friend_id, reservations)?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /**
+    Each one leaves the groups of the other
+    **/
+    fn remove_from_groups(
+        transaction: &Transaction,
+        user_id: i64,
+        friend_id: i64,
+    ) -> Result<(), FriendsManagerError> {
+        transaction.execute(
+            "DELETE FROM friendGroupMembers WHERE friendId=?1 AND groupId IN (SELECT id FROM friendGroups WHERE userId=?2)",
+            params![friend_id, user_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM friendGroupMembers WHERE friendId=?2 AND groupId IN (SELECT id FROM friendGroups WHERE userId=?1)",
+            params![friend_id, user_id],
+        )?;
+        Ok(())
+    }
+
+    /**
+    Blocking ends the friendship and our requests, releasing their reservations on our wishlist.
+    Their pending requests are kept but hidden.
+    **/
+    pub fn block_user(&self, user_id: i64, blocked_user: &str) -> Result<i64, FriendsManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let blocked_user = UsersManager::static_get_user(&connection, blocked_user)?;
+        if blocked_user.id == user_id {
+            return Err(FriendsManagerError::CannotBlockYourself(user_id));
+        }
+
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "DELETE FROM friendRequests WHERE (userOne=? AND userTwo=?) OR (userOne=? AND userTwo=? AND status=?)",
+            params![
+                user_id,
+                blocked_user.id,
+                blocked_user.id,
+                user_id,
+                RequestStatus::Accepted
+            ],
+        )?;
+        transaction.execute(
+            "INSERT OR IGNORE INTO blockedUsers(userId, blockedId) VALUES (?, ?)",
+            params![user_id, blocked_user.id],
+        )?;
+        Self::remove_from_groups(&transaction, user_id, blocked_user.id)?;
+        WishlistManager::static_remove_friend(
+            &transaction,
+            user_id,
+            blocked_user.id,
+            ReservationPolicy::Release,
+        )?;
+        transaction.commit()?;
+
+        Ok(blocked_user.id)
+    }
+
+    pub fn unblock_user(&self, user_id: i64, blocked_id: i64) -> Result<(), FriendsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let count = connection.execute(
+            "DELETE FROM blockedUsers WHERE userId=? AND blockedId=?",
+            params![user_id, blocked_id],
+        )?;
+        if count == 0 {
+            return Err(FriendsManagerError::UnknownUser(blocked_id.to_string()));
+        }
+
+        Ok(())
+    }
+
+    pub fn get_blocked_users(&self, user_id: i64) -> Result<Vec<CleanUser>, FriendsManagerError> {
         let connection = self.connection.lock().unwrap();
+
         let mut statement = connection.prepare(
-            "SELECT id FROM friendRequests WHERE userOne=? AND userTwo =? and status=? \
-        UNION ALL \
-        SELECT id FROM friendRequests WHERE userOne=? AND userTwo =? and status=?",
+            "SELECT u.id, u.name, u.picture, u.dateOfBirth FROM blockedUsers b LEFT JOIN users u ON u.id=b.blockedId WHERE b.userId=?",
         )?;
-        Ok(statement.exists(params![
-            user_id,
-            friend_id,
-            RequestStatus::Accepted,
-            friend_id,
-            user_id,
-            RequestStatus::Accepted
-        ])?)
+        let rows = statement.query_map(params![user_id], |row| CleanUser::try_from(row))?;
+
+        let mut blocked_users = Vec::new();
+        for row in rows {
+            blocked_users.push(row?);
+        }
+
+        Ok(blocked_users)
+    }
+
+    pub fn add_group(
+        &self,
+        user_id: i64,
+        name: &str,
+        members: HashSet<i64>,
+    ) -> Result<(), FriendsManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO friendGroups (userId, name) VALUES (?, ?)",
+            params![user_id, name],
+        )?;
+        let group_id = transaction.last_insert_rowid();
+        Self::add_group_members(&transaction, group_id, members)?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    fn add_group_members(
+        transaction: &Transaction,
+        group_id: i64,
+        members: HashSet<i64>,
+    ) -> Result<(), FriendsManagerError> {
+        for member in members {
+            transaction.execute(
+                "INSERT INTO friendGroupMembers (groupId, friendId) VALUES (?, ?)",
+                params![group_id, member],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn edit_group(
+        &self,
+        group_id: i64,
+        name: &str,
+        members: HashSet<i64>,
+    ) -> Result<(), FriendsManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "UPDATE friendGroups SET name=? WHERE id=?",
+            params![name, group_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM friendGroupMembers WHERE groupId=?",
+            params![group_id],
+        )?;
+        Self::add_group_members(&transaction, group_id, members)?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    pub fn delete_group(&self, group_id: i64) -> Result<(), FriendsManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "DELETE FROM friendGroupMembers WHERE groupId=?",
+            params![group_id],
+        )?;
+        transaction.execute("DELETE FROM friendGroups WHERE id=?", params![group_id])?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    pub fn get_groups(&self, user_id: i64) -> Result<Vec<FriendGroup>, FriendsManagerError> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement =
+            connection.prepare("SELECT id, name FROM friendGroups WHERE userId=? ORDER BY id")?;
+        let rows = statement.query_map(params![user_id], |row| {
+            Ok(FriendGroup {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                members: Vec::new(),
+            })
+        })?;
+
+        let mut groups = Vec::new();
+        for row in rows {
+            let mut group = row?;
+            let mut statement = connection.prepare(
+                "SELECT friendId FROM friendGroupMembers WHERE groupId=? ORDER BY friendId",
+            )?;
+            let rows = statement.query_map(params![group.id], |row| row.get(0))?;
+            for row in rows {
+                group.members.push(row?);
+            }
+            groups.push(group);
+        }
+
+        Ok(groups)
+    }
+
+    pub fn is_my_group(&self, user_id: i64, group_id: i64) -> Result<bool, FriendsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT 1 FROM friendGroups WHERE id=? AND userId=?")?;
+        Ok(statement.exists(params![group_id, user_id])?)
+    }
+
+    /**
+    Search the discoverable users by name and display name, best matches first: exact, prefix,
+    word prefix, substring, then names with a typo. Users who blocked us, or whom we blocked,
+    are left out.
+    **/
+    pub fn search_users(
+        &self,
+        user_id: i64,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<FoundUser>, FriendsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let query = query.trim().to_lowercase();
+
+        let mut statement = connection.prepare(
+            "SELECT id, name, displayName, picture FROM users u WHERE id!=?1 AND discoverable=TRUE \
+            AND NOT EXISTS (SELECT 1 FROM blockedUsers b WHERE (b.userId=u.id AND b.blockedId=?1) OR (b.userId=?1 AND b.blockedId=u.id))",
+        )?;
+        let rows = statement.query_map(params![user_id], |row| {
+            <(i64, String, Option<String>, Option<String>)>::try_from(row)
+        })?;
+        let mut matches = Vec::new();
+        for row in rows {
+            let (id, name, display_name, picture) = row?;
+            let score = [Some(&name), display_name.as_ref()]
+                .into_iter()
+                .flatten()
+                .filter_map(|candidate| match_score(&query, &candidate.to_lowercase()))
+                .min();
+            if let Some(score) = score {
+                matches.push((score, id, name, display_name, picture));
+            }
+        }
+        matches.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.2.cmp(&b.2)));
+        matches.truncate(limit);
+
+        let mut users = Vec::new();
+        for (_, id, name, display_name, picture) in matches {
+            let mutual_friends = connection.query_row(
+                "WITH mine AS (SELECT userTwo AS id FROM friendRequests WHERE userOne=?1 AND status=?3 \
+                    UNION SELECT userOne FROM friendRequests WHERE userTwo=?1 AND status=?3), \
+                theirs AS (SELECT userTwo AS id FROM friendRequests WHERE userOne=?2 AND status=?3 \
+                    UNION SELECT userOne FROM friendRequests WHERE userTwo=?2 AND status=?3) \
+                SELECT COUNT(*) FROM mine WHERE id IN (SELECT id FROM theirs)",
+                params![user_id, id, RequestStatus::Accepted],
+                |row| row.get(0),
+            )?;
+            let relation = connection
+                .query_row(
+                    "SELECT userOne, status FROM friendRequests WHERE ((userOne=?1 AND userTwo=?2) OR (userOne=?2 AND userTwo=?1)) AND status!=?3",
+                    params![user_id, id, RequestStatus::Declined],
+                    |row| <(i64, RequestStatus)>::try_from(row),
+                )
+                .optional()?;
+            let relation = match relation {
+                None => Relation::None,
+                Some((_, RequestStatus::Accepted)) => Relation::Friend,
+                Some((from, _)) if from == user_id => Relation::RequestSent,
+                Some(_) => Relation::RequestReceived,
+            };
+            users.push(FoundUser {
+                id,
+                name,
+                display_name,
+                picture,
+                mutual_friends,
+                relation,
+            });
+        }
+
+        Ok(users)
+    }
+
+    /**
+    Friends of our friends, the most mutual friends first. Users we already asked or who asked
+    us, blocked users, users who are not discoverable and dismissed suggestions are left out.
+    **/
+    pub fn get_suggestions(
+        &self,
+        user_id: i64,
+        limit: usize,
+    ) -> Result<Vec<Suggestion>, FriendsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "WITH friends AS (SELECT userOne AS userId, userTwo AS friendId FROM friendRequests WHERE status=?2 \
+                UNION SELECT userTwo, userOne FROM friendRequests WHERE status=?2) \
+            SELECT u.id, u.name, u.displayName, u.picture, COUNT(*) AS mutual FROM friends mine \
+            JOIN friends theirs ON theirs.userId=mine.friendId JOIN users u ON u.id=theirs.friendId \
+            WHERE mine.userId=?1 AND u.id!=?1 AND u.discoverable=TRUE \
+            AND NOT EXISTS (SELECT 1 FROM friendRequests r WHERE (r.userOne=?1 AND r.userTwo=u.id) OR (r.userOne=u.id AND r.userTwo=?1)) \
+            AND NOT EXISTS (SELECT 1 FROM blockedUsers b WHERE (b.userId=u.id AND b.blockedId=?1) OR (b.userId=?1 AND b.blockedId=u.id)) \
+            AND NOT EXISTS (SELECT 1 FROM dismissedSuggestions d WHERE d.userId=?1 AND d.dismissedId=u.id) \
+            GROUP BY u.id ORDER BY mutual DESC, u.name LIMIT ?3",
+        )?;
+        let rows = statement.query_map(
+            params![user_id, RequestStatus::Accepted, limit as i64],
+            |row| {
+                Ok(Suggestion {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    display_name: row.get(2)?,
+                    picture: row.get(3)?,
+                    mutual_friends: row.get(4)?,
+                })
+            },
+        )?;
+
+        let mut suggestions = Vec::new();
+        for row in rows {
+            suggestions.push(row?);
+        }
+        Ok(suggestions)
+    }
+
+    pub fn dismiss_suggestion(
+        &self,
+        user_id: i64,
+        dismissed_id: i64,
+    ) -> Result<(), FriendsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT 1 FROM users WHERE id=?")?;
+        if !statement.exists(params![dismissed_id])? {
+            return Err(FriendsManagerError::UnknownUser(dismissed_id.to_string()));
+        }
+        connection.execute(
+            "INSERT OR IGNORE INTO dismissedSuggestions(userId, dismissedId) VALUES (?, ?)",
+            params![user_id, dismissed_id],
+        )?;
+        Ok(())
+    }
+
+    /**
+    Every friend request the user sent or received, whatever became of it
+    **/
+    pub fn get_friendships(&self, user_id: i64) -> Result<Vec<Friendship>, FriendsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT f.id, f.userOne=?1, lower(f.status), u.id, u.name, u.picture, u.dateOfBirth FROM friendRequests f \
+            JOIN users u ON u.id=(CASE WHEN f.userOne=?1 THEN f.userTwo ELSE f.userOne END) WHERE f.userOne=?1 OR f.userTwo=?1 ORDER BY f.id",
+        )?;
+        let rows = statement.query_map(params![user_id], |row| {
+            Ok(Friendship {
+                id: row.get(0)?,
+                sent: row.get(1)?,
+                status: row.get(2)?,
+                other_user: CleanUser {
+                    id: row.get(3)?,
+                    name: row.get(4)?,
+                    picture: row.get(5)?,
+                    date_of_birth: row.get(6)?,
+                },
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /**
+    Forget the friendships, requests, blocks, groups and suggestions of a deleted user
+    **/
+    pub fn delete_user(&self, user_id: i64) -> Result<(), FriendsManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "DELETE FROM friendGroupMembers WHERE friendId=?1 OR groupId IN (SELECT id FROM friendGroups WHERE userId=?1)",
+            params![user_id],
+        )?;
+        transaction.execute("DELETE FROM friendGroups WHERE userId=?", params![user_id])?;
+        transaction.execute(
+            "DELETE FROM friendRequests WHERE userOne=?1 OR userTwo=?1",
+            params![user_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM blockedUsers WHERE userId=?1 OR blockedId=?1",
+            params![user_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM dismissedSuggestions WHERE userId=?1 OR dismissedId=?1",
+            params![user_id],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn is_my_friend(&self, user_id: i64, friend_id: i64) -> Result<bool, FriendsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare(&format!("SELECT 1 WHERE ?2 IN ({})", friends_of("?1")))?;
+        Ok(statement.exists(params![user_id, friend_id])?)
     }
 }
 
+/**
+Lower is better, `None` when the name does not match
+**/
+fn match_score(query: &str, name: &str) -> Option<usize> {
+    if query.is_empty() {
+        return None;
+    }
+    if name == query {
+        return Some(0);
+    }
+    if name.starts_with(query) {
+        return Some(1);
+    }
+    if name
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| word.starts_with(query))
+    {
+        return Some(2);
+    }
+    if name.contains(query) {
+        return Some(3);
+    }
+    //A typo every four characters
+    let query = query.chars().collect::<Vec<_>>();
+    if query.len() < 3 {
+        return None;
+    }
+    let prefix = name.chars().take(query.len()).collect::<Vec<_>>();
+    let distance = edit_distance(&query, &prefix);
+    (distance <= (query.len() / 4).max(1)).then_some(4 + distance)
+}
+
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+#[serde(rename_all = "snake_case")]
+pub enum Relation {
+    None,
+    Friend,
+    RequestSent,
+    RequestReceived,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct FoundUser {
+    id: i64,
+    name: String,
+    display_name: Option<String>,
+    picture: Option<String>,
+    mutual_friends: u32,
+    relation: Relation,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct Suggestion {
+    id: i64,
+    name: String,
+    display_name: Option<String>,
+    picture: Option<String>,
+    mutual_friends: u32,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct FriendGroup {
+    id: i64,
+    name: String,
+    members: Vec<i64>,
+}
+
 #[derive(Serialize)]
 #[cfg_attr(test, derive(PartialEq, Eq, Debug))]
 pub struct FriendRequest {
@@ -221,6 +769,17 @@ pub struct FriendRequest {
     other_user: CleanUser,
 }
 
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct Friendship {
+    id: i64,
+    other_user: CleanUser,
+    /** Whether the user asked, rather than being asked **/
+    sent: bool,
+    /** pending, accepted or declined **/
+    status: String,
+}
+
 #[derive(Serialize)]
 #[cfg_attr(test, derive(PartialEq, Eq, Debug))]
 pub struct Requests {
@@ -244,13 +803,28 @@ impl ToSql for RequestStatus {
     }
 }
 
+impl FromSql for RequestStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "PENDING" => Ok(RequestStatus::Pending),
+            "ACCEPTED" => Ok(RequestStatus::Accepted),
+            "DECLINED" => Ok(RequestStatus::Declined),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use crate::clock::SystemClock;
     use crate::managers::friends_manager::{
-        FriendRequest, FriendsManager, FriendsManagerError, RequestStatus, Requests,
+        FoundUser, FriendGroup, FriendRequest, FriendsManager, FriendsManagerError, Friendship,
+        Relation, RequestStatus, Requests, Suggestion,
     };
     use crate::managers::test_helper::create_test_database;
-    use crate::managers::users_manager::{CleanUser, UsersManager};
+    use crate::managers::users_manager::{CleanUser, EditProfile, EditSettings, UsersManager};
+    use crate::managers::wishlist_manager::{ReservationPolicy, WishlistManager};
+    use std::collections::HashSet;
     use std::sync::{Arc, Mutex};
 
     #[test]
@@ -258,7 +832,7 @@ mod test {
         let connection = Arc::new(Mutex::new(create_test_database(
             "test_create_friend_requests",
         )));
-        let users_manager = UsersManager::new(connection.clone()).unwrap();
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
         let one = users_manager.add_user("one", "pwd").unwrap();
         let two = users_manager.add_user("two", "pwd").unwrap();
         let three = users_manager.add_user("three", "pwd").unwrap();
@@ -298,7 +872,7 @@ mod test {
     #[test]
     fn test_get_friend_requests() {
         let connection = Arc::new(Mutex::new(create_test_database("test_get_friend_requests")));
-        let users_manager = UsersManager::new(connection.clone()).unwrap();
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
         let one = users_manager.add_user("one", "pwd").unwrap();
         let two = users_manager.add_user("two", "pwd").unwrap();
         let three = users_manager.add_user("three", "pwd").unwrap();
@@ -372,7 +946,7 @@ mod test {
         let connection = Arc::new(Mutex::new(create_test_database(
             "test_update_friend_request",
         )));
-        let users_manager = UsersManager::new(connection.clone()).unwrap();
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
         let one = users_manager.add_user("one", "pwd").unwrap();
         let two = users_manager.add_user("two", "pwd").unwrap();
 
@@ -447,7 +1021,7 @@ mod test {
         let connection = Arc::new(Mutex::new(create_test_database(
             "test_cancel_friend_request",
         )));
-        let users_manager = UsersManager::new(connection.clone()).unwrap();
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
         let one = users_manager.add_user("one", "pwd").unwrap();
         let two = users_manager.add_user("two", "pwd").unwrap();
 
@@ -493,4 +1067,505 @@ mod test {
             FriendsManagerError::FriendRequestDoesNotExists(_, _)
         ));
     }
+
+    #[test]
+    fn test_request_after_decline() {
+        let connection = Arc::new(Mutex::new(create_test_database(
+            "test_request_after_decline",
+        )));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+
+        let friend_manager = FriendsManager::new(connection.clone()).unwrap();
+        friend_manager.create_friend_request(one, "two").unwrap();
+        friend_manager
+            .update_received_request(1, two, RequestStatus::Declined)
+            .unwrap();
+
+        //Both sides can ask again
+        friend_manager.create_friend_request(two, "one").unwrap();
+        let requests = friend_manager.get_requests(one).unwrap();
+        assert_eq!(
+            requests,
+            Requests {
+                sent: vec![],
+                received: vec![FriendRequest {
+                    id: 2,
+                    other_user: CleanUser {
+                        id: 2,
+                        name: "two".to_string(),
+                        picture: None,
+                        date_of_birth: None
+                    }
+                }]
+            }
+        );
+        let error = friend_manager
+            .create_friend_request(one, "two")
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            FriendsManagerError::FriendRequestAlreadyExists(_, _)
+        ));
+    }
+
+    #[test]
+    fn test_get_friendships() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_get_friendships")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let three = users_manager.add_user("three", "pwd").unwrap();
+
+        let friend_manager = FriendsManager::new(connection.clone()).unwrap();
+        friend_manager.create_friend_request(one, "two").unwrap();
+        friend_manager
+            .update_received_request(1, two, RequestStatus::Accepted)
+            .unwrap();
+        friend_manager.create_friend_request(three, "one").unwrap();
+
+        let user = |id: i64, name: &str| CleanUser {
+            id,
+            name: name.to_string(),
+            picture: None,
+            date_of_birth: None,
+        };
+        assert_eq!(
+            friend_manager.get_friendships(one).unwrap(),
+            vec![
+                Friendship {
+                    id: 1,
+                    other_user: user(two, "two"),
+                    sent: true,
+                    status: "accepted".to_string(),
+                },
+                Friendship {
+                    id: 2,
+                    other_user: user(three, "three"),
+                    sent: false,
+                    status: "pending".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            friend_manager.get_friendships(two).unwrap(),
+            vec![Friendship {
+                id: 1,
+                other_user: user(one, "one"),
+                sent: false,
+                status: "accepted".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_remove_friend() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_remove_friend")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+
+        let friend_manager = FriendsManager::new(connection.clone()).unwrap();
+        WishlistManager::new(connection.clone()).unwrap();
+        friend_manager.create_friend_request(one, "two").unwrap();
+        let error = friend_manager
+            .remove_friend(one, two, ReservationPolicy::Release)
+            .unwrap_err();
+        assert!(matches!(error, FriendsManagerError::UnknownUser(_)));
+
+        friend_manager
+            .update_received_request(1, two, RequestStatus::Accepted)
+            .unwrap();
+        friend_manager
+            .remove_friend(two, one, ReservationPolicy::Release)
+            .unwrap();
+        assert!(!friend_manager.is_my_friend(one, two).unwrap());
+        assert_eq!(friend_manager.get_friends(one).unwrap(), vec![]);
+
+        //Can become friends again
+        friend_manager.create_friend_request(two, "one").unwrap();
+        friend_manager
+            .update_received_request(2, one, RequestStatus::Accepted)
+            .unwrap();
+        assert!(friend_manager.is_my_friend(one, two).unwrap());
+    }
+
+    #[test]
+    fn test_block_user() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_block_user")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let three = users_manager.add_user("three", "pwd").unwrap();
+
+        let friend_manager = FriendsManager::new(connection.clone()).unwrap();
+        WishlistManager::new(connection.clone()).unwrap();
+        friend_manager.create_friend_request(one, "two").unwrap();
+        friend_manager
+            .update_received_request(1, two, RequestStatus::Accepted)
+            .unwrap();
+        friend_manager.create_friend_request(one, "three").unwrap();
+
+        let error = friend_manager.block_user(one, "one").unwrap_err();
+        assert!(matches!(error, FriendsManagerError::CannotBlockYourself(_)));
+        assert_eq!(friend_manager.block_user(one, "two").unwrap(), two);
+        assert_eq!(friend_manager.block_user(three, "one").unwrap(), one);
+        assert!(!friend_manager.is_my_friend(one, two).unwrap());
+        assert_eq!(
+            friend_manager.get_blocked_users(one).unwrap(),
+            vec![CleanUser {
+                id: 2,
+                name: "two".to_string(),
+                picture: None,
+                date_of_birth: None
+            }]
+        );
+
+        //Cannot ask a user we blocked
+        let error = friend_manager
+            .create_friend_request(one, "two")
+            .unwrap_err();
+        assert!(matches!(error, FriendsManagerError::UserBlocked(_, _)));
+
+        //Requests from a blocked user are silently hidden
+        friend_manager.create_friend_request(two, "one").unwrap();
+        assert_eq!(
+            friend_manager.get_requests(one).unwrap(),
+            Requests {
+                sent: vec![FriendRequest {
+                    id: 2,
+                    other_user: CleanUser {
+                        id: 3,
+                        name: "three".to_string(),
+                        picture: None,
+                        date_of_birth: None
+                    }
+                }],
+                received: vec![]
+            }
+        );
+        assert_eq!(friend_manager.get_requests(two).unwrap().sent.len(), 1);
+        let error = friend_manager
+            .update_received_request(3, one, RequestStatus::Accepted)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            FriendsManagerError::FriendRequestDoesNotExists(_, _)
+        ));
+        //Nor does three see the request of the user they blocked
+        assert_eq!(friend_manager.get_requests(three).unwrap().received, vec![]);
+
+        let error = friend_manager.unblock_user(one, three).unwrap_err();
+        assert!(matches!(error, FriendsManagerError::UnknownUser(_)));
+        friend_manager.unblock_user(one, two).unwrap();
+        assert_eq!(friend_manager.get_blocked_users(one).unwrap(), vec![]);
+        assert_eq!(friend_manager.get_requests(one).unwrap().received.len(), 1);
+    }
+
+    #[test]
+    fn test_groups() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_friend_groups")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let three = users_manager.add_user("three", "pwd").unwrap();
+
+        let friend_manager = FriendsManager::new(connection.clone()).unwrap();
+        WishlistManager::new(connection.clone()).unwrap();
+        friend_manager.create_friend_request(one, "two").unwrap();
+        friend_manager
+            .update_received_request(1, two, RequestStatus::Accepted)
+            .unwrap();
+        friend_manager.create_friend_request(one, "three").unwrap();
+        friend_manager
+            .update_received_request(2, three, RequestStatus::Accepted)
+            .unwrap();
+
+        friend_manager
+            .add_group(one, "Family", HashSet::from([two, three]))
+            .unwrap();
+        friend_manager
+            .add_group(two, "Colleagues", HashSet::from([one]))
+            .unwrap();
+        assert!(friend_manager.is_my_group(one, 1).unwrap());
+        assert!(!friend_manager.is_my_group(two, 1).unwrap());
+        assert_eq!(
+            friend_manager.get_groups(one).unwrap(),
+            vec![FriendGroup {
+                id: 1,
+                name: "Family".to_string(),
+                members: vec![two, three]
+            }]
+        );
+
+        friend_manager
+            .edit_group(1, "Close family", HashSet::from([three]))
+            .unwrap();
+        assert_eq!(
+            friend_manager.get_groups(one).unwrap(),
+            vec![FriendGroup {
+                id: 1,
+                name: "Close family".to_string(),
+                members: vec![three]
+            }]
+        );
+
+        //Removing a friend removes them from our groups and us from theirs
+        friend_manager
+            .remove_friend(one, two, ReservationPolicy::Release)
+            .unwrap();
+        assert_eq!(
+            friend_manager.get_groups(two).unwrap(),
+            vec![FriendGroup {
+                id: 2,
+                name: "Colleagues".to_string(),
+                members: vec![]
+            }]
+        );
+
+        friend_manager.delete_group(1).unwrap();
+        assert_eq!(friend_manager.get_groups(one).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_search_users() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_search_users")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let me = users_manager.add_user("me", "pwd").unwrap();
+        let alice = users_manager.add_user("alice", "pwd").unwrap();
+        let malice = users_manager.add_user("malice", "pwd").unwrap();
+        let bob = users_manager.add_user("bob", "pwd").unwrap();
+        let alise = users_manager.add_user("alise", "pwd").unwrap();
+        users_manager.add_user("alice_hidden", "pwd").unwrap();
+        let blocker = users_manager.add_user("alice_blocker", "pwd").unwrap();
+        let carol = users_manager.add_user("carol", "pwd").unwrap();
+        //Users are only found once they opt in
+        let discoverable = EditSettings {
+            discoverable: Some(true),
+            ..Default::default()
+        };
+        for user in [me, alice, malice, bob, alise, blocker, carol] {
+            users_manager.edit_settings(user, &discoverable).unwrap();
+        }
+        users_manager
+            .edit_profile(
+                bob,
+                &EditProfile {
+                    display_name: Some("Bob Alice".to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let friend_manager = FriendsManager::new(connection.clone()).unwrap();
+        WishlistManager::new(connection.clone()).unwrap();
+        friend_manager.create_friend_request(me, "carol").unwrap();
+        friend_manager
+            .update_received_request(1, carol, RequestStatus::Accepted)
+            .unwrap();
+        friend_manager
+            .create_friend_request(alice, "carol")
+            .unwrap();
+        friend_manager
+            .update_received_request(2, carol, RequestStatus::Accepted)
+            .unwrap();
+        friend_manager.create_friend_request(me, "malice").unwrap();
+        friend_manager.block_user(blocker, "me").unwrap();
+
+        assert_eq!(
+            friend_manager.search_users(me, " Alice", 20).unwrap(),
+            vec![
+                FoundUser {
+                    id: alice,
+                    name: "alice".to_string(),
+                    display_name: None,
+                    picture: None,
+                    mutual_friends: 1,
+                    relation: Relation::None,
+                },
+                FoundUser {
+                    id: bob,
+                    name: "bob".to_string(),
+                    display_name: Some("Bob Alice".to_string()),
+                    picture: None,
+                    mutual_friends: 0,
+                    relation: Relation::None,
+                },
+                FoundUser {
+                    id: malice,
+                    name: "malice".to_string(),
+                    display_name: None,
+                    picture: None,
+                    mutual_friends: 0,
+                    relation: Relation::RequestSent,
+                },
+                FoundUser {
+                    id: alise,
+                    name: "alise".to_string(),
+                    display_name: None,
+                    picture: None,
+                    mutual_friends: 0,
+                    relation: Relation::None,
+                },
+            ]
+        );
+        assert_eq!(
+            friend_manager.search_users(me, "alice", 1).unwrap().len(),
+            1
+        );
+        assert_eq!(
+            friend_manager.search_users(alice, "carol", 20).unwrap(),
+            vec![FoundUser {
+                id: carol,
+                name: "carol".to_string(),
+                display_name: None,
+                picture: None,
+                mutual_friends: 0,
+                relation: Relation::Friend,
+            }]
+        );
+        //Nobody can find themselves
+        assert_eq!(friend_manager.search_users(me, "me", 20).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_suggestions")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let me = users_manager.add_user("me", "pwd").unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let common = users_manager.add_user("common", "pwd").unwrap();
+        let other = users_manager.add_user("other", "pwd").unwrap();
+        let asked = users_manager.add_user("asked", "pwd").unwrap();
+        let blocked = users_manager.add_user("blocked", "pwd").unwrap();
+        let discoverable = EditSettings {
+            discoverable: Some(true),
+            ..Default::default()
+        };
+        for user in [one, two, common, other, asked, blocked] {
+            users_manager.edit_settings(user, &discoverable).unwrap();
+        }
+
+        let friend_manager = FriendsManager::new(connection.clone()).unwrap();
+        WishlistManager::new(connection.clone()).unwrap();
+        let mut request_id = 0;
+        for (from, to) in [
+            (me, "one"),
+            (me, "two"),
+            (one, "common"),
+            (two, "common"),
+            (one, "other"),
+            (one, "asked"),
+            (one, "blocked"),
+        ] {
+            friend_manager.create_friend_request(from, to).unwrap();
+            request_id += 1;
+            let to = users_manager.get_user(to).unwrap().id;
+            friend_manager
+                .update_received_request(request_id, to, RequestStatus::Accepted)
+                .unwrap();
+        }
+        friend_manager.create_friend_request(asked, "me").unwrap();
+        friend_manager.block_user(me, "blocked").unwrap();
+
+        assert_eq!(
+            friend_manager.get_suggestions(me, 10).unwrap(),
+            vec![
+                Suggestion {
+                    id: common,
+                    name: "common".to_string(),
+                    display_name: None,
+                    picture: None,
+                    mutual_friends: 2,
+                },
+                Suggestion {
+                    id: other,
+                    name: "other".to_string(),
+                    display_name: None,
+                    picture: None,
+                    mutual_friends: 1,
+                },
+            ]
+        );
+        assert_eq!(friend_manager.get_suggestions(me, 1).unwrap().len(), 1);
+
+        friend_manager.dismiss_suggestion(me, common).unwrap();
+        friend_manager.dismiss_suggestion(me, common).unwrap();
+        assert_eq!(
+            friend_manager.get_suggestions(me, 10).unwrap(),
+            vec![Suggestion {
+                id: other,
+                name: "other".to_string(),
+                display_name: None,
+                picture: None,
+                mutual_friends: 1,
+            }]
+        );
+        let error = friend_manager.dismiss_suggestion(me, 42).unwrap_err();
+        assert!(matches!(error, FriendsManagerError::UnknownUser(_)));
+    }
+
+    #[test]
+    fn test_dependent_friends() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_dependent_friends")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let mum = users_manager.add_user("mum", "pwd").unwrap();
+        let friend = users_manager.add_user("friend", "pwd").unwrap();
+        let stranger = users_manager.add_user("stranger", "pwd").unwrap();
+        let kid = users_manager.add_dependent(mum, "kid", None).unwrap();
+        let sibling = users_manager.add_dependent(mum, "sibling", None).unwrap();
+
+        let friend_manager = FriendsManager::new(connection.clone()).unwrap();
+        WishlistManager::new(connection.clone()).unwrap();
+        friend_manager.create_friend_request(mum, "friend").unwrap();
+        friend_manager
+            .update_received_request(1, friend, RequestStatus::Accepted)
+            .unwrap();
+
+        assert!(friend_manager.is_my_friend(mum, kid).unwrap());
+        assert!(friend_manager.is_my_friend(kid, sibling).unwrap());
+        assert!(friend_manager.is_my_friend(friend, kid).unwrap());
+        assert!(friend_manager.is_my_friend(kid, friend).unwrap());
+        assert!(!friend_manager.is_my_friend(stranger, kid).unwrap());
+        assert!(!friend_manager.is_my_friend(kid, kid).unwrap());
+        //The kid does not see the wishlist of their guardian as a friend
+        assert!(!friend_manager.is_my_friend(kid, mum).unwrap());
+        assert_eq!(
+            friend_manager
+                .get_friends(friend)
+                .unwrap()
+                .into_iter()
+                .map(|user| user.id)
+                .collect::<Vec<_>>(),
+            vec![mum, kid, sibling]
+        );
+        assert_eq!(
+            friend_manager
+                .get_friends(kid)
+                .unwrap()
+                .into_iter()
+                .map(|user| user.id)
+                .collect::<Vec<_>>(),
+            vec![friend, sibling]
+        );
+
+        let error = friend_manager
+            .remove_friend(friend, kid, ReservationPolicy::Release)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            FriendsManagerError::DerivedFriendship(_, _)
+        ));
+        friend_manager.block_user(friend, "sibling").unwrap();
+        assert!(!friend_manager.is_my_friend(friend, sibling).unwrap());
+        assert!(!friend_manager.is_my_friend(sibling, friend).unwrap());
+        assert!(friend_manager.is_my_friend(friend, kid).unwrap());
+
+        friend_manager
+            .remove_friend(friend, mum, ReservationPolicy::Release)
+            .unwrap();
+        assert!(!friend_manager.is_my_friend(friend, kid).unwrap());
+    }
 }
@@ -0,0 +1,435 @@
+use crate::clock::Clock;
+use crate::managers::friends_manager::RequestStatus;
+use crate::managers::users_manager::{UsersManager, UsersManagerError};
+use chrono::Duration;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+/**
+Invitation links, making whoever redeems them a friend of the user who shared them
+**/
+#[derive(Clone)]
+pub struct InvitationsManager {
+    connection: Arc<Mutex<Connection>>,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub(crate) enum InvitationsManagerError {
+    Sqlite(#[from] rusqlite::Error),
+    UsersManager(#[from] UsersManagerError),
+    #[error("Unknown or expired invitation")]
+    UnknownInvitation,
+    #[error("{0} tried to redeem their own invitation")]
+    CannotRedeemYourInvitation(i64),
+}
+
+impl InvitationsManager {
+    pub fn new(
+        connection: Arc<Mutex<Connection>>,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, InvitationsManagerError> {
+        Self::init_database(&connection)?;
+        Ok(Self { connection, clock })
+    }
+
+    fn init_database(connection: &Arc<Mutex<Connection>>) -> Result<(), InvitationsManagerError> {
+        let connection = connection.lock().unwrap();
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS invitations (token TEXT PRIMARY KEY, userId INTEGER NOT NULL, expiresAt INTEGER NOT NULL, \
+            remainingUses INTEGER, FOREIGN KEY(userId) REFERENCES users(id))")?;
+        Ok(())
+    }
+
+    /**
+    Without `max_uses`, the invitation can be redeemed any number of times until it expires.
+    Dependents cannot invite, their friends coming from their guardians.
+    **/
+    pub fn create_invitation(
+        &self,
+        user_id: i64,
+        valid_days: u32,
+        max_uses: Option<u32>,
+    ) -> Result<Invitation, InvitationsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        if UsersManager::is_dependent(&connection, user_id)? {
+            return Err(UsersManagerError::DependentAccount(user_id.to_string()).into());
+        }
+        let invitation = Invitation {
+            token: rand::thread_rng()
+                .sample_iter(Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect::<String>(),
+            expires_at: (self.clock.now() + Duration::days(valid_days.into())).timestamp(),
+            remaining_uses: max_uses,
+        };
+        connection.execute(
+            "INSERT INTO invitations(token, userId, expiresAt, remainingUses) VALUES (?,?,?,?)",
+            params![
+                &invitation.token,
+                user_id,
+                invitation.expires_at,
+                invitation.remaining_uses
+            ],
+        )?;
+        Ok(invitation)
+    }
+
+    pub fn get_invitations(
+        &self,
+        user_id: i64,
+    ) -> Result<Vec<Invitation>, InvitationsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT token, expiresAt, remainingUses FROM invitations WHERE userId=? AND expiresAt>? \
+            AND (remainingUses IS NULL OR remainingUses>0) ORDER BY expiresAt",
+        )?;
+        let rows = statement.query_map(params![user_id, self.clock.now().timestamp()], |row| {
+            Ok(Invitation {
+                token: row.get(0)?,
+                expires_at: row.get(1)?,
+                remaining_uses: row.get(2)?,
+            })
+        })?;
+
+        let mut invitations = Vec::new();
+        for row in rows {
+            invitations.push(row?);
+        }
+        Ok(invitations)
+    }
+
+    pub fn revoke_invitation(
+        &self,
+        user_id: i64,
+        token: &str,
+    ) -> Result<(), InvitationsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let count = connection.execute(
+            "DELETE FROM invitations WHERE token=? AND userId=?",
+            params![token, user_id],
+        )?;
+        if count == 0 {
+            return Err(InvitationsManagerError::UnknownInvitation);
+        }
+        Ok(())
+    }
+
+    pub fn delete_invitations(&self, user_id: i64) -> Result<(), InvitationsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute("DELETE FROM invitations WHERE userId=?", params![user_id])?;
+        Ok(())
+    }
+
+    fn get_inviter(
+        connection: &Connection,
+        token: &str,
+        now: i64,
+    ) -> Result<i64, InvitationsManagerError> {
+        connection
+            .query_row(
+                "SELECT userId FROM invitations WHERE token=? AND expiresAt>? \
+                AND (remainingUses IS NULL OR remainingUses>0)",
+                params![token, now],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?
+            .ok_or(InvitationsManagerError::UnknownInvitation)
+    }
+
+    /**
+    Spend one use of the invitation, unless none is left or it expired
+    **/
+    fn use_invitation(
+        connection: &Connection,
+        token: &str,
+        now: i64,
+    ) -> Result<(), InvitationsManagerError> {
+        let count = connection.execute(
+            "UPDATE invitations SET remainingUses=remainingUses-1 WHERE token=? AND expiresAt>? \
+            AND (remainingUses IS NULL OR remainingUses>0)",
+            params![token, now],
+        )?;
+        if count != 1 {
+            return Err(InvitationsManagerError::UnknownInvitation);
+        }
+        Ok(())
+    }
+
+    /**
+    Create the user as a friend of the inviter. Nothing is created, and no use is spent, when the
+    invitation cannot be redeemed or the name is taken.
+    **/
+    pub fn sign_up_with(
+        &self,
+        token: &str,
+        name: &str,
+        password: &str,
+    ) -> Result<i64, InvitationsManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        let now = self.clock.now().timestamp();
+        let inviter = Self::get_inviter(&transaction, token, now)?;
+        Self::use_invitation(&transaction, token, now)?;
+        let user_id = UsersManager::static_add_user(&transaction, name, password)?;
+        transaction.execute(
+            "INSERT INTO friendRequests(userOne, userTwo, status) VALUES (?, ?, ?)",
+            params![inviter, user_id, RequestStatus::Accepted],
+        )?;
+        transaction.commit()?;
+
+        Ok(user_id)
+    }
+
+    /**
+    The user becomes a friend of the inviter, replacing any request between them. Invitations of
+    a user who blocked us, or whom we blocked, cannot be redeemed, nor by dependents. Friends of
+    the inviter keep their friendship without spending a use.
+    **/
+    pub fn redeem_invitation(
+        &self,
+        token: &str,
+        user_id: i64,
+    ) -> Result<i64, InvitationsManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        let inviter = Self::get_inviter(&transaction, token, self.clock.now().timestamp())?;
+        if inviter == user_id {
+            return Err(InvitationsManagerError::CannotRedeemYourInvitation(user_id));
+        }
+        if UsersManager::is_dependent(&transaction, user_id)? {
+            return Err(UsersManagerError::DependentAccount(user_id.to_string()).into());
+        }
+        let mut statement = transaction.prepare(
+            "SELECT 1 FROM blockedUsers WHERE (userId=?1 AND blockedId=?2) OR (userId=?2 AND blockedId=?1)",
+        )?;
+        if statement.exists(params![inviter, user_id])? {
+            return Err(InvitationsManagerError::UnknownInvitation);
+        }
+        drop(statement);
+        let mut statement = transaction.prepare(
+            "SELECT 1 FROM friendRequests WHERE ((userOne=?1 AND userTwo=?2) OR (userOne=?2 AND userTwo=?1)) AND status=?3",
+        )?;
+        if statement.exists(params![inviter, user_id, RequestStatus::Accepted])? {
+            return Ok(inviter);
+        }
+        drop(statement);
+
+        Self::use_invitation(&transaction, token, self.clock.now().timestamp())?;
+        transaction.execute(
+            "DELETE FROM friendRequests WHERE (userOne=?1 AND userTwo=?2) OR (userOne=?2 AND userTwo=?1)",
+            params![inviter, user_id],
+        )?;
+        transaction.execute(
+            "INSERT INTO friendRequests(userOne, userTwo, status) VALUES (?, ?, ?)",
+            params![inviter, user_id, RequestStatus::Accepted],
+        )?;
+        transaction.commit()?;
+
+        Ok(inviter)
+    }
+}
+
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct Invitation {
+    token: String,
+    expires_at: i64,
+    remaining_uses: Option<u32>,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::TestClock;
+    use crate::managers::friends_manager::FriendsManager;
+    use crate::managers::invitations_manager::{InvitationsManager, InvitationsManagerError};
+    use crate::managers::test_helper::create_test_database;
+    use crate::managers::users_manager::{UsersManager, UsersManagerError};
+    use crate::managers::wishlist_manager::WishlistManager;
+    use chrono::{Duration, TimeZone, Utc};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_redeem_invitation() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_redeem_invitation")));
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let three = users_manager.add_user("three", "pwd").unwrap();
+        let four = users_manager.add_user("four", "pwd").unwrap();
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+        WishlistManager::new(connection.clone()).unwrap();
+
+        let invitations_manager = InvitationsManager::new(connection, clock.clone()).unwrap();
+        let invitation = invitations_manager
+            .create_invitation(one, 7, Some(2))
+            .unwrap();
+        let token = invitation.token.clone();
+        assert_eq!(
+            invitations_manager.get_invitations(one).unwrap(),
+            vec![invitation]
+        );
+
+        let error = invitations_manager
+            .redeem_invitation(&token, one)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            InvitationsManagerError::CannotRedeemYourInvitation(_)
+        ));
+
+        //A pending request is replaced by the friendship
+        friends_manager.create_friend_request(two, "one").unwrap();
+        assert_eq!(
+            invitations_manager.redeem_invitation(&token, two).unwrap(),
+            one
+        );
+        assert!(friends_manager.is_my_friend(one, two).unwrap());
+        assert_eq!(friends_manager.get_friends(one).unwrap().len(), 1);
+
+        friends_manager.block_user(three, "one").unwrap();
+        let error = invitations_manager
+            .redeem_invitation(&token, three)
+            .unwrap_err();
+        assert!(matches!(error, InvitationsManagerError::UnknownInvitation));
+
+        invitations_manager.redeem_invitation(&token, four).unwrap();
+        assert!(friends_manager.is_my_friend(one, four).unwrap());
+        //All uses are spent
+        assert_eq!(invitations_manager.get_invitations(one).unwrap(), vec![]);
+        friends_manager.unblock_user(three, one).unwrap();
+        let error = invitations_manager
+            .redeem_invitation(&token, three)
+            .unwrap_err();
+        assert!(matches!(error, InvitationsManagerError::UnknownInvitation));
+        assert!(!friends_manager.is_my_friend(one, three).unwrap());
+    }
+
+    #[test]
+    fn test_sign_up_with() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_sign_up_with")));
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+
+        let invitations_manager = InvitationsManager::new(connection, clock.clone()).unwrap();
+        let token = invitations_manager
+            .create_invitation(one, 7, Some(2))
+            .unwrap()
+            .token;
+
+        //A taken name does not spend the invitation
+        let error = invitations_manager
+            .sign_up_with(&token, "one", "pwd")
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            InvitationsManagerError::UsersManager(UsersManagerError::UserAlreadyExist(_))
+        ));
+
+        let two = invitations_manager
+            .sign_up_with(&token, "two", "pwd")
+            .unwrap();
+        assert_eq!(users_manager.get_user("two").unwrap().id, two);
+        assert!(friends_manager.is_my_friend(one, two).unwrap());
+        //Already friends, the invitation is not spent again
+        assert_eq!(
+            invitations_manager.redeem_invitation(&token, two).unwrap(),
+            one
+        );
+
+        invitations_manager
+            .sign_up_with(&token, "three", "pwd")
+            .unwrap();
+        let error = invitations_manager
+            .sign_up_with(&token, "four", "pwd")
+            .unwrap_err();
+        assert!(matches!(error, InvitationsManagerError::UnknownInvitation));
+        assert!(users_manager.get_user("four").is_err());
+    }
+
+    #[test]
+    fn test_dependent_invitations() {
+        let connection = Arc::new(Mutex::new(create_test_database(
+            "test_dependent_invitations",
+        )));
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let kid = users_manager.add_dependent(two, "kid", None).unwrap();
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+
+        let invitations_manager = InvitationsManager::new(connection, clock.clone()).unwrap();
+        let error = invitations_manager
+            .create_invitation(kid, 7, None)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            InvitationsManagerError::UsersManager(UsersManagerError::DependentAccount(_))
+        ));
+
+        let token = invitations_manager
+            .create_invitation(one, 7, Some(1))
+            .unwrap()
+            .token;
+        let error = invitations_manager
+            .redeem_invitation(&token, kid)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            InvitationsManagerError::UsersManager(UsersManagerError::DependentAccount(_))
+        ));
+        assert!(!friends_manager.is_my_friend(one, kid).unwrap());
+        assert_eq!(invitations_manager.get_invitations(one).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_invitation_expiry() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_invitation_expiry")));
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        FriendsManager::new(connection.clone()).unwrap();
+
+        let invitations_manager = InvitationsManager::new(connection, clock.clone()).unwrap();
+        let unlimited = invitations_manager.create_invitation(one, 1, None).unwrap();
+        let revoked = invitations_manager.create_invitation(one, 1, None).unwrap();
+        invitations_manager
+            .revoke_invitation(one, &revoked.token)
+            .unwrap();
+        let error = invitations_manager
+            .revoke_invitation(two, &unlimited.token)
+            .unwrap_err();
+        assert!(matches!(error, InvitationsManagerError::UnknownInvitation));
+        let error = invitations_manager
+            .redeem_invitation(&revoked.token, two)
+            .unwrap_err();
+        assert!(matches!(error, InvitationsManagerError::UnknownInvitation));
+        assert_eq!(invitations_manager.get_invitations(one).unwrap().len(), 1);
+
+        clock.advance(Duration::days(1));
+        let error = invitations_manager
+            .redeem_invitation(&unlimited.token, two)
+            .unwrap_err();
+        assert!(matches!(error, InvitationsManagerError::UnknownInvitation));
+        assert_eq!(invitations_manager.get_invitations(one).unwrap(), vec![]);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::clock::Clock;
+use crate::managers::reminders_manager::{Digest, Notifier, NotifierError};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+/**
+In-app notifications, shown to the user the next time they open the application
+**/
+#[derive(Clone)]
+pub struct NotificationsManager {
+    connection: Arc<Mutex<Connection>>,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub(crate) enum NotificationsManagerError {
+    Sqlite(#[from] rusqlite::Error),
+    Json(#[from] serde_json::Error),
+    #[error("Unknown notification {0}")]
+    UnknownNotification(i64),
+}
+
+impl NotificationsManager {
+    pub fn new(
+        connection: Arc<Mutex<Connection>>,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, NotificationsManagerError> {
+        Self::init_database(&connection)?;
+        Ok(Self { connection, clock })
+    }
+
+    fn init_database(connection: &Arc<Mutex<Connection>>) -> Result<(), NotificationsManagerError> {
+        let connection = connection.lock().unwrap();
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS notifications (id INTEGER PRIMARY KEY AUTOINCREMENT, userId INTEGER NOT NULL, \
+            createdAt INTEGER NOT NULL, digest TEXT NOT NULL, FOREIGN KEY(userId) REFERENCES users(id))")?;
+        Ok(())
+    }
+
+    pub fn add_notification(
+        &self,
+        user_id: i64,
+        digest: &Digest,
+    ) -> Result<(), NotificationsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO notifications(userId, createdAt, digest) VALUES (?,?,?)",
+            params![
+                user_id,
+                self.clock.now().timestamp(),
+                serde_json::to_string(digest)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_notifications(
+        &self,
+        user_id: i64,
+    ) -> Result<Vec<Notification>, NotificationsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT id, createdAt, digest FROM notifications WHERE userId=? ORDER BY createdAt DESC, id DESC",
+        )?;
+        let rows =
+            statement.query_map(params![user_id], |row| <(i64, i64, String)>::try_from(row))?;
+
+        let mut notifications = Vec::new();
+        for row in rows {
+            let (id, created_at, digest) = row?;
+            notifications.push(Notification {
+                id,
+                created_at,
+                digest: serde_json::from_str(&digest)?,
+            });
+        }
+        Ok(notifications)
+    }
+
+    pub fn delete_notifications(&self, user_id: i64) -> Result<(), NotificationsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute("DELETE FROM notifications WHERE userId=?", params![user_id])?;
+        Ok(())
+    }
+
+    pub fn delete_notification(
+        &self,
+        user_id: i64,
+        notification_id: i64,
+    ) -> Result<(), NotificationsManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let deleted = connection.execute(
+            "DELETE FROM notifications WHERE id=? AND userId=?",
+            params![notification_id, user_id],
+        )?;
+        if deleted == 0 {
+            return Err(NotificationsManagerError::UnknownNotification(
+                notification_id,
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for NotificationsManager {
+    fn notify<'a>(
+        &'a self,
+        user_id: i64,
+        digest: &'a Digest,
+    ) -> BoxFuture<'a, Result<(), NotifierError>> {
+        let result = self
+            .add_notification(user_id, digest)
+            .map_err(NotifierError::from);
+        futures_util::future::ready(result).boxed()
+    }
+}
+
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct Notification {
+    pub id: i64,
+    pub created_at: i64,
+    pub digest: Digest,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::TestClock;
+    use crate::managers::events_manager::{EventGift, EventKind};
+    use crate::managers::notifications_manager::{
+        Notification, NotificationsManager, NotificationsManagerError,
+    };
+    use crate::managers::reminders_manager::Digest;
+    use crate::managers::test_helper::create_test_database;
+    use crate::managers::users_manager::UsersManager;
+    use chrono::{TimeZone, Utc};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_notifications() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_notifications")));
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 12, 18, 8, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+
+        let notifications_manager = NotificationsManager::new(connection, clock).unwrap();
+        let digest = Digest {
+            kind: EventKind::Christmas,
+            date: 1735084800,
+            friend_id: None,
+            name: None,
+            gifts: vec![EventGift {
+                id: 1,
+                friend_id: two,
+                name: "Gift".to_string(),
+                free: true,
+            }],
+        };
+        notifications_manager
+            .add_notification(one, &digest)
+            .unwrap();
+
+        assert_eq!(
+            notifications_manager.get_notifications(one).unwrap(),
+            vec![Notification {
+                id: 1,
+                created_at: 1734508800,
+                digest
+            }]
+        );
+        assert_eq!(
+            notifications_manager.get_notifications(two).unwrap(),
+            vec![]
+        );
+
+        let error = notifications_manager
+            .delete_notification(two, 1)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            NotificationsManagerError::UnknownNotification(1)
+        ));
+        notifications_manager.delete_notification(one, 1).unwrap();
+        assert_eq!(
+            notifications_manager.get_notifications(one).unwrap(),
+            vec![]
+        );
+    }
+}
@@ -1,359 +1,1338 @@
-use std::path::PathBuf;
-use std::sync::Arc;
-use printpdf::{Actions, BorderArray, Color, ColorArray, FontId, HighlightingMode, LinePoint, LinkAnnotation, Mm, Op, PaintMode, PdfDocument, PdfPage, PdfSaveOptions, Point, Polygon, PolygonRing, Pt, RawImage, Rect, Rgb, Svg, TextAlign, TextShapingOptions, WindingOrder, XObjectTransform};
-use crate::configuration::Configuration;
-use crate::managers::wishlist_manager::WishList;
-use crate::routes::files::resize_file;
-
-const PAGE_WIDTH: Mm = Mm(525.0);
-const PAGE_HEIGHT: Mm = Mm(286.0);
+use crate::managers::events_manager::EventKind;
+use crate::managers::wishlist_manager::{FriendWishList, WishList};
+use printpdf::{
+    Actions, BorderArray, Color, ColorArray, FontId, HighlightingMode, Line, LinePoint,
+    LinkAnnotation, Mm, Op, PaintMode, PdfDocument, PdfPage, PdfSaveOptions, Point, Polygon,
+    PolygonRing, Pt, RawImage, Rect, Rgb, Svg, TextAlign, TextShapingOptions, WindingOrder,
+    XObjectId, XObjectTransform,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::debug;
+use unicode_segmentation::UnicodeSegmentation;
+
 const GIFT_SIZE_PXL: usize = 880; //Pixels
-const GIFT_SIZE_MM: Mm = Mm(74.5);
-const PRICE_TAG_DIAMETER: Mm = Mm(20.0);
-const POSITIONS: [(Mm, Mm); 5] = [
-    (Mm(25.0), Mm(150.0)),
-    (Mm(125.0), Mm(150.0)),
-    (Mm(225.0), Mm(150.0)),
-    (Mm(325.0), Mm(150.0)),
-    (Mm(425.0), Mm(150.0)),
-];
-
-pub fn get_pdf(wishlist: WishList, configuration: Arc<Configuration>) -> Vec<u8> {
-    let mut doc = PdfDocument::new("Wishlist");
+const MARGIN: Mm = Mm(15.0);
+/** Room for the category name on top of each page **/
+const HEADER: Mm = Mm(25.0);
+/** Space around each gift in its cell **/
+const GAP: Mm = Mm(5.0);
+const LIST_ROW_HEIGHT: Mm = Mm(18.0);
+const MAX_COLUMNS: u32 = 8;
+const MAX_ROWS: u32 = 8;
+const MAX_LIST_ROWS: u32 = 40;
+/**
+Average width of a character relative to the font size, a bit more than Roboto's, for the texts
+that cannot be shaped
+**/
+const CHARACTER_WIDTH: f32 = 0.55;
+const LINE_HEIGHT: f32 = 1.2;
+/** Category names are made smaller to fit, down to this scale before being ellipsized **/
+const MIN_CATEGORY_NAME_SCALE: f32 = 0.6;
 
-    let roboto_regular_font_id = get_font(
-        include_bytes!("../../resource/Roboto-Regular.ttf"),
-        &mut doc,
-    );
-    let roboto_bold_font_id =
-        get_font(include_bytes!("../../resource/Roboto-Bold.ttf"), &mut doc);
-
-    let category_name_options = TextShapingOptions {
-        font_size: Pt(36.0),
-        max_width: Some(PAGE_WIDTH.into_pt()),
-        align: TextAlign::Center,
-        ..Default::default()
-    };
+/**
+Gift cards are drawn in a 85 x 200 mm box, scaled down to fit the cells of the grid
+**/
+const CARD_WIDTH: f32 = 85.0;
+const CARD_HEIGHT: f32 = 200.0;
+const CARD_PICTURE: (f32, f32) = (5.0, 120.0);
+const CARD_PICTURE_SIZE: f32 = 74.5;
+const CARD_PRICE_TAG_DIAMETER: f32 = 20.0;
 
-    let gift_name_options = TextShapingOptions {
-        font_size: Pt(18.0),
-        max_width: Some(GIFT_SIZE_MM.into_pt()),
-        align: TextAlign::Center,
-        ..Default::default()
-    };
+static ROBOTO_REGULAR: &[u8] = include_bytes!("../../resource/Roboto-Regular.ttf");
+static ROBOTO_BOLD: &[u8] = include_bytes!("../../resource/Roboto-Bold.ttf");
 
-    let gift_description_options = TextShapingOptions {
-        font_size: Pt(12.0),
-        max_width: Some(GIFT_SIZE_MM.into_pt()),
-        align: TextAlign::Center,
-        ..Default::default()
-    };
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum PdfError {
+    #[error("Cannot parse font")]
+    Font,
+    #[error("Cannot parse SVG: {0}")]
+    Svg(String),
+    #[error("Cannot decode image: {0}")]
+    Image(String),
+}
 
-    let gift_price_options = TextShapingOptions {
-        font_size: Pt(14.0),
-        max_width: Some(PRICE_TAG_DIAMETER.into_pt()),
-        align: TextAlign::Center,
-        ..Default::default()
-    };
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum PaperSize {
+    /** The original 525 x 286 mm page **/
+    #[default]
+    Banner,
+    A4,
+    Letter,
+}
+
+impl PaperSize {
+    /**
+    Width and height in portrait
+    **/
+    fn dimensions(&self) -> (Mm, Mm) {
+        match self {
+            PaperSize::Banner => (Mm(286.0), Mm(525.0)),
+            PaperSize::A4 => (Mm(210.0), Mm(297.0)),
+            PaperSize::Letter => (Mm(215.9), Mm(279.4)),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutStyle {
+    /** Gift cards with their picture **/
+    #[default]
+    Grid,
+    /** One line per gift, without picture **/
+    List,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(test, derive(Debug))]
+pub struct Layout {
+    pub paper: PaperSize,
+    pub orientation: Orientation,
+    pub style: LayoutStyle,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl Layout {
+    /**
+    Missing values depend on the paper, the banner being in landscape with five gifts per page
+    and other papers in portrait with six gifts per page
+    **/
+    pub fn new(
+        paper: Option<PaperSize>,
+        orientation: Option<Orientation>,
+        style: Option<LayoutStyle>,
+        columns: Option<u32>,
+        rows: Option<u32>,
+    ) -> Self {
+        let paper = paper.unwrap_or_default();
+        let orientation = orientation.unwrap_or(match paper {
+            PaperSize::Banner => Orientation::Landscape,
+            _ => Orientation::Portrait,
+        });
+        let style = style.unwrap_or_default();
+        let mut layout = Self {
+            paper,
+            orientation,
+            style,
+            columns: 1,
+            rows: 1,
+        };
+        let (default_columns, default_rows, max_rows) = match (style, paper, orientation) {
+            (LayoutStyle::List, _, _) => (1, layout.list_rows(), MAX_LIST_ROWS),
+            (LayoutStyle::Grid, PaperSize::Banner, Orientation::Landscape) => (5, 1, MAX_ROWS),
+            (LayoutStyle::Grid, _, Orientation::Landscape) => (4, 1, MAX_ROWS),
+            (LayoutStyle::Grid, _, Orientation::Portrait) => (3, 2, MAX_ROWS),
+        };
+        layout.columns = columns.unwrap_or(default_columns).clamp(1, MAX_COLUMNS);
+        layout.rows = rows.unwrap_or(default_rows).clamp(1, max_rows);
+        layout
+    }
+
+    pub fn page_size(&self) -> (Mm, Mm) {
+        let (width, height) = self.paper.dimensions();
+        match self.orientation {
+            Orientation::Portrait => (width, height),
+            Orientation::Landscape => (height, width),
+        }
+    }
+
+    fn list_rows(&self) -> u32 {
+        let (_, height) = self.page_size();
+        ((height - HEADER - MARGIN).0 / LIST_ROW_HEIGHT.0) as u32
+    }
+
+    /**
+    The cells of a page, from the top left one, row after row
+    **/
+    pub fn cells(&self) -> Vec<Cell> {
+        let (width, height) = self.page_size();
+        let cell_width = (width - MARGIN - MARGIN) / self.columns as f32;
+        let cell_height = (height - HEADER - MARGIN) / self.rows as f32;
+        let top = height - HEADER;
+        (0..self.rows)
+            .flat_map(|row| {
+                (0..self.columns).map(move |column| Cell {
+                    x: MARGIN + cell_width * column as f32,
+                    y: top - cell_height * (row + 1) as f32,
+                    width: cell_width,
+                    height: cell_height,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::new(None, None, None, None, None)
+    }
+}
+
+/**
+A rectangle of the page, `y` being its bottom as PDF coordinates go up
+**/
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub struct Cell {
+    pub x: Mm,
+    pub y: Mm,
+    pub width: Mm,
+    pub height: Mm,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Classic,
+    Festive,
+    Minimal,
+}
+
+impl Theme {
+    fn title_font(&self) -> &'static [u8] {
+        match self {
+            Theme::Classic | Theme::Festive => ROBOTO_BOLD,
+            Theme::Minimal => ROBOTO_REGULAR,
+        }
+    }
+
+    fn text_font(&self) -> &'static [u8] {
+        ROBOTO_REGULAR
+    }
+
+    /** Price tags, separators and title page band **/
+    fn accent(&self) -> Color {
+        let (r, g, b) = match self {
+            Theme::Classic => (0.86, 0.86, 0.86),
+            Theme::Festive => (0.95, 0.68, 0.68),
+            Theme::Minimal => (0.93, 0.93, 0.93),
+        };
+        Color::Rgb(Rgb {
+            r,
+            g,
+            b,
+            icc_profile: None,
+        })
+    }
+
+    fn frame(&self) -> Option<Color> {
+        match self {
+            Theme::Classic => Some(Color::Rgb(Rgb {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                icc_profile: None,
+            })),
+            Theme::Festive => Some(Color::Rgb(Rgb {
+                r: 0.7,
+                g: 0.1,
+                b: 0.15,
+                icc_profile: None,
+            })),
+            Theme::Minimal => None,
+        }
+    }
+
+    pub fn title_page(&self) -> bool {
+        *self == Theme::Festive
+    }
+}
+
+/**
+What is printed, either our own wishlist or the one of a friend with its reservations and
+secret gifts
+**/
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct PdfWishList {
+    pub categories: Vec<PdfCategory>,
+}
+
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct PdfCategory {
+    pub name: String,
+    pub gifts: Vec<PdfGift>,
+}
+
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct PdfGift {
+    pub name: String,
+    pub description: Option<String>,
+    pub price: Option<String>,
+    pub where_to_buy: Option<String>,
+    pub picture: Option<String>,
+    pub heart: bool,
+    pub secret: bool,
+    pub reservation: Option<Reservation>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Reservation {
+    ByMe,
+    ByOther,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    Fr,
+}
+
+impl Language {
+    fn reservation(&self, reservation: Reservation) -> &'static str {
+        match (self, reservation) {
+            (Language::En, Reservation::ByMe) => "Reserved by you",
+            (Language::En, Reservation::ByOther) => "Taken",
+            (Language::Fr, Reservation::ByMe) => "Réservé par vous",
+            (Language::Fr, Reservation::ByOther) => "Déjà pris",
+        }
+    }
 
-    let heart = Svg::parse(include_str!("../../resource/heart.svg"), &mut Vec::new()).unwrap();
+    fn secret(&self) -> &'static str {
+        match self {
+            Language::En => "Secret",
+            Language::Fr => "Secret",
+        }
+    }
+
+    fn event(&self, event: EventKind) -> &'static str {
+        match (self, event) {
+            (Language::En, EventKind::Birthday) => "Birthday",
+            (Language::En, EventKind::Christmas) => "Christmas",
+            (Language::Fr, EventKind::Birthday) => "Anniversaire",
+            (Language::Fr, EventKind::Christmas) => "Noël",
+        }
+    }
+}
+
+impl From<WishList> for PdfWishList {
+    fn from(wishlist: WishList) -> Self {
+        Self {
+            categories: wishlist
+                .categories
+                .into_iter()
+                .map(|category| PdfCategory {
+                    name: category.name,
+                    gifts: category
+                        .gifts
+                        .into_iter()
+                        .map(|gift| PdfGift {
+                            name: gift.name,
+                            description: gift.description,
+                            price: gift.price,
+                            where_to_buy: gift.where_to_buy,
+                            picture: gift.picture,
+                            heart: gift.heart,
+                            secret: false,
+                            reservation: None,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl PdfWishList {
+    /**
+    The wishlist of a friend, as seen by the user
+    **/
+    pub fn from_friend_wishlist(wishlist: FriendWishList, user_id: i64) -> Self {
+        Self {
+            categories: wishlist
+                .categories
+                .into_iter()
+                .map(|category| PdfCategory {
+                    name: category.name,
+                    gifts: category
+                        .gifts
+                        .into_iter()
+                        .map(|gift| PdfGift {
+                            name: gift.name,
+                            description: gift.description,
+                            price: gift.price,
+                            where_to_buy: gift.where_to_buy,
+                            picture: gift.picture,
+                            heart: gift.heart,
+                            secret: gift.secret,
+                            reservation: gift.reserved_by.map(|reserved_by| {
+                                if reserved_by == user_id {
+                                    Reservation::ByMe
+                                } else {
+                                    Reservation::ByOther
+                                }
+                            }),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+pub struct PdfOptions {
+    /** Shown on the title page, usually the name of the wishlist owner **/
+    pub title: String,
+    pub event: Option<EventKind>,
+    pub layout: Layout,
+    pub theme: Theme,
+    pub title_page: bool,
+    /** Of the labels added to the gifts and the title page **/
+    pub language: Language,
+}
+
+/**
+Pictures are given by name, in their print size, gifts being shown with their cover. A picture
+that cannot be decoded is replaced by the blank one, and a text that cannot be shaped is left out.
+**/
+pub fn get_pdf(
+    wishlist: PdfWishList,
+    pictures: &HashMap<String, Vec<u8>>,
+    options: &PdfOptions,
+) -> Result<Vec<u8>, PdfError> {
+    let mut doc = PdfDocument::new("Wishlist");
+
+    let title_font_id = get_font(options.theme.title_font(), &mut doc)?;
+    let text_font_id = get_font(options.theme.text_font(), &mut doc)?;
+
+    let heart = Svg::parse(include_str!("../../resource/heart.svg"), &mut Vec::new())
+        .map_err(|error| PdfError::Svg(error.to_string()))?;
     let heart_id = doc.add_xobject(&heart);
 
     let content = include_bytes!("../../front-vue/src/assets/images/blank_profile_picture.png");
-    let blank_gift = RawImage::decode_from_bytes(content, &mut Vec::new()).unwrap();
+    let blank_gift = RawImage::decode_from_bytes(content, &mut Vec::new())
+        .map_err(|error| PdfError::Image(error.to_string()))?;
 
+    let mut drawer = Drawer {
+        doc,
+        title_font_id,
+        text_font_id,
+        heart_id,
+        blank_gift,
+        pictures,
+        theme: options.theme,
+        language: options.language,
+    };
+
+    let (page_width, page_height) = options.layout.page_size();
+    let cells = options.layout.cells();
     let mut pages = Vec::new();
+    if options.title_page {
+        pages.push(PdfPage::new(
+            page_width,
+            page_height,
+            drawer.title_page(options),
+        ));
+    }
     for category in wishlist.categories {
         let mut ops = Vec::new();
         for (index, gift) in category.gifts.into_iter().enumerate() {
-            if index % 5 == 0 {
+            if index % cells.len() == 0 {
                 if !ops.is_empty() {
-                    let page = PdfPage::new(PAGE_WIDTH, PAGE_HEIGHT, ops);
+                    let page = PdfPage::new(page_width, page_height, ops);
                     ops = Vec::new();
                     pages.push(page);
                 }
+                ops.extend(drawer.category_name(&category.name, page_width, page_height));
+            }
 
-                let shaped_text = doc
-                    .shape_text(&category.name, &roboto_bold_font_id, &category_name_options)
-                    .unwrap();
-                let origin = Point { x: Pt(0.0), y: (PAGE_HEIGHT - Mm(10.0)).into_pt() };
-                ops.extend(shaped_text.get_ops(origin));
+            let cell = cells[index % cells.len()];
+            match options.layout.style {
+                LayoutStyle::Grid => ops.extend(drawer.gift_card(gift, cell)),
+                LayoutStyle::List => ops.extend(drawer.gift_line(gift, cell)),
             }
+        }
 
-            let image = if let Some(picture) = gift.picture {
-                let root_path = PathBuf::from(&configuration.upload_file_storage);
-                let root_output_path = root_path.parent().unwrap().to_path_buf().join("tmp_rs");
-                let original_file = root_path.join(picture);
-                if let Some(output_file) = resize_file(&original_file, &root_output_path) {
-                    let content = std::fs::read(output_file).unwrap();
-                    RawImage::decode_from_bytes(&content, &mut Vec::new()).unwrap()
-                } else {
-                    blank_gift.clone()
-                }
-            } else {
-                blank_gift.clone()
-            };
+        if !ops.is_empty() {
+            let page = PdfPage::new(page_width, page_height, ops);
+            pages.push(page);
+        }
+    }
 
-            let scale_height = GIFT_SIZE_PXL as f32 / image.height as f32;
-            let scale_width = GIFT_SIZE_PXL as f32 / image.width as f32;
-            let scale = if scale_height > scale_width {
-                scale_width
-            } else {
-                scale_height
-            };
-            let add_x = ((GIFT_SIZE_PXL as f32 - image.width as f32 * scale) / 2.0)
-                * (GIFT_SIZE_MM.0 / GIFT_SIZE_PXL as f32);
-            let add_y = ((GIFT_SIZE_PXL as f32 - image.height as f32 * scale) / 2.0)
-                * (GIFT_SIZE_MM.0 / GIFT_SIZE_PXL as f32);
-
-            let (x, y) = POSITIONS[index % 5];
-            ops.push(Op::UseXobject {
-                id: doc.add_image(&image),
-                transform: XObjectTransform {
-                    translate_x: Some((x + Mm(add_x)).into_pt()),
-                    translate_y: Some((y + Mm(add_y)).into_pt()),
-                    scale_x: Some(scale),
-                    scale_y: Some(scale),
-                    ..Default::default()
+    Ok(drawer
+        .doc
+        .with_pages(pages)
+        .save(&PdfSaveOptions::default(), &mut Vec::new()))
+}
+
+struct Drawer<'a> {
+    doc: PdfDocument,
+    title_font_id: FontId,
+    text_font_id: FontId,
+    heart_id: XObjectId,
+    blank_gift: RawImage,
+    pictures: &'a HashMap<String, Vec<u8>>,
+    theme: Theme,
+    language: Language,
+}
+
+/**
+A text drawn in a box, from its top
+**/
+struct TextBox<'a> {
+    font_id: &'a FontId,
+    font_size: Pt,
+    x: Mm,
+    top: Mm,
+    width: Mm,
+    align: TextAlign,
+    max_lines: usize,
+}
+
+impl Drawer<'_> {
+    fn title_page(&mut self, options: &PdfOptions) -> Vec<Op> {
+        let (width, height) = options.layout.page_size();
+        let mut ops = vec![
+            Op::SaveGraphicsState,
+            Op::SetFillColor {
+                col: self.theme.accent(),
+            },
+            Op::DrawPolygon {
+                polygon: rectangle(Mm(0.0), height * 0.4, width, height * 0.2, PaintMode::Fill),
+            },
+            Op::RestoreGraphicsState,
+        ];
+        let title_font_id = self.title_font_id.clone();
+        ops.extend(self.text(
+            &options.title,
+            TextBox {
+                font_id: &title_font_id,
+                font_size: Pt(48.0),
+                x: Mm(0.0),
+                top: height * 0.58,
+                width,
+                align: TextAlign::Center,
+                max_lines: 2,
+            },
+        ));
+
+        if let Some(event) = options.event {
+            let event = self.language.event(event);
+            let text_font_id = self.text_font_id.clone();
+            ops.extend(self.text(
+                event,
+                TextBox {
+                    font_id: &text_font_id,
+                    font_size: Pt(24.0),
+                    x: Mm(0.0),
+                    top: height * 0.46,
+                    width,
+                    align: TextAlign::Center,
+                    max_lines: 1,
                 },
+            ));
+        }
+        ops
+    }
+
+    /**
+    The name is made smaller to fit on its line, down to a minimum size
+    **/
+    fn category_name(&mut self, name: &str, page_width: Mm, page_height: Mm) -> Vec<Op> {
+        //36pt on the banner, smaller on narrower pages
+        let font_size = (36.0 * page_width.0 / 400.0).min(36.0);
+        let width = page_width - MARGIN - MARGIN;
+        let title_font_id = self.title_font_id.clone();
+        let name_width = self.measure(name, &title_font_id, Pt(font_size));
+        let fitting_size = font_size * width.into_pt().0 / name_width.0;
+        let font_size = Pt(fitting_size.clamp(font_size * MIN_CATEGORY_NAME_SCALE, font_size));
+        self.text(
+            name,
+            TextBox {
+                font_id: &title_font_id,
+                font_size,
+                x: MARGIN,
+                top: page_height - Mm(10.0),
+                width,
+                align: TextAlign::Center,
+                max_lines: 1,
+            },
+        )
+    }
+
+    /**
+    The card is drawn at its original size, then scaled down and centered in the cell
+    **/
+    fn gift_card(&mut self, gift: PdfGift, cell: Cell) -> Vec<Op> {
+        let scale = ((cell.width - GAP - GAP).0 / CARD_WIDTH)
+            .min((cell.height - GAP - GAP).0 / CARD_HEIGHT);
+        let left = cell.x + (cell.width - Mm(CARD_WIDTH * scale)) / 2.0;
+        let bottom = cell.y + (cell.height - Mm(CARD_HEIGHT * scale)) / 2.0;
+        let at = |x: f32, y: f32| (left + Mm(x * scale), bottom + Mm(y * scale));
+        let title_font_id = self.title_font_id.clone();
+        let text_font_id = self.text_font_id.clone();
+        let mut ops = Vec::new();
+
+        let image = self.picture(gift.picture.as_deref());
+        let picture_size = CARD_PICTURE_SIZE * scale;
+        let image_scale = (GIFT_SIZE_PXL as f32 / image.height.max(1) as f32)
+            .min(GIFT_SIZE_PXL as f32 / image.width.max(1) as f32);
+        let add_x = ((GIFT_SIZE_PXL as f32 - image.width as f32 * image_scale) / 2.0)
+            * (picture_size / GIFT_SIZE_PXL as f32);
+        let add_y = ((GIFT_SIZE_PXL as f32 - image.height as f32 * image_scale) / 2.0)
+            * (picture_size / GIFT_SIZE_PXL as f32);
+        let (x, y) = at(CARD_PICTURE.0, CARD_PICTURE.1);
+        ops.push(Op::UseXobject {
+            id: self.doc.add_image(&image),
+            transform: XObjectTransform {
+                translate_x: Some((x + Mm(add_x)).into_pt()),
+                translate_y: Some((y + Mm(add_y)).into_pt()),
+                scale_x: Some(image_scale * picture_size / CARD_PICTURE_SIZE),
+                scale_y: Some(image_scale * picture_size / CARD_PICTURE_SIZE),
+                ..Default::default()
+            },
+        });
+
+        if let Some(reservation) = gift.reservation {
+            let (x, y) = at(CARD_PICTURE.0, CARD_PICTURE.1);
+            ops.push(Op::SaveGraphicsState);
+            ops.push(Op::SetFillColor { col: grey() });
+            ops.push(Op::DrawPolygon {
+                polygon: rectangle(x, y, Mm(picture_size), Mm(12.0 * scale), PaintMode::Fill),
             });
+            ops.push(Op::RestoreGraphicsState);
+            let (x, top) = at(CARD_PICTURE.0, CARD_PICTURE.1 + 9.0);
+            ops.extend(self.text(
+                self.language.reservation(reservation),
+                TextBox {
+                    font_id: &title_font_id,
+                    font_size: Pt(16.0 * scale),
+                    x,
+                    top,
+                    width: Mm(picture_size),
+                    align: TextAlign::Center,
+                    max_lines: 1,
+                },
+            ));
+        }
+
+        if gift.secret {
+            let (x, top) = at(CARD_PICTURE.0, CARD_HEIGHT - 1.0);
+            ops.extend(self.text(
+                self.language.secret(),
+                TextBox {
+                    font_id: &text_font_id,
+                    font_size: Pt(9.0 * scale),
+                    x,
+                    top,
+                    width: Mm(picture_size),
+                    align: TextAlign::Left,
+                    max_lines: 1,
+                },
+            ));
+        }
+
+        if let Some(frame) = self.theme.frame() {
+            ops.push(Op::SaveGraphicsState);
+            ops.push(Op::SetOutlineColor { col: frame });
             ops.push(Op::DrawPolygon {
-                polygon: Polygon {
-                    rings: vec![PolygonRing {
-                        points: vec![
-                            LinePoint {
-                                p: Point {
-                                    x: (x - Mm(5.0)).into_pt(),
-                                    y: (y - Mm(120.0)).into_pt(),
-                                },
-                                bezier: false,
-                            },
-                            LinePoint {
-                                p: Point {
-                                    x: (x + Mm(80.0)).into_pt(),
-                                    y: (y - Mm(120.0)).into_pt(),
-                                },
-                                bezier: false,
-                            },
-                            LinePoint {
-                                p: Point {
-                                    x: (x + Mm(80.0)).into_pt(),
-                                    y: (y + Mm(80.0)).into_pt(),
-                                },
-                                bezier: false,
-                            },
-                            LinePoint {
-                                p: Point {
-                                    x: (x - Mm(5.0)).into_pt(),
-                                    y: (y + Mm(80.0)).into_pt(),
-                                },
-                                bezier: false,
-                            },
-                        ],
-                    }],
-                    mode: PaintMode::Stroke,
-                    winding_order: WindingOrder::NonZero,
+                polygon: rectangle(
+                    left,
+                    bottom,
+                    Mm(CARD_WIDTH * scale),
+                    Mm(CARD_HEIGHT * scale),
+                    PaintMode::Stroke,
+                ),
+            });
+            ops.push(Op::RestoreGraphicsState);
+        }
+
+        let (x, top) = at(CARD_PICTURE.0, CARD_PICTURE.1 - 10.0);
+        ops.extend(self.text(
+            &gift.name,
+            TextBox {
+                font_id: &text_font_id,
+                font_size: Pt(18.0 * scale),
+                x,
+                top,
+                width: Mm(picture_size),
+                align: TextAlign::Center,
+                max_lines: 2,
+            },
+        ));
+
+        if let Some(description) = gift.description {
+            let (x, top) = at(CARD_PICTURE.0, CARD_PICTURE.1 - 30.0);
+            //Down to the bottom of the card
+            let font_size = Pt(12.0 * scale);
+            let max_lines = ((top - bottom).into_pt().0 / (font_size.0 * LINE_HEIGHT)) as usize;
+            ops.extend(self.text(
+                &description,
+                TextBox {
+                    font_id: &text_font_id,
+                    font_size,
+                    x,
+                    top,
+                    width: Mm(picture_size),
+                    align: TextAlign::Center,
+                    max_lines,
                 },
+            ));
+        }
+
+        if let Some(price) = gift.price {
+            let radius = Mm(CARD_PRICE_TAG_DIAMETER * scale / 2.0);
+            let corner = CARD_PICTURE.0 + CARD_PICTURE_SIZE + 4.5 - CARD_PRICE_TAG_DIAMETER / 2.0;
+            let (x, y) = at(
+                corner,
+                CARD_PICTURE.1 + CARD_PICTURE_SIZE + 4.5 - CARD_PRICE_TAG_DIAMETER / 2.0,
+            );
+            ops.push(Op::SaveGraphicsState);
+            ops.push(Op::SetFillColor {
+                col: self.theme.accent(),
+            });
+            ops.push(Op::DrawPolygon {
+                polygon: circle(x, y, radius),
             });
+            ops.push(Op::RestoreGraphicsState);
 
-            let shaped_text = doc
-                .shape_text(&gift.name, &roboto_regular_font_id, &gift_name_options)
-                .unwrap();
+            ops.extend(self.text(
+                &price,
+                TextBox {
+                    font_id: &text_font_id,
+                    font_size: Pt(14.0 * scale),
+                    x: x - radius,
+                    top: y + Mm(3.5 * scale),
+                    width: radius + radius,
+                    align: TextAlign::Center,
+                    max_lines: 1,
+                },
+            ));
+        }
+
+        if let Some(where_to_buy) = gift.where_to_buy {
+            let (x, y) = at(CARD_PICTURE.0, CARD_PICTURE.1);
+            ops.push(link(x, y, Mm(picture_size), Mm(picture_size), where_to_buy));
+        }
+
+        if gift.heart {
+            let (x, y) = at(0.0, CARD_PICTURE.1 + 64.0);
+            ops.push(self.heart(x, y, 0.25 * scale));
+        }
+        ops
+    }
+
+    /**
+    Name and price on the first line, description under them on as many lines as the row holds.
+    Rows smaller than the default one are drawn smaller.
+    **/
+    fn gift_line(&mut self, gift: PdfGift, cell: Cell) -> Vec<Op> {
+        let mut ops = Vec::new();
+        let scale = (cell.height.0 / LIST_ROW_HEIGHT.0).min(1.0);
+        let top = cell.y + cell.height;
+        let text_x = cell.x + Mm(8.0 * scale);
+        let text_width = cell.width - Mm(8.0 * scale) - Mm(25.0);
+        let title_font_id = self.title_font_id.clone();
+        let text_font_id = self.text_font_id.clone();
+
+        if gift.heart {
+            ops.push(self.heart(cell.x, top - Mm(7.0 * scale), 0.08 * scale));
+        }
+
+        ops.extend(self.text(
+            &gift.name,
+            TextBox {
+                font_id: &title_font_id,
+                font_size: Pt(14.0 * scale),
+                x: text_x,
+                top: top - Mm(2.0 * scale),
+                width: text_width,
+                align: TextAlign::Left,
+                max_lines: 1,
+            },
+        ));
+
+        //Down to the bottom of the row
+        let description_top = top - Mm(9.0 * scale);
+        let font_size = Pt(10.0 * scale);
+        let max_lines =
+            ((description_top - cell.y).into_pt().0 / (font_size.0 * LINE_HEIGHT)) as usize;
+
+        if let Some(price) = gift.price {
+            ops.extend(self.text(
+                &price,
+                TextBox {
+                    font_id: &text_font_id,
+                    font_size: Pt(14.0 * scale),
+                    x: cell.x + cell.width - Mm(25.0),
+                    top: top - Mm(2.0 * scale),
+                    width: Mm(25.0),
+                    align: TextAlign::Right,
+                    max_lines: 1,
+                },
+            ));
+        }
+
+        let status = [
+            gift.secret.then_some(self.language.secret()),
+            gift.reservation
+                .map(|reservation| self.language.reservation(reservation)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        if !status.is_empty() {
+            ops.extend(self.text(
+                &status.join(", "),
+                TextBox {
+                    font_id: &text_font_id,
+                    font_size,
+                    x: cell.x + cell.width - Mm(25.0),
+                    top: description_top,
+                    width: Mm(25.0),
+                    align: TextAlign::Right,
+                    max_lines: max_lines.min(2),
+                },
+            ));
+        }
+
+        if let Some(description) = gift.description {
+            ops.extend(self.text(
+                &description,
+                TextBox {
+                    font_id: &text_font_id,
+                    font_size,
+                    x: text_x,
+                    top: description_top,
+                    width: text_width,
+                    align: TextAlign::Left,
+                    max_lines,
+                },
+            ));
+        }
+
+        if let Some(where_to_buy) = gift.where_to_buy {
+            ops.push(link(cell.x, cell.y, cell.width, cell.height, where_to_buy));
+        }
+
+        ops.push(Op::SaveGraphicsState);
+        ops.push(Op::SetOutlineColor {
+            col: self.theme.accent(),
+        });
+        ops.push(Op::DrawLine {
+            line: Line {
+                points: vec![
+                    LinePoint {
+                        p: Point {
+                            x: cell.x.into_pt(),
+                            y: cell.y.into_pt(),
+                        },
+                        bezier: false,
+                    },
+                    LinePoint {
+                        p: Point {
+                            x: (cell.x + cell.width).into_pt(),
+                            y: cell.y.into_pt(),
+                        },
+                        bezier: false,
+                    },
+                ],
+                is_closed: false,
+            },
+        });
+        ops.push(Op::RestoreGraphicsState);
+        ops
+    }
+
+    /**
+    Each line is shaped on its own and aligned here, without a maximum width for the shaping to
+    wrap it again, so the text never goes out of its box
+    **/
+    fn text(&mut self, text: &str, text_box: TextBox) -> Vec<Op> {
+        let options = TextShapingOptions {
+            font_size: text_box.font_size,
+            ..Default::default()
+        };
+        let width = text_box.width.into_pt();
+        let mut widths = HashMap::new();
+        let lines = fit_text(text, width, text_box.max_lines, |grapheme| {
+            *widths
+                .entry(grapheme.to_string())
+                .or_insert_with(|| self.measure(grapheme, text_box.font_id, text_box.font_size))
+        });
+        let mut ops = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            let Some(shaped_text) = self.doc.shape_text(line, text_box.font_id, &options) else {
+                debug!("Could not shape text {line}");
+                continue;
+            };
+            let free = width.0 - shaped_text.width;
+            let shift = match text_box.align {
+                TextAlign::Center => free / 2.0,
+                TextAlign::Right => free,
+                _ => 0.0,
+            };
             let origin = Point {
-                x: x.into_pt(),
-                y: (y - Mm(10.0)).into_pt(),
+                x: text_box.x.into_pt() + Pt(shift),
+                y: text_box.top.into_pt() - Pt(index as f32 * text_box.font_size.0 * LINE_HEIGHT),
             };
             ops.extend(shaped_text.get_ops(origin));
+        }
+        ops
+    }
 
-            if let Some(description) = gift.description {
-                let shaped_text = doc
-                    .shape_text(
-                        &description,
-                        &roboto_regular_font_id,
-                        &gift_description_options,
-                    )
-                    .unwrap();
-                let origin = Point {
-                    x: x.into_pt(),
-                    y: (y - Mm(30.0)).into_pt(),
-                };
-                ops.extend(shaped_text.get_ops(origin));
-            }
+    /**
+    Width of the text on a single line, estimated when it cannot be shaped
+    **/
+    fn measure(&self, text: &str, font_id: &FontId, font_size: Pt) -> Pt {
+        let options = TextShapingOptions {
+            font_size,
+            ..Default::default()
+        };
+        match self.doc.shape_text(text, font_id, &options) {
+            Some(shaped_text) => Pt(shaped_text.width),
+            None => Pt(text.graphemes(true).count() as f32 * font_size.0 * CHARACTER_WIDTH),
+        }
+    }
 
-            if let Some(price) = gift.price {
-                let diameter = Mm(20.0);
-                let radius = (diameter / 2.0).into_pt();
-                let offset_x = (x + Mm(79.0)).into_pt() - radius;
-                let offset_y = (y + Mm(79.0)).into_pt() - radius;
-                let k = 0.551_915_05;
-                let k_radius = Pt(k * radius.0);
-
-                ops.push(Op::SaveGraphicsState);
-                ops.push(Op::SetFillColor {
-                    col: Color::Rgb(Rgb {
-                        r: 0.86,
-                        g: 0.86,
-                        b: 0.86,
-                        icc_profile: None,
-                    }),
-                });
-                ops.push(Op::DrawPolygon {
-                    polygon: Polygon {
-                        rings: vec![PolygonRing {
-                            points: vec![
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x,
-                                        y: offset_y + radius,
-                                    },
-                                    bezier: true,
-                                },
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x + k_radius,
-                                        y: offset_y + radius,
-                                    },
-                                    bezier: true,
-                                },
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x + radius,
-                                        y: offset_y + k_radius,
-                                    },
-                                    bezier: true,
-                                },
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x + radius,
-                                        y: offset_y,
-                                    },
-                                    bezier: false,
-                                },
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x + radius,
-                                        y: offset_y - k_radius,
-                                    },
-                                    bezier: true,
-                                },
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x + k_radius,
-                                        y: offset_y - radius,
-                                    },
-                                    bezier: true,
-                                },
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x,
-                                        y: offset_y - radius,
-                                    },
-                                    bezier: false,
-                                },
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x - k_radius,
-                                        y: offset_y - radius,
-                                    },
-                                    bezier: true,
-                                },
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x - radius,
-                                        y: offset_y - k_radius,
-                                    },
-                                    bezier: true,
-                                },
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x - radius,
-                                        y: offset_y,
-                                    },
-                                    bezier: false,
-                                },
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x - radius,
-                                        y: offset_y + k_radius,
-                                    },
-                                    bezier: true,
-                                },
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x - k_radius,
-                                        y: offset_y + radius,
-                                    },
-                                    bezier: true,
-                                },
-                                LinePoint {
-                                    p: Point {
-                                        x: offset_x,
-                                        y: offset_y + radius,
-                                    },
-                                    bezier: false,
-                                },
-                            ],
-                        }],
-                        mode: PaintMode::Fill,
-                        winding_order: WindingOrder::NonZero,
-                    },
-                });
-                ops.push(Op::RestoreGraphicsState);
-
-                let shaped_text = doc
-                    .shape_text(&price, &roboto_regular_font_id, &gift_price_options)
-                    .unwrap();
-                let origin = Point {
-                    x: offset_x - radius,
-                    y: offset_y + Mm(3.5).into_pt(),
-                };
-                ops.extend(shaped_text.get_ops(origin));
+    fn picture(&self, picture: Option<&str>) -> RawImage {
+        let Some(content) = picture.and_then(|picture| self.pictures.get(picture)) else {
+            return self.blank_gift.clone();
+        };
+        match RawImage::decode_from_bytes(content, &mut Vec::new()) {
+            Ok(image) => image,
+            Err(error) => {
+                debug!("Could not decode picture {picture:?}: {error}");
+                self.blank_gift.clone()
             }
+        }
+    }
 
-            if let Some(where_to_buy) = gift.where_to_buy {
-                ops.push(Op::LinkAnnotation {
-                    link: LinkAnnotation {
-                        rect: Rect {
-                            x: x.into_pt(),
-                            y: y.into_pt(),
-                            width: GIFT_SIZE_MM.into_pt(),
-                            height: GIFT_SIZE_MM.into_pt(),
-                        },
-                        actions: Actions::Uri(where_to_buy),
-                        border: BorderArray::default(),
-                        color: ColorArray::default(),
-                        highlighting: HighlightingMode::None,
-                    },
-                })
-            }
+    fn heart(&self, x: Mm, y: Mm, scale: f32) -> Op {
+        Op::UseXobject {
+            id: self.heart_id.clone(),
+            transform: XObjectTransform {
+                translate_x: Some(x.into_pt()),
+                translate_y: Some(y.into_pt()),
+                scale_x: Some(scale),
+                scale_y: Some(scale),
+                ..Default::default()
+            },
+        }
+    }
+}
 
-            if gift.heart {
-                ops.push(Op::UseXobject {
-                    id: heart_id.clone(),
-                    transform: XObjectTransform {
-                        translate_x: Some((x - Mm(5.0)).into_pt()),
-                        translate_y: Some((y + Mm(64.0)).into_pt()),
-                        scale_x: Some(0.25),
-                        scale_y: Some(0.25),
-                        ..Default::default()
-                    },
-                });
+/**
+Split the text in lines fitting the width, breaking the words too long for a line between their
+graphemes, measured one by one. The last line is ellipsized when there are too many lines.
+**/
+fn fit_text(
+    text: &str,
+    width: Pt,
+    max_lines: usize,
+    mut measure: impl FnMut(&str) -> Pt,
+) -> Vec<String> {
+    let space = measure(" ").0;
+    let mut lines = Vec::new();
+    'paragraphs: for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut line_width = 0.0;
+        for word in paragraph.split_whitespace() {
+            let graphemes = word
+                .graphemes(true)
+                .map(|grapheme| (grapheme, measure(grapheme).0))
+                .collect::<Vec<_>>();
+            let word_width = graphemes.iter().map(|(_, width)| width).sum::<f32>();
+            if !line.is_empty() {
+                if line_width + space + word_width <= width.0 {
+                    line.push(' ');
+                    line.push_str(word);
+                    line_width += space + word_width;
+                    continue;
+                }
+                lines.push(std::mem::take(&mut line));
+                line_width = 0.0;
+                //A very long text is not split further than needed
+                if lines.len() > max_lines {
+                    break 'paragraphs;
+                }
+            }
+            for (grapheme, grapheme_width) in graphemes {
+                if !line.is_empty() && line_width + grapheme_width > width.0 {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0.0;
+                    if lines.len() > max_lines {
+                        break 'paragraphs;
+                    }
+                }
+                line.push_str(grapheme);
+                line_width += grapheme_width;
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        if lines.len() > max_lines {
+            break;
+        }
+    }
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        let ellipsis = measure("…").0;
+        if let Some(last) = lines.last_mut() {
+            let mut line_width = 0.0;
+            let mut ellipsized = String::new();
+            for grapheme in last.trim_end().graphemes(true) {
+                line_width += measure(grapheme).0;
+                if line_width + ellipsis > width.0 {
+                    break;
+                }
+                ellipsized.push_str(grapheme);
             }
+            ellipsized.push('…');
+            *last = ellipsized;
         }
+    }
+    lines
+}
 
-        if !ops.is_empty() {
-            let page = PdfPage::new(PAGE_WIDTH, PAGE_HEIGHT, ops);
-            pages.push(page);
+fn link(x: Mm, y: Mm, width: Mm, height: Mm, uri: String) -> Op {
+    Op::LinkAnnotation {
+        link: LinkAnnotation {
+            rect: Rect {
+                x: x.into_pt(),
+                y: y.into_pt(),
+                width: width.into_pt(),
+                height: height.into_pt(),
+            },
+            actions: Actions::Uri(uri),
+            border: BorderArray::default(),
+            color: ColorArray::default(),
+            highlighting: HighlightingMode::None,
+        },
+    }
+}
 
-        }
+fn grey() -> Color {
+    Color::Rgb(Rgb {
+        r: 0.75,
+        g: 0.75,
+        b: 0.75,
+        icc_profile: None,
+    })
+}
+
+fn rectangle(x: Mm, y: Mm, width: Mm, height: Mm, mode: PaintMode) -> Polygon {
+    let point = |x: Mm, y: Mm| LinePoint {
+        p: Point {
+            x: x.into_pt(),
+            y: y.into_pt(),
+        },
+        bezier: false,
+    };
+    Polygon {
+        rings: vec![PolygonRing {
+            points: vec![
+                point(x, y),
+                point(x + width, y),
+                point(x + width, y + height),
+                point(x, y + height),
+            ],
+        }],
+        mode,
+        winding_order: WindingOrder::NonZero,
     }
+}
 
-    doc
-        .with_pages(pages)
-        .save(&PdfSaveOptions::default(), &mut Vec::new())
+/**
+Four bezier curves around the center
+**/
+fn circle(x: Mm, y: Mm, radius: Mm) -> Polygon {
+    let (x, y, radius) = (x.into_pt(), y.into_pt(), radius.into_pt());
+    let k_radius = Pt(0.551_915_05 * radius.0);
+    let point = |x: Pt, y: Pt, bezier: bool| LinePoint {
+        p: Point { x, y },
+        bezier,
+    };
+    Polygon {
+        rings: vec![PolygonRing {
+            points: vec![
+                point(x, y + radius, true),
+                point(x + k_radius, y + radius, true),
+                point(x + radius, y + k_radius, true),
+                point(x + radius, y, false),
+                point(x + radius, y - k_radius, true),
+                point(x + k_radius, y - radius, true),
+                point(x, y - radius, false),
+                point(x - k_radius, y - radius, true),
+                point(x - radius, y - k_radius, true),
+                point(x - radius, y, false),
+                point(x - radius, y + k_radius, true),
+                point(x - k_radius, y + radius, true),
+                point(x, y + radius, false),
+            ],
+        }],
+        mode: PaintMode::Fill,
+        winding_order: WindingOrder::NonZero,
+    }
+}
+
+fn get_font(font_bytes: &[u8], doc: &mut PdfDocument) -> Result<FontId, PdfError> {
+    let font =
+        printpdf::ParsedFont::from_bytes(font_bytes, 0, &mut Vec::new()).ok_or(PdfError::Font)?;
+    Ok(doc.add_font(&font))
 }
 
-fn get_font(font_bytes: &[u8], doc: &mut PdfDocument) -> FontId {
-    let font = printpdf::ParsedFont::from_bytes(font_bytes, 0, &mut Vec::new()).unwrap();
-    doc.add_font(&font)
-}
\ No newline at end of file
+#[cfg(test)]
+mod test {
+    use crate::clock::SystemClock;
+    use crate::managers::events_manager::EventKind;
+    use crate::managers::friends_manager::FriendsManager;
+    use crate::managers::pdf_generator::{
+        fit_text, get_pdf, Cell, Language, Layout, LayoutStyle, Orientation, PaperSize,
+        PdfCategory, PdfGift, PdfOptions, PdfWishList, Reservation, Theme,
+    };
+    use crate::managers::test_helper::create_test_database;
+    use crate::managers::users_manager::UsersManager;
+    use crate::managers::wishlist_manager::WishlistManager;
+    use printpdf::{Mm, Pt};
+    use std::collections::{HashMap, HashSet};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_layout() {
+        let banner = Layout::default();
+        assert_eq!(
+            banner,
+            Layout {
+                paper: PaperSize::Banner,
+                orientation: Orientation::Landscape,
+                style: LayoutStyle::Grid,
+                columns: 5,
+                rows: 1,
+            }
+        );
+        assert_eq!(banner.page_size(), (Mm(525.0), Mm(286.0)));
+        let cells = banner.cells();
+        assert_eq!(cells.len(), 5);
+        assert_eq!(
+            cells[1],
+            Cell {
+                x: Mm(114.0),
+                y: Mm(15.0),
+                width: Mm(99.0),
+                height: Mm(246.0),
+            }
+        );
+
+        let a4 = Layout::new(Some(PaperSize::A4), None, None, None, None);
+        assert_eq!(a4.orientation, Orientation::Portrait);
+        assert_eq!((a4.columns, a4.rows), (3, 2));
+        assert_eq!(a4.page_size(), (Mm(210.0), Mm(297.0)));
+        let cells = a4.cells();
+        assert_eq!(cells.len(), 6);
+        //Row after row, from the top
+        assert_eq!(cells[0].y, Mm(15.0 + 128.5));
+        assert_eq!(cells[3].x, Mm(15.0));
+        assert_eq!(cells[3].y, Mm(15.0));
+
+        let letter = Layout::new(
+            Some(PaperSize::Letter),
+            Some(Orientation::Landscape),
+            None,
+            Some(20),
+            Some(0),
+        );
+        assert_eq!(letter.page_size(), (Mm(279.4), Mm(215.9)));
+        assert_eq!((letter.columns, letter.rows), (8, 1));
+
+        let list = Layout::new(
+            Some(PaperSize::A4),
+            None,
+            Some(LayoutStyle::List),
+            None,
+            None,
+        );
+        assert_eq!((list.columns, list.rows), (1, 14));
+    }
+
+    #[test]
+    fn test_friend_wishlist() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_pdf_friend_wishlist")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let three = users_manager.add_user("three", "pwd").unwrap();
+        FriendsManager::new(connection.clone()).unwrap();
+
+        let wishlist_manager = WishlistManager::new(connection).unwrap();
+        wishlist_manager
+            .add_category("OneCategory", HashSet::from([one]))
+            .unwrap();
+        wishlist_manager
+            .add_category("Shared", HashSet::from([one, two]))
+            .unwrap();
+        for (name, secret, category) in [
+            ("Free", false, 1),
+            ("ReservedByMe", false, 1),
+            ("Taken", false, 1),
+            ("Secret", true, 1),
+            ("SharedGift", false, 2),
+        ] {
+            wishlist_manager
+                .add_gift(
+                    name,
+                    None,
+                    None,
+                    None,
+                    None,
+                    secret.then_some(two),
+                    category,
+                )
+                .unwrap();
+        }
+        wishlist_manager.reserve_gift(2, Some(two)).unwrap();
+        wishlist_manager.reserve_gift(3, Some(three)).unwrap();
+
+        let wishlist = wishlist_manager.get_friend_wishlist(two, one).unwrap();
+        let gift = |name: &str, secret, reservation| PdfGift {
+            name: name.to_string(),
+            description: None,
+            price: None,
+            where_to_buy: None,
+            picture: None,
+            heart: false,
+            secret,
+            reservation,
+        };
+        assert_eq!(
+            PdfWishList::from_friend_wishlist(wishlist, two),
+            PdfWishList {
+                categories: vec![PdfCategory {
+                    name: "OneCategory".to_string(),
+                    gifts: vec![
+                        gift("Free", false, None),
+                        gift("ReservedByMe", false, Some(Reservation::ByMe)),
+                        gift("Taken", false, Some(Reservation::ByOther)),
+                        gift("Secret", true, None),
+                    ]
+                }]
+            }
+        );
+    }
+
+    #[test]
+    fn test_fit_text() {
+        //Ten graphemes per line, CJK ones being twice as wide
+        let measure = |grapheme: &str| match grapheme.chars().next() {
+            Some('\u{2E80}'..='\u{9FFF}') => Pt(11.0),
+            _ => Pt(5.5),
+        };
+        let fit = |text: &str, max_lines| fit_text(text, Pt(55.0), max_lines, measure);
+        assert_eq!(
+            fit("👨‍👩‍👧‍👦🇫🇷👍🏽e\u{301} 🏳️‍🌈🏳️‍🌈🏳️‍🌈🏳️‍🌈🏳️‍🌈", 1),
+            vec!["👨‍👩‍👧‍👦🇫🇷👍🏽e\u{301} 🏳️‍🌈🏳️‍🌈🏳️‍🌈🏳️‍🌈🏳️‍🌈"]
+        );
+        assert_eq!(
+            fit("日本語のテキストです", 2),
+            vec!["日本語のテ", "キストです"]
+        );
+        assert_eq!(fit("漢字 and text", 1), vec!["漢字 and…"]);
+        assert_eq!(fit("🎁🎄 Gifts 🎅", 1), vec!["🎁🎄 Gifts 🎅"]);
+        assert_eq!(fit("هدية عيد ميلاد سعيد", 2), vec!["هدية عيد", "ميلاد سعيد"]);
+        assert_eq!(fit("מתנה ליום הולדת", 1), vec!["מתנה ליום…"]);
+        assert_eq!(
+            fit("a very long description that goes on", 2),
+            vec!["a very", "long…"]
+        );
+        assert_eq!(
+            fit("abcdefghijklmnopqrstuvwxyz", 3),
+            vec!["abcdefghij", "klmnopqrst", "uvwxyz"]
+        );
+        assert_eq!(fit("one\ntwo\n\nthree", 5), vec!["one", "two", "three"]);
+        assert_eq!(fit(&"long ".repeat(100_000), 1), vec!["long long…"]);
+        assert_eq!(fit("", 2), Vec::<String>::new());
+        assert_eq!(fit("nothing", 0), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_get_pdf() {
+        let gift = |index: usize, name: &str, picture: Option<&str>| PdfGift {
+            name: name.to_string(),
+            description: Some("🎁 ".repeat(1000)),
+            price: Some("1234567890 €".to_string()),
+            where_to_buy: Some(format!("https://example.com/{index}")),
+            picture: picture.map(str::to_string),
+            heart: true,
+            secret: true,
+            reservation: Some(Reservation::ByOther),
+        };
+        let wishlist = || PdfWishList {
+            categories: vec![
+                PdfCategory {
+                    name: "קטגוריה ".repeat(50),
+                    gifts: vec![
+                        gift(0, "هدية", Some("broken.png")),
+                        gift(1, &"x".repeat(10_000), Some("missing.png")),
+                        gift(2, "", None),
+                        gift(3, "日本語", None),
+                        gift(4, "👨‍👩‍👧‍👦", None),
+                        gift(5, "Gift", None),
+                        gift(6, "Gift", None),
+                    ],
+                },
+                PdfCategory {
+                    name: "Other".to_string(),
+                    gifts: vec![gift(7, "Gift", None)],
+                },
+                PdfCategory {
+                    name: "Empty".to_string(),
+                    gifts: vec![],
+                },
+            ],
+        };
+        //Page and link dictionaries are not compressed, whatever the spacing of the writer
+        let count = |pdf: &[u8], pattern: &str| {
+            let pdf = pdf
+                .iter()
+                .filter(|byte| !byte.is_ascii_whitespace())
+                .copied()
+                .collect::<Vec<_>>();
+            pdf.windows(pattern.len())
+                .filter(|window| *window == pattern.as_bytes())
+                .count()
+        };
+        let pictures = HashMap::from([("broken.png".to_string(), vec![0, 1, 2, 3])]);
+        //Title page, then five gifts per page on the banner or thirteen rows
+        for (style, rows, language, title_page, pages) in [
+            (LayoutStyle::Grid, None, Language::En, true, 4),
+            (LayoutStyle::List, None, Language::Fr, true, 3),
+            (LayoutStyle::List, Some(40), Language::En, false, 2),
+            (LayoutStyle::Grid, Some(8), Language::Fr, false, 2),
+        ] {
+            let options = PdfOptions {
+                title: "🎅".repeat(500),
+                event: Some(EventKind::Christmas),
+                layout: Layout::new(None, None, Some(style), None, rows),
+                theme: Theme::Festive,
+                title_page,
+                language,
+            };
+            let pdf = get_pdf(wishlist(), &pictures, &options).unwrap();
+            assert!(pdf.starts_with(b"%PDF-"));
+            let page_count = count(&pdf, "/Type/Page") - count(&pdf, "/Type/Pages");
+            assert_eq!(page_count, pages, "{style:?} {rows:?}");
+            for index in 0..8 {
+                assert_eq!(count(&pdf, &format!("(https://example.com/{index})")), 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,495 @@
+use crate::clock::Clock;
+use crate::managers::events_manager::{EventGift, EventKind, EventsManager, EventsManagerError};
+use crate::managers::notifications_manager::NotificationsManagerError;
+use crate::managers::users_manager::{UsersManager, UsersManagerError};
+use crate::managers::wishlist_manager::WishlistManagerError;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, error};
+
+/**
+Look far enough to find the events of the longest reminder lead time
+**/
+const REMINDERS_HORIZON_MONTHS: u32 = 3;
+
+/**
+Way of delivering a digest to a user
+**/
+pub trait Notifier: Send + Sync {
+    fn notify<'a>(
+        &'a self,
+        user_id: i64,
+        digest: &'a Digest,
+    ) -> BoxFuture<'a, Result<(), NotifierError>>;
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub(crate) enum NotifierError {
+    NotificationsManager(#[from] NotificationsManagerError),
+    Http(#[from] reqwest::Error),
+}
+
+/**
+POST the digest as JSON to an external service, which is in charge of reaching the user
+**/
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    user_id: i64,
+    digest: &'a Digest,
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify<'a>(
+        &'a self,
+        user_id: i64,
+        digest: &'a Digest,
+    ) -> BoxFuture<'a, Result<(), NotifierError>> {
+        async move {
+            self.client
+                .post(&self.url)
+                .json(&WebhookPayload { user_id, digest })
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+#[derive(Clone)]
+pub struct RemindersManager {
+    connection: Arc<Mutex<Connection>>,
+    users_manager: UsersManager,
+    events_manager: EventsManager,
+    notifier: Arc<dyn Notifier>,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub(crate) enum RemindersManagerError {
+    Sqlite(#[from] rusqlite::Error),
+    UsersManager(#[from] UsersManagerError),
+    EventsManager(#[from] EventsManagerError),
+    WishlistManager(#[from] WishlistManagerError),
+}
+
+impl RemindersManager {
+    pub fn new(
+        connection: Arc<Mutex<Connection>>,
+        users_manager: UsersManager,
+        events_manager: EventsManager,
+        notifier: Arc<dyn Notifier>,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, RemindersManagerError> {
+        Self::init_database(&connection)?;
+        Ok(Self {
+            connection,
+            users_manager,
+            events_manager,
+            notifier,
+            clock,
+        })
+    }
+
+    /**
+    A reminder is identified by the event and its date, friendId being 0 for Christmas
+    **/
+    fn init_database(connection: &Arc<Mutex<Connection>>) -> Result<(), RemindersManagerError> {
+        let connection = connection.lock().unwrap();
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS sentReminders (userId INTEGER NOT NULL, kind TEXT NOT NULL, friendId INTEGER NOT NULL, \
+            date INTEGER NOT NULL, UNIQUE(userId, kind, friendId, date), FOREIGN KEY(userId) REFERENCES users(id))")?;
+        Ok(())
+    }
+
+    pub async fn run(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match self.send_reminders().await {
+                Ok(sent) => debug!("{sent} reminders sent"),
+                Err(e) => error!("Cannot send reminders: {e}"),
+            }
+        }
+    }
+
+    /**
+    Forget the reminders sent to a deleted user, and about them
+    **/
+    pub fn forget_user(&self, user_id: i64) -> Result<(), RemindersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "DELETE FROM sentReminders WHERE userId=?1 OR friendId=?1",
+            params![user_id],
+        )?;
+        Ok(())
+    }
+
+    /**
+    Send a digest for every event entering the lead time of the users that opted in.
+    The reminder is recorded before being sent so that a restart never sends it twice, and
+    forgotten if the sending failed so that it is retried on the next run. A failure for one
+    user does not prevent the others from getting their reminders.
+    **/
+    pub async fn send_reminders(&self) -> Result<usize, RemindersManagerError> {
+        let mut sent = 0;
+        for (user_id, reminder_days) in self.users_manager.get_users_with_reminders()? {
+            match self.send_user_reminders(user_id, reminder_days).await {
+                Ok(user_sent) => sent += user_sent,
+                Err(e) => error!("Cannot send reminders to user {user_id}: {e}"),
+            }
+        }
+        Ok(sent)
+    }
+
+    async fn send_user_reminders(
+        &self,
+        user_id: i64,
+        reminder_days: u32,
+    ) -> Result<usize, RemindersManagerError> {
+        let now = self.clock.now().timestamp();
+        let lead_time = i64::from(reminder_days) * 24 * 3600;
+        let mut sent = 0;
+        let events = self
+            .events_manager
+            .get_events(user_id, REMINDERS_HORIZON_MONTHS)?;
+        for event in events.into_iter().filter(|e| e.date - now < lead_time) {
+            let friend_id = event.friend_id.unwrap_or(0);
+            let already_sent = {
+                let connection = self.connection.lock().unwrap();
+                let mut statement = connection.prepare_cached(
+                    "SELECT 1 FROM sentReminders WHERE userId=? AND kind=? AND friendId=? AND date=?",
+                )?;
+                statement.exists(params![user_id, event.kind, friend_id, event.date])?
+            };
+            if already_sent {
+                continue;
+            }
+
+            //The fallback is only for birthdays without attached gifts, not for fully reserved ones
+            let gifts = if event.kind == EventKind::Birthday && event.gifts.is_empty() {
+                self.get_free_gifts(user_id, friend_id)?
+            } else {
+                event.gifts.into_iter().filter(|gift| gift.free).collect()
+            };
+            let digest = Digest {
+                kind: event.kind,
+                date: event.date,
+                friend_id: event.friend_id,
+                name: event.name,
+                gifts,
+            };
+
+            let recorded = {
+                let connection = self.connection.lock().unwrap();
+                connection.execute(
+                    "INSERT OR IGNORE INTO sentReminders(userId, kind, friendId, date) VALUES (?,?,?,?)",
+                    params![user_id, digest.kind, friend_id, digest.date],
+                )?
+            };
+            if recorded == 0 {
+                continue;
+            }
+
+            match self.notifier.notify(user_id, &digest).await {
+                Ok(()) => sent += 1,
+                Err(e) => {
+                    error!("Cannot notify user {user_id}: {e}");
+                    let connection = self.connection.lock().unwrap();
+                    connection.execute(
+                        "DELETE FROM sentReminders WHERE userId=? AND kind=? AND friendId=? AND date=?",
+                        params![user_id, digest.kind, friend_id, digest.date],
+                    )?;
+                }
+            }
+        }
+        Ok(sent)
+    }
+
+    /**
+    When nothing has been attached to the birthday, every gift of the friend is a candidate
+    **/
+    fn get_free_gifts(
+        &self,
+        user_id: i64,
+        friend_id: i64,
+    ) -> Result<Vec<EventGift>, RemindersManagerError> {
+        let wishlist = self
+            .events_manager
+            .wishlist_manager
+            .get_friend_wishlist(user_id, friend_id)?;
+        Ok(wishlist
+            .categories
+            .into_iter()
+            .flat_map(|category| category.gifts)
+            .filter(|gift| gift.reserved_by.is_none())
+            .map(|gift| EventGift {
+                id: gift.id,
+                friend_id,
+                name: gift.name,
+                free: true,
+            })
+            .collect())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct Digest {
+    pub kind: EventKind,
+    pub date: i64,
+    pub friend_id: Option<i64>,
+    pub name: Option<String>,
+    pub gifts: Vec<EventGift>,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::TestClock;
+    use crate::managers::events_manager::{EventGift, EventKind, EventsManager};
+    use crate::managers::friends_manager::{FriendsManager, RequestStatus};
+    use crate::managers::notifications_manager::NotificationsManagerError;
+    use crate::managers::reminders_manager::{Digest, Notifier, NotifierError, RemindersManager};
+    use crate::managers::test_helper::create_test_database;
+    use crate::managers::users_manager::{EditSettings, EditUser, UsersManager};
+    use crate::managers::wishlist_manager::WishlistManager;
+    use chrono::{TimeZone, Utc};
+    use futures_util::future::BoxFuture;
+    use futures_util::FutureExt;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct TestNotifier {
+        fail: AtomicBool,
+        digests: Mutex<Vec<(i64, Digest)>>,
+    }
+
+    impl Notifier for TestNotifier {
+        fn notify<'a>(
+            &'a self,
+            user_id: i64,
+            digest: &'a Digest,
+        ) -> BoxFuture<'a, Result<(), NotifierError>> {
+            let result = if self.fail.load(Ordering::SeqCst) {
+                Err(NotifierError::NotificationsManager(
+                    NotificationsManagerError::UnknownNotification(0),
+                ))
+            } else {
+                self.digests.lock().unwrap().push((user_id, digest.clone()));
+                Ok(())
+            };
+            futures_util::future::ready(result).boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_reminders() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_send_reminders")));
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let three = users_manager.add_user("three", "pwd").unwrap();
+        //1990-06-15
+        users_manager
+            .edit_user(
+                two,
+                &EditUser {
+                    date_of_birth: Some(Some(645408000)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        users_manager
+            .edit_settings(
+                one,
+                &EditSettings {
+                    reminders: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+        friends_manager.create_friend_request(one, "two").unwrap();
+        friends_manager
+            .update_received_request(1, two, RequestStatus::Accepted)
+            .unwrap();
+        friends_manager.create_friend_request(three, "two").unwrap();
+        friends_manager
+            .update_received_request(2, two, RequestStatus::Accepted)
+            .unwrap();
+
+        let wishlist_manager = WishlistManager::new(connection.clone()).unwrap();
+        wishlist_manager
+            .add_category("Category", HashSet::from([two]))
+            .unwrap();
+        wishlist_manager
+            .add_gift("Gift", None, None, None, None, None, 1)
+            .unwrap();
+        wishlist_manager
+            .add_gift("Reserved", None, None, None, None, None, 1)
+            .unwrap();
+        wishlist_manager.reserve_gift(2, Some(one)).unwrap();
+
+        let events_manager = EventsManager::new(
+            connection.clone(),
+            friends_manager,
+            wishlist_manager,
+            clock.clone(),
+        )
+        .unwrap();
+        let notifier = Arc::new(TestNotifier::default());
+        let reminders_manager = RemindersManager::new(
+            connection.clone(),
+            users_manager.clone(),
+            events_manager.clone(),
+            notifier.clone(),
+            clock.clone(),
+        )
+        .unwrap();
+
+        assert_eq!(reminders_manager.send_reminders().await.unwrap(), 0);
+
+        clock.set(Utc.with_ymd_and_hms(2024, 6, 9, 0, 0, 0).unwrap());
+        notifier.fail.store(true, Ordering::SeqCst);
+        assert_eq!(reminders_manager.send_reminders().await.unwrap(), 0);
+        notifier.fail.store(false, Ordering::SeqCst);
+        assert_eq!(reminders_manager.send_reminders().await.unwrap(), 1);
+        assert_eq!(reminders_manager.send_reminders().await.unwrap(), 0);
+        assert_eq!(
+            *notifier.digests.lock().unwrap(),
+            vec![(
+                one,
+                Digest {
+                    kind: EventKind::Birthday,
+                    //2024-06-15
+                    date: 1718409600,
+                    friend_id: Some(two),
+                    name: Some("two".to_string()),
+                    gifts: vec![EventGift {
+                        id: 1,
+                        friend_id: two,
+                        name: "Gift".to_string(),
+                        free: true
+                    }]
+                }
+            )]
+        );
+
+        //Restart
+        let reminders_manager = RemindersManager::new(
+            connection,
+            users_manager,
+            events_manager,
+            notifier.clone(),
+            clock,
+        )
+        .unwrap();
+        assert_eq!(reminders_manager.send_reminders().await.unwrap(), 0);
+        assert_eq!(notifier.digests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reserved_birthday_gifts() {
+        let connection = Arc::new(Mutex::new(create_test_database(
+            "test_reserved_birthday_gifts",
+        )));
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 6, 9, 0, 0, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection.clone(), clock.clone()).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        //1990-06-15
+        users_manager
+            .edit_user(
+                two,
+                &EditUser {
+                    date_of_birth: Some(Some(645408000)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        users_manager
+            .edit_settings(
+                one,
+                &EditSettings {
+                    reminders: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+        friends_manager.create_friend_request(one, "two").unwrap();
+        friends_manager
+            .update_received_request(1, two, RequestStatus::Accepted)
+            .unwrap();
+
+        //Everything attached to the birthday is reserved, the other gift is not suggested
+        let wishlist_manager = WishlistManager::new(connection.clone()).unwrap();
+        wishlist_manager
+            .add_category("Birthday", HashSet::from([two]))
+            .unwrap();
+        wishlist_manager
+            .add_category("Other", HashSet::from([two]))
+            .unwrap();
+        wishlist_manager
+            .set_category_event(two, 1, Some(EventKind::Birthday))
+            .unwrap();
+        wishlist_manager
+            .add_gift("Reserved", None, None, None, None, None, 1)
+            .unwrap();
+        wishlist_manager
+            .add_gift("Other", None, None, None, None, None, 2)
+            .unwrap();
+        wishlist_manager.reserve_gift(1, Some(one)).unwrap();
+
+        let events_manager = EventsManager::new(
+            connection.clone(),
+            friends_manager,
+            wishlist_manager,
+            clock.clone(),
+        )
+        .unwrap();
+        let notifier = Arc::new(TestNotifier::default());
+        let reminders_manager = RemindersManager::new(
+            connection,
+            users_manager,
+            events_manager,
+            notifier.clone(),
+            clock,
+        )
+        .unwrap();
+
+        assert_eq!(reminders_manager.send_reminders().await.unwrap(), 1);
+        let digests = notifier.digests.lock().unwrap();
+        assert_eq!(digests.len(), 1);
+        assert!(digests[0].1.gifts.is_empty());
+    }
+}
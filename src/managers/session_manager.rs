@@ -1,21 +1,83 @@
+use crate::clock::Clock;
+use chrono::{DateTime, Duration, Utc};
 use rand::distributions::Alphanumeric;
 use rand::Rng;
+use serde::Serialize;
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
-#[derive(Clone, Default)]
+/** A browser session unused for that long is signed out **/
+const SESSION_IDLE_DAYS: i64 = 30;
+
+/**
+Browser sessions, each one holding the accounts signed in that browser with their own token
+**/
+#[derive(Clone)]
 pub struct SessionManager {
-    pub(crate) sessions: Arc<Mutex<HashMap<i64, String>>>,
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    clock: Arc<dyn Clock>,
 }
 
-impl SessionManager {
-    pub fn generate_session(&mut self, user_id: i64) -> String {
-        let mut sessions = self.sessions.lock().unwrap();
+struct Session {
+    /** Signed in accounts, with the account they switched from if they did not sign in by password **/
+    users: HashMap<i64, Option<i64>>,
+    last_used: DateTime<Utc>,
+}
+
+/**
+A browser session the user is signed in, without its id
+**/
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub(crate) struct SessionInfo {
+    last_used: i64,
+    /** The account the user switched from, if they did not sign in by password **/
+    switched_from: Option<i64>,
+}
+
+impl Session {
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now - self.last_used > Duration::days(SESSION_IDLE_DAYS)
+    }
+
+    /**
+    Also remove the accounts switched to from there
+    **/
+    fn remove(&mut self, user_id: i64) {
+        self.users.remove(&user_id);
+        while let Some(orphan) = self
+            .users
+            .iter()
+            .find(|(_, from)| from.is_some_and(|from| !self.users.contains_key(&from)))
+            .map(|(id, _)| *id)
         {
-            if let Some(current_session) = sessions.get(&user_id) {
-                return current_session.to_string();
-            }
+            self.users.remove(&orphan);
+        }
+    }
+}
+
+impl SessionManager {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            clock,
         }
+    }
+
+    /**
+    Sign the user in by password, in a new browser session taking over the accounts of the
+    previous one if it still exists, so that the session id changes on each sign-in
+    **/
+    pub fn sign_in(&self, session: Option<&str>, user_id: i64) -> String {
+        let now = self.clock.now();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| !session.is_expired(now));
+        let mut users = session
+            .and_then(|session| sessions.remove(session))
+            .map(|session| session.users)
+            .unwrap_or_default();
+        users.insert(user_id, None);
 
         let session = rand::thread_rng()
             .sample_iter(Alphanumeric)
@@ -23,27 +85,268 @@ impl SessionManager {
             .map(char::from)
             .collect::<String>();
 
-        sessions.insert(user_id, session.clone());
+        sessions.insert(
+            session.clone(),
+            Session {
+                users,
+                last_used: now,
+            },
+        );
         session
     }
 
-    pub fn set_common_session(&mut self, user_id: i64, other_user_id: i64) -> Option<()> {
+    /**
+    Sign in the browser session an account the user can switch to without password. Return
+    false when the user is no longer signed in the session, which is never recreated.
+    **/
+    pub fn switch(&self, session: &str, from_id: i64, user_id: i64) -> bool {
+        let now = self.clock.now();
         let mut sessions = self.sessions.lock().unwrap();
-        let other_session = {
-            let other_session = sessions.get(&other_user_id)?;
-            other_session.to_string()
-        };
-        sessions.insert(user_id, other_session);
-        Some(())
+        match sessions.get_mut(session) {
+            Some(session) if !session.is_expired(now) && session.users.contains_key(&from_id) => {
+                session.users.entry(user_id).or_insert(Some(from_id));
+                session.last_used = now;
+                true
+            }
+            _ => false,
+        }
     }
 
-    pub fn get_session(&self, user_id: i64) -> Option<String> {
+    /**
+    Also keeps the browser session alive
+    **/
+    pub fn is_signed_in(&self, session: &str, user_id: i64) -> bool {
+        let now = self.clock.now();
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get_mut(session) {
+            Some(session) if !session.is_expired(now) && session.users.contains_key(&user_id) => {
+                session.last_used = now;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_signed_in_anywhere(&self, user_id: i64) -> bool {
+        self.count_sessions(user_id) > 0
+    }
+
+    /**
+    Number of browser sessions the user is signed in
+    **/
+    pub fn count_sessions(&self, user_id: i64) -> usize {
+        let now = self.clock.now();
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .values()
+            .filter(|session| !session.is_expired(now) && session.users.contains_key(&user_id))
+            .count()
+    }
+
+    /**
+    Browser sessions the user is signed in, the most recently used first
+    **/
+    pub(crate) fn get_sessions(&self, user_id: i64) -> Vec<SessionInfo> {
+        let now = self.clock.now();
         let sessions = self.sessions.lock().unwrap();
-        sessions.get(&user_id).map(|s| s.to_string())
+        let mut infos = sessions
+            .values()
+            .filter(|session| !session.is_expired(now))
+            .filter_map(|session| {
+                session.users.get(&user_id).map(|from| SessionInfo {
+                    last_used: session.last_used.timestamp(),
+                    switched_from: *from,
+                })
+            })
+            .collect::<Vec<_>>();
+        infos.sort_by_key(|info| Reverse(info.last_used));
+        infos
+    }
+
+    /**
+    Sign the user out of every other browser session, after a password change
+    **/
+    pub fn sign_out_elsewhere(&self, session: &str, user_id: i64) {
+        let mut sessions = self.sessions.lock().unwrap();
+        for (_, other) in sessions.iter_mut().filter(|(other, _)| *other != session) {
+            other.users.remove(&user_id);
+        }
+        sessions.retain(|_, session| !session.users.is_empty());
     }
 
-    pub fn delete_session(&self, user_id: i64) {
+    /**
+    Sign the user out of the browser sessions they entered by switching from the other account,
+    once they are no longer related, along with the accounts they switched to from there
+    **/
+    pub fn sign_out_switched(&self, from_id: i64, user_id: i64) {
         let mut sessions = self.sessions.lock().unwrap();
-        sessions.remove(&user_id).map(|s| s.to_string());
+        for session in sessions.values_mut() {
+            if session.users.get(&user_id) == Some(&Some(from_id)) {
+                session.remove(user_id);
+            }
+        }
+        sessions.retain(|_, session| !session.users.is_empty());
+    }
+
+    /**
+    Sign the user out of every browser session, once the account is deleted
+    **/
+    pub fn sign_out_everywhere(&self, user_id: i64) {
+        let mut sessions = self.sessions.lock().unwrap();
+        for session in sessions.values_mut() {
+            session.remove(user_id);
+        }
+        sessions.retain(|_, session| !session.users.is_empty());
+    }
+
+    /**
+    Only this account is signed out, the other accounts of the browser session stay signed in
+    **/
+    pub fn sign_out(&self, session: &str, user_id: i64) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(users) = sessions.get_mut(session).map(|session| &mut session.users) {
+            users.remove(&user_id);
+            if users.is_empty() {
+                sessions.remove(session);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::{SystemClock, TestClock};
+    use crate::managers::session_manager::{SessionInfo, SessionManager};
+    use chrono::{Duration, TimeZone, Utc};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_sign_out_linked_account() {
+        let session_manager = SessionManager::new(Arc::new(SystemClock));
+        let session = session_manager.sign_in(None, 1);
+        session_manager.switch(&session, 1, 2);
+        let other_session = session_manager.sign_in(Some("expired"), 1);
+        assert_ne!(other_session, session);
+
+        session_manager.sign_out(&session, 1);
+        assert!(!session_manager.is_signed_in(&session, 1));
+        assert!(session_manager.is_signed_in(&session, 2));
+        assert!(session_manager.is_signed_in(&other_session, 1));
+        assert!(!session_manager.is_signed_in(&other_session, 2));
+
+        session_manager.sign_out(&session, 2);
+        assert!(!session_manager.is_signed_in(&session, 2));
+        assert!(!session_manager.is_signed_in_anywhere(2));
+        assert!(session_manager.is_signed_in_anywhere(1));
+        //A new sign-in does not revive the old browser session
+        assert_ne!(session_manager.sign_in(Some(&session), 2), session);
+    }
+
+    #[test]
+    fn test_switch_signed_out_session() {
+        let session_manager = SessionManager::new(Arc::new(SystemClock));
+        let session = session_manager.sign_in(None, 1);
+        assert!(session_manager.switch(&session, 1, 2));
+        assert!(!session_manager.switch(&session, 3, 4));
+
+        session_manager.sign_out_everywhere(2);
+        session_manager.sign_out_everywhere(1);
+        assert!(!session_manager.switch(&session, 1, 2));
+        assert!(!session_manager.is_signed_in_anywhere(2));
+        assert!(session_manager.sessions.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sign_in_rotates_session() {
+        let session_manager = SessionManager::new(Arc::new(SystemClock));
+        let session = session_manager.sign_in(None, 1);
+        let new_session = session_manager.sign_in(Some(&session), 2);
+        assert_ne!(new_session, session);
+        assert!(!session_manager.is_signed_in(&session, 1));
+        assert!(session_manager.is_signed_in(&new_session, 1));
+        assert!(session_manager.is_signed_in(&new_session, 2));
+        assert_eq!(session_manager.count_sessions(1), 1);
+    }
+
+    #[test]
+    fn test_session_expiry() {
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+        ));
+        let session_manager = SessionManager::new(clock.clone());
+        let session = session_manager.sign_in(None, 1);
+        let idle_session = session_manager.sign_in(None, 1);
+
+        clock.advance(Duration::days(20));
+        assert!(session_manager.is_signed_in(&session, 1));
+        clock.advance(Duration::days(20));
+        assert!(session_manager.is_signed_in(&session, 1));
+        assert!(!session_manager.is_signed_in(&idle_session, 1));
+        assert_eq!(session_manager.count_sessions(1), 1);
+
+        assert_eq!(
+            session_manager.get_sessions(1),
+            vec![SessionInfo {
+                last_used: Utc
+                    .with_ymd_and_hms(2024, 7, 11, 12, 0, 0)
+                    .unwrap()
+                    .timestamp(),
+                switched_from: None,
+            }]
+        );
+        session_manager.switch(&session, 1, 2);
+        assert_eq!(
+            session_manager.get_sessions(2),
+            vec![SessionInfo {
+                last_used: Utc
+                    .with_ymd_and_hms(2024, 7, 11, 12, 0, 0)
+                    .unwrap()
+                    .timestamp(),
+                switched_from: Some(1),
+            }]
+        );
+
+        clock.advance(Duration::days(31));
+        assert!(!session_manager.is_signed_in_anywhere(1));
+        session_manager.sign_in(None, 2);
+        assert_eq!(session_manager.sessions.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_sign_out_elsewhere() {
+        let session_manager = SessionManager::new(Arc::new(SystemClock));
+        let session = session_manager.sign_in(None, 1);
+        let other_session = session_manager.sign_in(None, 1);
+        session_manager.switch(&other_session, 1, 2);
+        let lone_session = session_manager.sign_in(None, 1);
+
+        session_manager.sign_out_elsewhere(&session, 1);
+        assert!(session_manager.is_signed_in(&session, 1));
+        assert!(!session_manager.is_signed_in(&other_session, 1));
+        assert!(session_manager.is_signed_in(&other_session, 2));
+        assert!(!session_manager.is_signed_in(&lone_session, 1));
+        assert_eq!(session_manager.sessions.lock().unwrap().len(), 2);
+        assert_eq!(session_manager.count_sessions(1), 1);
+        assert_eq!(session_manager.count_sessions(2), 1);
+    }
+
+    #[test]
+    fn test_sign_out_switched() {
+        let session_manager = SessionManager::new(Arc::new(SystemClock));
+        let session = session_manager.sign_in(None, 1);
+        session_manager.switch(&session, 1, 2);
+        session_manager.switch(&session, 2, 4);
+        let other_session = session_manager.sign_in(None, 3);
+        session_manager.switch(&other_session, 3, 2);
+        let both_session = session_manager.sign_in(None, 1);
+        let both_session = session_manager.sign_in(Some(&both_session), 2);
+
+        session_manager.sign_out_switched(1, 2);
+        assert!(session_manager.is_signed_in(&session, 1));
+        assert!(!session_manager.is_signed_in(&session, 2));
+        assert!(!session_manager.is_signed_in(&session, 4));
+        assert!(session_manager.is_signed_in(&other_session, 2));
+        //Signed in by password
+        assert!(session_manager.is_signed_in(&both_session, 2));
     }
 }
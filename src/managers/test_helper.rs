@@ -1,6 +1,7 @@
 use rusqlite::Connection;
 use std::env::current_dir;
-use std::fs::{create_dir_all, remove_file};
+use std::fs::{create_dir_all, remove_dir_all, remove_file};
+use std::path::PathBuf;
 
 pub(crate) fn create_test_database(test_name: &str) -> Connection {
     let mut path = current_dir().unwrap();
@@ -18,3 +19,20 @@ pub(crate) fn create_test_database(test_name: &str) -> Connection {
         .unwrap();
     connection
 }
+
+/**
+Empty upload storage, with its resize cache next to it
+**/
+pub(crate) fn create_test_storage(test_name: &str) -> PathBuf {
+    let mut path = current_dir().unwrap();
+    path.push("test_databases");
+    path.push(test_name);
+    if path.exists() {
+        remove_dir_all(&path).unwrap();
+    }
+    create_dir_all(path.join("tmp_rs")).unwrap();
+
+    path.push("uploads");
+    create_dir_all(&path).unwrap();
+    path
+}
@@ -1,16 +1,25 @@
+use crate::clock::Clock;
+use crate::managers::add_column_if_missing;
+use chrono::Duration;
+use chrono_tz::Tz;
 use pbkdf2::pbkdf2_hmac;
 use rand::RngCore;
 use rusqlite::{params, Connection, OptionalExtension, Row};
-use serde::Serialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use sha1::Sha1;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tracing::log::error;
 use uuid::Uuid;
 
+const MAX_REMINDER_DAYS: u32 = 60;
+const MAX_BIO_LENGTH: usize = 500;
+const MAX_PROFILE_FIELD_LENGTH: usize = 200;
+const DELETION_GRACE_DAYS: i64 = 30;
+
 #[derive(Clone)]
 pub struct UsersManager {
     connection: Arc<Mutex<Connection>>,
+    clock: Arc<dyn Clock>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -23,56 +32,333 @@ pub(crate) enum UsersManagerError {
     PasswordMismatch,
     #[error("Unknown user {0}")]
     UnknownUser(String),
+    #[error("Unknown time zone {0}")]
+    UnknownTimeZone(String),
+    #[error("Reminders cannot be sent {0} days before")]
+    InvalidReminderDays(u32),
+    #[error("{0} is a dependent account")]
+    DependentAccount(String),
+    #[error("{0} is the last guardian of {1}")]
+    LastGuardian(i64, i64),
+    #[error("{0} tried to link their own account")]
+    CannotLinkYourself(i64),
+    #[error("Profile field {0} is too long")]
+    ProfileFieldTooLong(&'static str),
 }
 
 impl UsersManager {
-    pub fn new(connection: Arc<Mutex<Connection>>) -> Result<Self, UsersManagerError> {
+    pub fn new(
+        connection: Arc<Mutex<Connection>>,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, UsersManagerError> {
         Self::init_database(&connection)?;
-        Ok(Self { connection })
+        Ok(Self { connection, clock })
     }
 
     fn init_database(connection: &Arc<Mutex<Connection>>) -> Result<(), UsersManagerError> {
         let connection = connection.lock().unwrap();
         connection.execute_batch("CREATE TABLE IF NOT EXISTS users (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT UNIQUE NOT NULL, password BLOB NOT NULL, \
         salt BLOB NOT NULL, picture TEXT, dateOfBirth LONG)")?;
+        add_column_if_missing(&connection, "users", "timeZone", "TEXT")?;
+        add_column_if_missing(
+            &connection,
+            "users",
+            "reminders",
+            "INTEGER NOT NULL DEFAULT FALSE",
+        )?;
+        add_column_if_missing(
+            &connection,
+            "users",
+            "reminderDays",
+            "INTEGER NOT NULL DEFAULT 7",
+        )?;
+        add_column_if_missing(&connection, "users", "displayName", "TEXT")?;
+        add_column_if_missing(
+            &connection,
+            "users",
+            "discoverable",
+            "INTEGER NOT NULL DEFAULT FALSE",
+        )?;
+        add_column_if_missing(
+            &connection,
+            "users",
+            "dependent",
+            "INTEGER NOT NULL DEFAULT FALSE",
+        )?;
+        //When the account will be deleted, NULL unless its owner asked for it
+        add_column_if_missing(&connection, "users", "deletionAt", "INTEGER")?;
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS guardians (guardianId INTEGER NOT NULL, dependentId INTEGER NOT NULL, UNIQUE(guardianId, dependentId), \
+        FOREIGN KEY(guardianId) REFERENCES users(id), FOREIGN KEY(dependentId) REFERENCES users(id))")?;
+        //Each account consents to the link separately, the accounts being linked once both did
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS accountLinks (userId INTEGER NOT NULL, linkedId INTEGER NOT NULL, UNIQUE(userId, linkedId), \
+        FOREIGN KEY(userId) REFERENCES users(id), FOREIGN KEY(linkedId) REFERENCES users(id))")?;
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS profileFields (userId INTEGER NOT NULL, field TEXT NOT NULL, value TEXT NOT NULL, \
+        visible INTEGER NOT NULL DEFAULT TRUE, UNIQUE(userId, field), FOREIGN KEY(userId) REFERENCES users(id))")?;
         connection.execute_batch("CREATE TABLE IF NOT EXISTS reset_password (userId INTEGER NOT NULL, uuid TEXT NOT NULL, expiry INTEGER NOT NULL, FOREIGN KEY(userId) REFERENCES users(id))")?;
         Ok(())
     }
 
     pub fn add_user(&self, name: &str, password: &str) -> Result<i64, UsersManagerError> {
         let connection = self.connection.lock().unwrap();
+        Self::static_add_user(&connection, name, password)
+    }
+
+    pub fn static_add_user(
+        connection: &Connection,
+        name: &str,
+        password: &str,
+    ) -> Result<i64, UsersManagerError> {
         let (encoded_password, salt) = Self::generate_salt_and_encoded_password(password);
         let inserted = connection.execute("INSERT INTO users (name,password,salt,picture,dateOfBirth) VALUES (?, ?, ?, null, null) ON CONFLICT DO NOTHING", params![name, encoded_password, salt])?;
         if inserted != 1 {
             return Err(UsersManagerError::UserAlreadyExist(name.to_string()));
         }
-        drop(connection);
-        Ok(self.get_user(name)?.id)
+        Ok(connection.last_insert_rowid())
     }
 
-    pub fn edit_user(
+    /**
+    A dependent account has a random password so nobody can log in with it, its guardians
+    switching to it instead. It cannot be found by searching.
+    **/
+    pub fn add_dependent(
         &self,
-        user_id: i64,
+        guardian_id: i64,
         name: &str,
-        picture: &Option<String>,
-        date_of_birth: &Option<i64>,
+        date_of_birth: Option<i64>,
+    ) -> Result<i64, UsersManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+        if Self::is_dependent(&connection, guardian_id)? {
+            return Err(UsersManagerError::DependentAccount(guardian_id.to_string()));
+        }
+        let (encoded_password, salt) =
+            Self::generate_salt_and_encoded_password(&Uuid::new_v4().to_string());
+
+        let transaction = connection.transaction()?;
+        let inserted = transaction.execute("INSERT INTO users (name,password,salt,picture,dateOfBirth,discoverable,dependent) VALUES (?, ?, ?, null, ?, FALSE, TRUE) ON CONFLICT DO NOTHING", params![name, encoded_password, salt, date_of_birth])?;
+        if inserted != 1 {
+            return Err(UsersManagerError::UserAlreadyExist(name.to_string()));
+        }
+        let dependent_id = transaction.last_insert_rowid();
+        transaction.execute(
+            "INSERT INTO guardians(guardianId, dependentId) VALUES (?, ?)",
+            params![guardian_id, dependent_id],
+        )?;
+        transaction.commit()?;
+        Ok(dependent_id)
+    }
+
+    pub fn get_dependents(&self, guardian_id: i64) -> Result<Vec<CleanUser>, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        Self::get_clean_users(&connection, "SELECT u.id, u.name, u.picture, u.dateOfBirth FROM guardians g JOIN users u ON u.id=g.dependentId WHERE g.guardianId=? ORDER BY u.name", guardian_id)
+    }
+
+    pub fn get_guardians(&self, dependent_id: i64) -> Result<Vec<CleanUser>, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        Self::get_clean_users(&connection, "SELECT u.id, u.name, u.picture, u.dateOfBirth FROM guardians g JOIN users u ON u.id=g.guardianId WHERE g.dependentId=? ORDER BY u.name", dependent_id)
+    }
+
+    fn get_clean_users(
+        connection: &Connection,
+        query: &str,
+        user_id: i64,
+    ) -> Result<Vec<CleanUser>, UsersManagerError> {
+        let mut statement = connection.prepare(query)?;
+        let rows = statement.query_map(params![user_id], |row| CleanUser::try_from(row))?;
+        let mut users = Vec::new();
+        for row in rows {
+            users.push(row?);
+        }
+        Ok(users)
+    }
+
+    pub fn is_guardian(
+        &self,
+        guardian_id: i64,
+        dependent_id: i64,
+    ) -> Result<bool, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT 1 FROM guardians WHERE guardianId=? AND dependentId=?")?;
+        Ok(statement.exists(params![guardian_id, dependent_id])?)
+    }
+
+    /**
+    Share a dependent account with another user, who cannot be a dependent themselves
+    **/
+    pub fn add_guardian(
+        &self,
+        dependent_id: i64,
+        guardian_name: &str,
+    ) -> Result<i64, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let guardian = Self::static_get_user(&connection, guardian_name)?;
+        if Self::is_dependent(&connection, guardian.id)? {
+            return Err(UsersManagerError::DependentAccount(guardian.name));
+        }
+        connection.execute(
+            "INSERT OR IGNORE INTO guardians(guardianId, dependentId) VALUES (?, ?)",
+            params![guardian.id, dependent_id],
+        )?;
+        Ok(guardian.id)
+    }
+
+    /**
+    A dependent account always keeps at least one guardian
+    **/
+    pub fn remove_guardian(
+        &self,
+        dependent_id: i64,
+        guardian_id: i64,
     ) -> Result<(), UsersManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let count = transaction.execute(
+            "DELETE FROM guardians WHERE guardianId=? AND dependentId=?",
+            params![guardian_id, dependent_id],
+        )?;
+        if count == 0 {
+            return Err(UsersManagerError::UnknownUser(guardian_id.to_string()));
+        }
+        let remaining: i64 = transaction.query_row(
+            "SELECT COUNT(*) FROM guardians WHERE dependentId=?",
+            params![dependent_id],
+            |row| row.get(0),
+        )?;
+        if remaining == 0 {
+            return Err(UsersManagerError::LastGuardian(guardian_id, dependent_id));
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /**
+    Consent to link our account with another one, the accounts being linked once they consent too
+    **/
+    pub fn link_account(&self, user_id: i64, other_name: &str) -> Result<i64, UsersManagerError> {
         let connection = self.connection.lock().unwrap();
-        let updated = connection.execute(
-            "UPDATE users SET name=?, picture=?, dateOfBirth=? WHERE id=?",
-            params![name, picture, date_of_birth, user_id],
+        let other = Self::static_get_user(&connection, other_name)?;
+        if other.id == user_id {
+            return Err(UsersManagerError::CannotLinkYourself(user_id));
+        }
+        if Self::is_dependent(&connection, user_id)? {
+            return Err(UsersManagerError::DependentAccount(user_id.to_string()));
+        }
+        if Self::is_dependent(&connection, other.id)? {
+            return Err(UsersManagerError::DependentAccount(other.name));
+        }
+        connection.execute(
+            "INSERT OR IGNORE INTO accountLinks(userId, linkedId) VALUES (?, ?)",
+            params![user_id, other.id],
         )?;
-        if updated != 1 {
-            return Err(UsersManagerError::UserAlreadyExist(name.to_string()));
+        Ok(other.id)
+    }
+
+    /**
+    Remove the link, or the consent if the other account did not consent yet, in both directions
+    **/
+    pub fn unlink_account(&self, user_id: i64, other_id: i64) -> Result<(), UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let count = connection.execute(
+            "DELETE FROM accountLinks WHERE (userId=?1 AND linkedId=?2) OR (userId=?2 AND linkedId=?1)",
+            params![user_id, other_id],
+        )?;
+        if count == 0 {
+            return Err(UsersManagerError::UnknownUser(other_id.to_string()));
         }
         Ok(())
     }
 
+    pub fn get_linked_accounts(&self, user_id: i64) -> Result<LinkedAccounts, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let linked = Self::get_clean_users(&connection, "SELECT u.id, u.name, u.picture, u.dateOfBirth FROM accountLinks l JOIN users u ON u.id=l.linkedId \
+            WHERE l.userId=?1 AND EXISTS (SELECT 1 FROM accountLinks r WHERE r.userId=l.linkedId AND r.linkedId=?1) ORDER BY u.name", user_id)?;
+        let sent = Self::get_clean_users(&connection, "SELECT u.id, u.name, u.picture, u.dateOfBirth FROM accountLinks l JOIN users u ON u.id=l.linkedId \
+            WHERE l.userId=?1 AND NOT EXISTS (SELECT 1 FROM accountLinks r WHERE r.userId=l.linkedId AND r.linkedId=?1) ORDER BY u.name", user_id)?;
+        let received = Self::get_clean_users(&connection, "SELECT u.id, u.name, u.picture, u.dateOfBirth FROM accountLinks l JOIN users u ON u.id=l.userId \
+            WHERE l.linkedId=?1 AND NOT EXISTS (SELECT 1 FROM accountLinks r WHERE r.userId=?1 AND r.linkedId=l.userId) ORDER BY u.name", user_id)?;
+        Ok(LinkedAccounts {
+            linked,
+            sent,
+            received,
+        })
+    }
+
+    pub(crate) fn is_dependent(
+        connection: &Connection,
+        user_id: i64,
+    ) -> Result<bool, UsersManagerError> {
+        let mut statement =
+            connection.prepare("SELECT 1 FROM users WHERE id=? AND dependent=TRUE")?;
+        Ok(statement.exists(params![user_id])?)
+    }
+
+    /**
+    Only the supplied fields are changed
+    **/
+    pub fn edit_user(&self, user_id: i64, user: &EditUser) -> Result<(), UsersManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        if let Some(name) = &user.name {
+            let updated = transaction
+                .execute("UPDATE users SET name=? WHERE id=?", params![name, user_id])?;
+            if updated != 1 {
+                return Err(UsersManagerError::UserAlreadyExist(name.to_string()));
+            }
+        }
+        if let Some(picture) = &user.picture {
+            transaction.execute(
+                "UPDATE users SET picture=? WHERE id=?",
+                params![picture, user_id],
+            )?;
+        }
+        if let Some(date_of_birth) = user.date_of_birth {
+            transaction.execute(
+                "UPDATE users SET dateOfBirth=? WHERE id=?",
+                params![date_of_birth, user_id],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
     pub fn get_user(&self, name: &str) -> Result<DbUser, UsersManagerError> {
         let connection = self.connection.lock().unwrap();
         Self::static_get_user(&connection, name)
     }
 
+    pub fn get_name(&self, user_id: i64) -> Result<String, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "SELECT name FROM users WHERE id=?",
+                params![user_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or(UsersManagerError::UnknownUser(user_id.to_string()))
+    }
+
+    pub fn get_account(&self, user_id: i64) -> Result<Account, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "SELECT id, name, picture, dateOfBirth, dependent, deletionAt FROM users WHERE id=?",
+                params![user_id],
+                |row| {
+                    Ok(Account {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        picture: row.get(2)?,
+                        date_of_birth: row.get(3)?,
+                        dependent: row.get(4)?,
+                        deletion_at: row.get(5)?,
+                    })
+                },
+            )
+            .optional()?
+            .ok_or(UsersManagerError::UnknownUser(user_id.to_string()))
+    }
+
     pub fn static_get_user(
         connection: &Connection,
         name: &str,
@@ -87,12 +373,216 @@ impl UsersManager {
         Ok(user)
     }
 
+    pub fn get_settings(&self, user_id: i64) -> Result<UserSettings, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let settings = connection.query_row(
+            "SELECT timeZone, reminders, reminderDays, discoverable FROM users WHERE id=?",
+            params![user_id],
+            |row| {
+                Ok(UserSettings {
+                    time_zone: row.get(0)?,
+                    reminders: row.get(1)?,
+                    reminder_days: row.get(2)?,
+                    discoverable: row.get(3)?,
+                })
+            },
+        )?;
+        Ok(settings)
+    }
+
+    /**
+    Only the supplied settings are changed
+    **/
+    pub fn edit_settings(
+        &self,
+        user_id: i64,
+        settings: &EditSettings,
+    ) -> Result<(), UsersManagerError> {
+        if let Some(time_zone) = &settings.time_zone {
+            if time_zone.parse::<Tz>().is_err() {
+                return Err(UsersManagerError::UnknownTimeZone(time_zone.to_string()));
+            }
+        }
+        if let Some(reminder_days) = settings.reminder_days {
+            if reminder_days == 0 || reminder_days > MAX_REMINDER_DAYS {
+                return Err(UsersManagerError::InvalidReminderDays(reminder_days));
+            }
+        }
+
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        if let Some(time_zone) = &settings.time_zone {
+            transaction.execute(
+                "UPDATE users SET timeZone=? WHERE id=?",
+                params![time_zone, user_id],
+            )?;
+        }
+        if let Some(reminders) = settings.reminders {
+            transaction.execute(
+                "UPDATE users SET reminders=? WHERE id=?",
+                params![reminders, user_id],
+            )?;
+        }
+        if let Some(reminder_days) = settings.reminder_days {
+            transaction.execute(
+                "UPDATE users SET reminderDays=? WHERE id=?",
+                params![reminder_days, user_id],
+            )?;
+        }
+        if let Some(discoverable) = settings.discoverable {
+            transaction.execute(
+                "UPDATE users SET discoverable=? WHERE id=?",
+                params![discoverable, user_id],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /**
+    Friends only get the fields we made visible to them
+    **/
+    pub fn get_profile(
+        &self,
+        user_id: i64,
+        only_visible: bool,
+    ) -> Result<Profile, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut profile = Profile {
+            display_name: connection.query_row(
+                "SELECT displayName FROM users WHERE id=?",
+                params![user_id],
+                |row| row.get(0),
+            )?,
+            ..Default::default()
+        };
+
+        let mut statement = connection.prepare(
+            "SELECT field, value, visible FROM profileFields WHERE userId=?1 AND (visible=TRUE OR ?2=FALSE)",
+        )?;
+        let rows = statement.query_map(params![user_id, only_visible], |row| {
+            <(String, String, bool)>::try_from(row)
+        })?;
+        for row in rows {
+            let (field, value, visible) = row?;
+            if let Some(profile_field) = profile.field_mut(&field) {
+                *profile_field = Some(ProfileField { value, visible });
+            }
+        }
+        Ok(profile)
+    }
+
+    /**
+    Only the supplied fields are changed, an empty value removing the field or the display name
+    **/
+    pub fn edit_profile(
+        &self,
+        user_id: i64,
+        profile: &EditProfile,
+    ) -> Result<(), UsersManagerError> {
+        let display_name_length = profile
+            .display_name
+            .as_ref()
+            .map_or(0, |name| name.trim().chars().count());
+        if display_name_length > MAX_PROFILE_FIELD_LENGTH {
+            return Err(UsersManagerError::ProfileFieldTooLong("displayName"));
+        }
+        for (field, edit) in profile.fields() {
+            let max_length = if field == "bio" {
+                MAX_BIO_LENGTH
+            } else {
+                MAX_PROFILE_FIELD_LENGTH
+            };
+            let length = edit
+                .and_then(|edit| edit.value.as_ref())
+                .map_or(0, |value| value.trim().chars().count());
+            if length > max_length {
+                return Err(UsersManagerError::ProfileFieldTooLong(field));
+            }
+        }
+
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        if let Some(display_name) = &profile.display_name {
+            let display_name = Some(display_name.trim()).filter(|name| !name.is_empty());
+            transaction.execute(
+                "UPDATE users SET displayName=? WHERE id=?",
+                params![display_name, user_id],
+            )?;
+        }
+        for (field, edit) in profile.fields() {
+            let Some(edit) = edit else {
+                continue;
+            };
+            match edit.value.as_ref().map(|value| value.trim()) {
+                Some("") => {
+                    transaction.execute(
+                        "DELETE FROM profileFields WHERE userId=? AND field=?",
+                        params![user_id, field],
+                    )?;
+                }
+                Some(value) => {
+                    transaction.execute(
+                        "INSERT INTO profileFields(userId, field, value, visible) VALUES (?1, ?2, ?3, COALESCE(?4, TRUE)) \
+                        ON CONFLICT(userId, field) DO UPDATE SET value=?3, visible=COALESCE(?4, visible)",
+                        params![user_id, field, value, edit.visible],
+                    )?;
+                }
+                None => {
+                    if let Some(visible) = edit.visible {
+                        transaction.execute(
+                            "UPDATE profileFields SET visible=? WHERE userId=? AND field=?",
+                            params![visible, user_id, field],
+                        )?;
+                    }
+                }
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /**
+    Users that want to be reminded of the events, with how many days before
+    **/
+    pub fn get_users_with_reminders(&self) -> Result<Vec<(i64, u32)>, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT id, reminderDays FROM users WHERE reminders=TRUE")?;
+        let rows = statement.query_map(params![], |row| <(i64, u32)>::try_from(row))?;
+        let mut users = Vec::new();
+        for row in rows {
+            users.push(row?);
+        }
+        Ok(users)
+    }
+
+    /**
+    Time zone in which the user lives, UTC if never set
+    **/
+    pub fn static_get_time_zone(
+        connection: &Connection,
+        user_id: i64,
+    ) -> Result<Tz, UsersManagerError> {
+        let time_zone = connection.query_row(
+            "SELECT timeZone FROM users WHERE id=?",
+            params![user_id],
+            |row| row.get::<_, Option<String>>(0),
+        )?;
+        Ok(time_zone
+            .and_then(|time_zone| time_zone.parse().ok())
+            .unwrap_or(Tz::UTC))
+    }
+
     pub fn check_password(
         &self,
         name: &str,
         password: &str,
     ) -> Result<CleanUser, UsersManagerError> {
         let user = self.get_user(name)?;
+        if Self::is_dependent(&self.connection.lock().unwrap(), user.id)? {
+            return Err(UsersManagerError::PasswordMismatch);
+        }
 
         if Self::encode_password(password, &user.salt) == user.encoded_password {
             Ok(user.into())
@@ -116,15 +606,13 @@ impl UsersManager {
         let user = self.get_user(user_name)?;
 
         let connection = self.connection.lock().unwrap();
+        if Self::is_dependent(&connection, user.id)? {
+            return Err(UsersManagerError::DependentAccount(user.name));
+        }
         connection.execute("DELETE FROM reset_password WHERE userId=?", [user.id])?;
 
         let uuid = Uuid::new_v4().to_string();
-        let expiry = SystemTime::now()
-            .checked_add(Duration::from_secs(60 * 60))
-            .unwrap()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        let expiry = self.clock.now().timestamp() + 60 * 60;
         connection.execute(
             "INSERT INTO reset_password(userId, uuid, expiry) VALUES (?,?,?)",
             params![user.id, &uuid, expiry],
@@ -132,6 +620,132 @@ impl UsersManager {
         Ok(uuid)
     }
 
+    /**
+    Unlike a reset, the current password is needed
+    **/
+    pub fn change_password(
+        &self,
+        user_id: i64,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<(), UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let (encoded_password, salt) = connection.query_row(
+            "SELECT password, salt FROM users WHERE id=?",
+            params![user_id],
+            |row| <([u8; 32], [u8; 16])>::try_from(row),
+        )?;
+        if Self::encode_password(current_password, &salt) != encoded_password {
+            return Err(UsersManagerError::PasswordMismatch);
+        }
+
+        let (encoded_password, salt) = Self::generate_salt_and_encoded_password(new_password);
+        connection.execute(
+            "UPDATE users SET password=?, salt=? WHERE id=?",
+            params![encoded_password, salt, user_id],
+        )?;
+        connection.execute("DELETE FROM reset_password WHERE userId=?", [user_id])?;
+        Ok(())
+    }
+
+    /**
+    The account is deleted after a grace period during which the deletion can be cancelled.
+    Return when it will be deleted.
+    **/
+    pub fn request_deletion(&self, user_id: i64) -> Result<i64, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let deletion_at = (self.clock.now() + Duration::days(DELETION_GRACE_DAYS)).timestamp();
+        connection.execute(
+            "UPDATE users SET deletionAt=COALESCE(deletionAt, ?) WHERE id=?",
+            params![deletion_at, user_id],
+        )?;
+        Self::static_get_deletion(&connection, user_id)?
+            .ok_or(UsersManagerError::UnknownUser(user_id.to_string()))
+    }
+
+    pub fn cancel_deletion(&self, user_id: i64) -> Result<(), UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "UPDATE users SET deletionAt=NULL WHERE id=?",
+            params![user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_deletion(&self, user_id: i64) -> Result<Option<i64>, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        Self::static_get_deletion(&connection, user_id)
+    }
+
+    fn static_get_deletion(
+        connection: &Connection,
+        user_id: i64,
+    ) -> Result<Option<i64>, UsersManagerError> {
+        Ok(connection
+            .query_row(
+                "SELECT deletionAt FROM users WHERE id=?",
+                params![user_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten())
+    }
+
+    /**
+    Accounts whose grace period is over
+    **/
+    pub fn get_accounts_to_delete(&self) -> Result<Vec<i64>, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT id FROM users WHERE deletionAt<=?")?;
+        let rows = statement.query_map(params![self.clock.now().timestamp()], |row| row.get(0))?;
+        let mut users = Vec::new();
+        for row in rows {
+            users.push(row?);
+        }
+        Ok(users)
+    }
+
+    /**
+    Dependents that would be left without guardian when the user is deleted
+    **/
+    pub fn get_sole_dependents(&self, guardian_id: i64) -> Result<Vec<i64>, UsersManagerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT dependentId FROM guardians g WHERE guardianId=?1 \
+            AND NOT EXISTS (SELECT 1 FROM guardians o WHERE o.dependentId=g.dependentId AND o.guardianId!=?1)",
+        )?;
+        let rows = statement.query_map(params![guardian_id], |row| row.get(0))?;
+        let mut users = Vec::new();
+        for row in rows {
+            users.push(row?);
+        }
+        Ok(users)
+    }
+
+    /**
+    Last step of an account deletion, once the other managers removed what refers to the user
+    **/
+    pub fn delete_user(&self, user_id: i64) -> Result<(), UsersManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "DELETE FROM guardians WHERE guardianId=?1 OR dependentId=?1",
+            params![user_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM accountLinks WHERE userId=?1 OR linkedId=?1",
+            params![user_id],
+        )?;
+        transaction.execute("DELETE FROM profileFields WHERE userId=?", params![user_id])?;
+        transaction.execute(
+            "DELETE FROM reset_password WHERE userId=?",
+            params![user_id],
+        )?;
+        transaction.execute("DELETE FROM users WHERE id=?", params![user_id])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
     pub fn change_user_password(
         &self,
         user_id: i64,
@@ -151,12 +765,7 @@ impl UsersManager {
             return Ok(());
         };
 
-        if SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            > expiry as u64
-        {
+        if self.clock.now().timestamp() > expiry {
             error!("Took too long to reset the password for {uuid} and user {user_id}");
             connection.execute("DELETE FROM reset_password WHERE uuid=?", [uuid])?;
             connection.execute("DELETE FROM reset_password WHERE userId=?", [user_id])?;
@@ -223,6 +832,139 @@ impl<'a> TryFrom<&Row<'a>> for DbUser {
     }
 }
 
+/**
+The user row, without the password
+**/
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Eq, PartialEq, Debug))]
+pub(crate) struct Account {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+    pub(crate) picture: Option<String>,
+    pub(crate) date_of_birth: Option<i64>,
+    pub(crate) dependent: bool,
+    pub(crate) deletion_at: Option<i64>,
+}
+
+/**
+A missing field is kept, while null removes the picture or the date of birth
+**/
+#[derive(Deserialize, Default)]
+pub(crate) struct EditUser {
+    pub(crate) name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub(crate) picture: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub(crate) date_of_birth: Option<Option<i64>>,
+}
+
+/**
+Tell a null field, deserialized as `Some(None)`, from a missing one
+**/
+fn deserialize_some<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error> {
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Eq, PartialEq, Debug))]
+pub(crate) struct UserSettings {
+    pub(crate) time_zone: Option<String>,
+    pub(crate) reminders: bool,
+    pub(crate) reminder_days: u32,
+    /** Whether other users can find us by searching **/
+    pub(crate) discoverable: bool,
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) struct EditSettings {
+    pub(crate) time_zone: Option<String>,
+    pub(crate) reminders: Option<bool>,
+    pub(crate) reminder_days: Option<u32>,
+    pub(crate) discoverable: Option<bool>,
+}
+
+#[derive(Serialize, Default)]
+#[cfg_attr(test, derive(Eq, PartialEq, Debug))]
+pub(crate) struct Profile {
+    /** Shown and searched besides the login name **/
+    pub(crate) display_name: Option<String>,
+    pub(crate) bio: Option<ProfileField>,
+    pub(crate) clothing_size: Option<ProfileField>,
+    pub(crate) shoe_size: Option<ProfileField>,
+    pub(crate) ring_size: Option<ProfileField>,
+    pub(crate) favourite_colors: Option<ProfileField>,
+    pub(crate) favourite_brands: Option<ProfileField>,
+    /** What we do not want to receive **/
+    pub(crate) please_no: Option<ProfileField>,
+}
+
+impl Profile {
+    fn field_mut(&mut self, field: &str) -> Option<&mut Option<ProfileField>> {
+        match field {
+            "bio" => Some(&mut self.bio),
+            "clothingSize" => Some(&mut self.clothing_size),
+            "shoeSize" => Some(&mut self.shoe_size),
+            "ringSize" => Some(&mut self.ring_size),
+            "favouriteColors" => Some(&mut self.favourite_colors),
+            "favouriteBrands" => Some(&mut self.favourite_brands),
+            "pleaseNo" => Some(&mut self.please_no),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Eq, PartialEq, Debug))]
+pub(crate) struct ProfileField {
+    pub(crate) value: String,
+    /** Whether our friends can see it **/
+    pub(crate) visible: bool,
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) struct EditProfile {
+    pub(crate) display_name: Option<String>,
+    pub(crate) bio: Option<EditProfileField>,
+    pub(crate) clothing_size: Option<EditProfileField>,
+    pub(crate) shoe_size: Option<EditProfileField>,
+    pub(crate) ring_size: Option<EditProfileField>,
+    pub(crate) favourite_colors: Option<EditProfileField>,
+    pub(crate) favourite_brands: Option<EditProfileField>,
+    pub(crate) please_no: Option<EditProfileField>,
+}
+
+impl EditProfile {
+    fn fields(&self) -> [(&'static str, Option<&EditProfileField>); 7] {
+        [
+            ("bio", self.bio.as_ref()),
+            ("clothingSize", self.clothing_size.as_ref()),
+            ("shoeSize", self.shoe_size.as_ref()),
+            ("ringSize", self.ring_size.as_ref()),
+            ("favouriteColors", self.favourite_colors.as_ref()),
+            ("favouriteBrands", self.favourite_brands.as_ref()),
+            ("pleaseNo", self.please_no.as_ref()),
+        ]
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) struct EditProfileField {
+    pub(crate) value: Option<String>,
+    pub(crate) visible: Option<bool>,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Eq, PartialEq, Debug))]
+pub(crate) struct LinkedAccounts {
+    pub(crate) linked: Vec<CleanUser>,
+    /** Accounts we consented to link with, waiting for them **/
+    pub(crate) sent: Vec<CleanUser>,
+    /** Accounts that consented to link with us **/
+    pub(crate) received: Vec<CleanUser>,
+}
+
 #[derive(Serialize)]
 #[cfg_attr(test, derive(Eq, PartialEq, Debug))]
 pub(crate) struct CleanUser {
@@ -258,14 +1000,19 @@ impl<'a> TryFrom<&Row<'a>> for CleanUser {
 
 #[cfg(test)]
 mod test {
+    use crate::clock::{SystemClock, TestClock};
     use crate::managers::test_helper::create_test_database;
-    use crate::managers::users_manager::{UsersManager, UsersManagerError};
+    use crate::managers::users_manager::{
+        CleanUser, EditProfile, EditProfileField, EditSettings, EditUser, LinkedAccounts, Profile,
+        ProfileField, UserSettings, UsersManager, UsersManagerError,
+    };
+    use chrono::{Duration, TimeZone, Utc};
     use std::sync::{Arc, Mutex};
 
     #[test]
     fn test_add_user() {
         let connection = Arc::new(Mutex::new(create_test_database("test_add_user")));
-        let users_manager = UsersManager::new(connection).unwrap();
+        let users_manager = UsersManager::new(connection, Arc::new(SystemClock)).unwrap();
 
         let id = users_manager.add_user("test", "strong_pwd").unwrap();
         assert_eq!(id, 1);
@@ -285,10 +1032,38 @@ mod test {
         assert!(matches!(error, UsersManagerError::UserAlreadyExist(_)));
     }
 
+    #[test]
+    fn test_edit_user() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_edit_user")));
+        let users_manager = UsersManager::new(connection, Arc::new(SystemClock)).unwrap();
+        let id = users_manager.add_user("test", "pwd").unwrap();
+        users_manager.add_user("other", "pwd").unwrap();
+
+        let edit_user: EditUser =
+            serde_json::from_str(r#"{"picture": "a.png", "date_of_birth": 645408000}"#).unwrap();
+        users_manager.edit_user(id, &edit_user).unwrap();
+        //Only the name is changed
+        let edit_user: EditUser = serde_json::from_str(r#"{"name": "renamed"}"#).unwrap();
+        users_manager.edit_user(id, &edit_user).unwrap();
+        let user = users_manager.get_user("renamed").unwrap();
+        assert!(
+            user.test_eq(id, "renamed", Some("a.png".to_string()), Some(645408000)),
+            "Got: {user:?}"
+        );
+
+        let edit_user: EditUser = serde_json::from_str(r#"{"picture": null}"#).unwrap();
+        users_manager.edit_user(id, &edit_user).unwrap();
+        let user = users_manager.get_user("renamed").unwrap();
+        assert!(
+            user.test_eq(id, "renamed", None, Some(645408000)),
+            "Got: {user:?}"
+        );
+    }
+
     #[test]
     fn test_get_unknown_user() {
         let connection = Arc::new(Mutex::new(create_test_database("test_get_unknown_user")));
-        let users_manager = UsersManager::new(connection).unwrap();
+        let users_manager = UsersManager::new(connection, Arc::new(SystemClock)).unwrap();
 
         users_manager.add_user("test", "strong_pwd").unwrap();
         let user = users_manager.get_user("test").unwrap();
@@ -302,7 +1077,7 @@ mod test {
     #[test]
     fn test_change_password() {
         let connection = Arc::new(Mutex::new(create_test_database("test_change_password")));
-        let users_manager = UsersManager::new(connection).unwrap();
+        let users_manager = UsersManager::new(connection, Arc::new(SystemClock)).unwrap();
 
         let id = users_manager.add_user("test", "strong_pwd").unwrap();
         assert_eq!(id, 1);
@@ -319,12 +1094,31 @@ mod test {
         assert!(users_manager.check_password("test", "strong_pwd").is_err());
     }
 
+    #[test]
+    fn test_change_own_password() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_change_own_password")));
+        let users_manager = UsersManager::new(connection, Arc::new(SystemClock)).unwrap();
+        let id = users_manager.add_user("test", "strong_pwd").unwrap();
+
+        let error = users_manager
+            .change_password(id, "wrong_pwd", "new_strong")
+            .unwrap_err();
+        assert!(matches!(error, UsersManagerError::PasswordMismatch));
+        assert!(users_manager.check_password("test", "strong_pwd").is_ok());
+
+        users_manager
+            .change_password(id, "strong_pwd", "new_strong")
+            .unwrap();
+        assert!(users_manager.check_password("test", "new_strong").is_ok());
+        assert!(users_manager.check_password("test", "strong_pwd").is_err());
+    }
+
     #[test]
     fn test_change_password_unknown_uuid() {
         let connection = Arc::new(Mutex::new(create_test_database(
             "test_change_password_unknown_uuid",
         )));
-        let users_manager = UsersManager::new(connection).unwrap();
+        let users_manager = UsersManager::new(connection, Arc::new(SystemClock)).unwrap();
 
         let id = users_manager.add_user("test", "strong_pwd").unwrap();
         assert_eq!(id, 1);
@@ -338,4 +1132,365 @@ mod test {
         assert!(users_manager.check_password("test", "new_strong").is_err());
         assert!(users_manager.check_password("test", "strong_pwd").is_ok());
     }
+
+    #[test]
+    fn test_change_password_expiry() {
+        let connection = Arc::new(Mutex::new(create_test_database(
+            "test_change_password_expiry",
+        )));
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 12, 31, 23, 30, 0).unwrap(),
+        ));
+        let users_manager = UsersManager::new(connection, clock.clone()).unwrap();
+
+        let id = users_manager.add_user("test", "strong_pwd").unwrap();
+
+        //Still valid at the last second
+        let uuid = users_manager.create_password_reset_request("test").unwrap();
+        clock.advance(Duration::seconds(60 * 60));
+        users_manager
+            .change_user_password(id, &uuid, "new_strong")
+            .unwrap();
+        assert!(users_manager.check_password("test", "new_strong").is_ok());
+
+        //Expired one second later, and cannot be used anymore
+        let uuid = users_manager.create_password_reset_request("test").unwrap();
+        clock.advance(Duration::seconds(60 * 60 + 1));
+        users_manager
+            .change_user_password(id, &uuid, "newer_strong")
+            .unwrap();
+        assert!(users_manager
+            .check_password("test", "newer_strong")
+            .is_err());
+        assert!(users_manager.check_password("test", "new_strong").is_ok());
+        clock.advance(Duration::seconds(-10));
+        users_manager
+            .change_user_password(id, &uuid, "newer_strong")
+            .unwrap();
+        assert!(users_manager
+            .check_password("test", "newer_strong")
+            .is_err());
+    }
+
+    #[test]
+    fn test_settings() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_settings")));
+        let users_manager = UsersManager::new(connection, Arc::new(SystemClock)).unwrap();
+
+        let id = users_manager.add_user("test", "strong_pwd").unwrap();
+        let other = users_manager.add_user("other", "strong_pwd").unwrap();
+        assert_eq!(
+            users_manager.get_settings(id).unwrap(),
+            UserSettings {
+                time_zone: None,
+                reminders: false,
+                reminder_days: 7,
+                discoverable: false
+            }
+        );
+
+        users_manager
+            .edit_settings(
+                id,
+                &EditSettings {
+                    time_zone: Some("Europe/Paris".to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        users_manager
+            .edit_settings(
+                id,
+                &EditSettings {
+                    reminders: Some(true),
+                    reminder_days: Some(3),
+                    discoverable: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            users_manager.get_settings(id).unwrap(),
+            UserSettings {
+                time_zone: Some("Europe/Paris".to_string()),
+                reminders: true,
+                reminder_days: 3,
+                discoverable: true
+            }
+        );
+        assert_eq!(
+            users_manager.get_settings(other).unwrap(),
+            UserSettings {
+                time_zone: None,
+                reminders: false,
+                reminder_days: 7,
+                discoverable: false
+            }
+        );
+        assert_eq!(
+            users_manager.get_users_with_reminders().unwrap(),
+            vec![(id, 3)]
+        );
+
+        let error = users_manager
+            .edit_settings(
+                id,
+                &EditSettings {
+                    time_zone: Some("Europe/Nowhere".to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(error, UsersManagerError::UnknownTimeZone(_)));
+        let error = users_manager
+            .edit_settings(
+                id,
+                &EditSettings {
+                    reminder_days: Some(0),
+                    ..Default::default()
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(error, UsersManagerError::InvalidReminderDays(_)));
+    }
+
+    #[test]
+    fn test_dependents() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_dependents")));
+        let users_manager = UsersManager::new(connection, Arc::new(SystemClock)).unwrap();
+        let mum = users_manager.add_user("mum", "pwd").unwrap();
+        let dad = users_manager.add_user("dad", "pwd").unwrap();
+
+        let kid = users_manager
+            .add_dependent(mum, "kid", Some(1_500_000_000))
+            .unwrap();
+        let error = users_manager.add_dependent(dad, "kid", None).unwrap_err();
+        assert!(matches!(error, UsersManagerError::UserAlreadyExist(_)));
+        let error = users_manager.add_dependent(kid, "baby", None).unwrap_err();
+        assert!(matches!(error, UsersManagerError::DependentAccount(_)));
+
+        let expected = CleanUser {
+            id: kid,
+            name: "kid".to_string(),
+            picture: None,
+            date_of_birth: Some(1_500_000_000),
+        };
+        assert_eq!(users_manager.get_dependents(mum).unwrap(), vec![expected]);
+        assert_eq!(users_manager.get_dependents(dad).unwrap(), vec![]);
+        assert!(!users_manager.get_settings(kid).unwrap().discoverable);
+        //Nobody can log in to a dependent account
+        let error = users_manager.check_password("kid", "").unwrap_err();
+        assert!(matches!(error, UsersManagerError::PasswordMismatch));
+        let error = users_manager
+            .create_password_reset_request("kid")
+            .unwrap_err();
+        assert!(matches!(error, UsersManagerError::DependentAccount(_)));
+
+        assert_eq!(users_manager.add_guardian(kid, "dad").unwrap(), dad);
+        assert!(users_manager.is_guardian(dad, kid).unwrap());
+        let error = users_manager.add_guardian(kid, "kid").unwrap_err();
+        assert!(matches!(error, UsersManagerError::DependentAccount(_)));
+        assert_eq!(
+            users_manager
+                .get_guardians(kid)
+                .unwrap()
+                .into_iter()
+                .map(|guardian| guardian.id)
+                .collect::<Vec<_>>(),
+            vec![dad, mum]
+        );
+
+        users_manager.remove_guardian(kid, mum).unwrap();
+        assert!(!users_manager.is_guardian(mum, kid).unwrap());
+        let error = users_manager.remove_guardian(kid, dad).unwrap_err();
+        assert!(matches!(error, UsersManagerError::LastGuardian(_, _)));
+        assert!(users_manager.is_guardian(dad, kid).unwrap());
+    }
+
+    #[test]
+    fn test_link_accounts() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_link_accounts")));
+        let users_manager = UsersManager::new(connection, Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        users_manager.add_dependent(one, "kid", None).unwrap();
+        let clean_user = |id, name: &str| CleanUser {
+            id,
+            name: name.to_string(),
+            picture: None,
+            date_of_birth: None,
+        };
+
+        let error = users_manager.link_account(one, "one").unwrap_err();
+        assert!(matches!(error, UsersManagerError::CannotLinkYourself(_)));
+        let error = users_manager.link_account(one, "kid").unwrap_err();
+        assert!(matches!(error, UsersManagerError::DependentAccount(_)));
+
+        assert_eq!(users_manager.link_account(one, "two").unwrap(), two);
+        assert_eq!(
+            users_manager.get_linked_accounts(one).unwrap(),
+            LinkedAccounts {
+                linked: vec![],
+                sent: vec![clean_user(two, "two")],
+                received: vec![],
+            }
+        );
+        assert_eq!(
+            users_manager.get_linked_accounts(two).unwrap(),
+            LinkedAccounts {
+                linked: vec![],
+                sent: vec![],
+                received: vec![clean_user(one, "one")],
+            }
+        );
+
+        users_manager.link_account(two, "one").unwrap();
+        assert_eq!(
+            users_manager.get_linked_accounts(two).unwrap(),
+            LinkedAccounts {
+                linked: vec![clean_user(one, "one")],
+                sent: vec![],
+                received: vec![],
+            }
+        );
+
+        users_manager.unlink_account(two, one).unwrap();
+        assert_eq!(
+            users_manager.get_linked_accounts(one).unwrap(),
+            LinkedAccounts {
+                linked: vec![],
+                sent: vec![],
+                received: vec![],
+            }
+        );
+        let error = users_manager.unlink_account(two, one).unwrap_err();
+        assert!(matches!(error, UsersManagerError::UnknownUser(_)));
+    }
+
+    #[test]
+    fn test_profile() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_profile")));
+        let users_manager = UsersManager::new(connection, Arc::new(SystemClock)).unwrap();
+        let id = users_manager.add_user("test", "pwd").unwrap();
+        assert_eq!(
+            users_manager.get_profile(id, false).unwrap(),
+            Profile::default()
+        );
+
+        users_manager
+            .edit_profile(
+                id,
+                &EditProfile {
+                    display_name: Some("Tester".to_string()),
+                    bio: Some(EditProfileField {
+                        value: Some(" Likes books ".to_string()),
+                        visible: None,
+                    }),
+                    shoe_size: Some(EditProfileField {
+                        value: Some("42".to_string()),
+                        visible: Some(false),
+                    }),
+                    please_no: Some(EditProfileField {
+                        value: Some("Candles".to_string()),
+                        visible: None,
+                    }),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        //Untouched fields are kept
+        users_manager
+            .edit_profile(
+                id,
+                &EditProfile {
+                    bio: Some(EditProfileField {
+                        value: None,
+                        visible: Some(false),
+                    }),
+                    please_no: Some(EditProfileField {
+                        value: Some("".to_string()),
+                        visible: None,
+                    }),
+                    ring_size: Some(EditProfileField {
+                        value: Some("54".to_string()),
+                        visible: None,
+                    }),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            users_manager.get_profile(id, false).unwrap(),
+            Profile {
+                display_name: Some("Tester".to_string()),
+                bio: Some(ProfileField {
+                    value: "Likes books".to_string(),
+                    visible: false,
+                }),
+                shoe_size: Some(ProfileField {
+                    value: "42".to_string(),
+                    visible: false,
+                }),
+                ring_size: Some(ProfileField {
+                    value: "54".to_string(),
+                    visible: true,
+                }),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            users_manager.get_profile(id, true).unwrap(),
+            Profile {
+                display_name: Some("Tester".to_string()),
+                ring_size: Some(ProfileField {
+                    value: "54".to_string(),
+                    visible: true,
+                }),
+                ..Default::default()
+            }
+        );
+
+        let error = users_manager
+            .edit_profile(
+                id,
+                &EditProfile {
+                    bio: Some(EditProfileField {
+                        value: Some("a".repeat(501)),
+                        visible: None,
+                    }),
+                    ..Default::default()
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            UsersManagerError::ProfileFieldTooLong("bio")
+        ));
+        let error = users_manager
+            .edit_profile(
+                id,
+                &EditProfile {
+                    display_name: Some("a".repeat(201)),
+                    ..Default::default()
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            UsersManagerError::ProfileFieldTooLong("displayName")
+        ));
+        users_manager
+            .edit_profile(
+                id,
+                &EditProfile {
+                    display_name: Some(" ".to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            users_manager.get_profile(id, true).unwrap().display_name,
+            None
+        );
+    }
 }
@@ -1,8 +1,18 @@
+use crate::managers::add_column_if_missing;
+use crate::managers::events_manager::EventKind;
 use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 
+/**
+Condition on a category `j` of a friend being visible to the user `?2`: either it is not
+restricted, or one of its rows is for this user or one of their groups
+**/
+pub(crate) const VISIBLE_CATEGORY: &str = "(NOT EXISTS (SELECT 1 FROM categoryVisibility v WHERE v.userId=j.userId AND v.categoryId=j.categoryId) \
+    OR EXISTS (SELECT 1 FROM categoryVisibility v WHERE v.userId=j.userId AND v.categoryId=j.categoryId \
+        AND (v.friendId=?2 OR v.groupId IN (SELECT groupId FROM friendGroupMembers WHERE friendId=?2))))";
+
 #[derive(Clone)]
 pub struct WishlistManager {
     connection: Arc<Mutex<Connection>>,
@@ -14,6 +24,8 @@ pub(crate) enum WishlistManagerError {
     Sqlite(#[from] rusqlite::Error),
     #[error("Unknown user {0}")]
     UnknownUser(i64),
+    #[error("Pictures of gift {0} do not match")]
+    PicturesMismatch(i64),
 }
 
 impl WishlistManager {
@@ -30,6 +42,43 @@ impl WishlistManager {
         connection.execute_batch("CREATE TABLE IF NOT EXISTS gifts (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, description TEXT, price TEXT, \
             whereToBuy TEXT, picture TEXT, secret INTEGER NOT NULL, heart INTEGER NOT NULL, rank INTEGER NOT NULL, reservedBy INTEGER, categoryId INTEGER NOT NULL, \
             FOREIGN KEY(reservedBy) REFERENCES users(id), FOREIGN KEY(categoryId) REFERENCES categories(id))")?;
+        //Who added a secret gift to the list of a friend, NULL for older gifts
+        add_column_if_missing(&connection, "gifts", "addedBy", "INTEGER")?;
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS categoryEvents (userId INTEGER NOT NULL, categoryId INTEGER NOT NULL, kind TEXT NOT NULL, \
+            UNIQUE(userId, categoryId), FOREIGN KEY(userId) REFERENCES users(id), FOREIGN KEY(categoryId) REFERENCES categories(id))")?;
+        //Gift events used to be shared by the users of a category, they are now kept for each of them
+        let migrate_gift_events = connection
+            .prepare("SELECT 1 FROM pragma_table_info('giftEvents') WHERE name='giftId'")?
+            .exists(params![])?
+            && !connection
+                .prepare("SELECT 1 FROM pragma_table_info('giftEvents') WHERE name='userId'")?
+                .exists(params![])?;
+        if migrate_gift_events {
+            connection.execute_batch("ALTER TABLE giftEvents RENAME TO oldGiftEvents")?;
+        }
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS giftEvents (userId INTEGER NOT NULL, giftId INTEGER NOT NULL, kind TEXT NOT NULL, \
+            UNIQUE(userId, giftId), FOREIGN KEY(userId) REFERENCES users(id), FOREIGN KEY(giftId) REFERENCES gifts(id))")?;
+        if migrate_gift_events {
+            connection.execute_batch(
+                "INSERT INTO giftEvents (userId, giftId, kind) SELECT j.userId, o.giftId, o.kind FROM oldGiftEvents o \
+                JOIN gifts g ON g.id=o.giftId JOIN joinUserAndCategory j ON j.categoryId=g.categoryId; \
+                DROP TABLE oldGiftEvents;",
+            )?;
+        }
+        //A restricted category always has a row without group nor friend, so it stays restricted when its groups and friends are gone
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS categoryVisibility (userId INTEGER NOT NULL, categoryId INTEGER NOT NULL, groupId INTEGER, friendId INTEGER, \
+            FOREIGN KEY(userId) REFERENCES users(id), FOREIGN KEY(categoryId) REFERENCES categories(id), FOREIGN KEY(friendId) REFERENCES users(id))")?;
+        //gifts.picture is only kept to fill the gallery of older databases
+        let migrate_pictures = !connection
+            .prepare("SELECT 1 FROM sqlite_master WHERE type='table' AND name='giftPictures'")?
+            .exists(params![])?;
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS giftPictures (giftId INTEGER NOT NULL, picture TEXT NOT NULL, rank INTEGER NOT NULL, \
+            UNIQUE(giftId, picture), FOREIGN KEY(giftId) REFERENCES gifts(id))")?;
+        if migrate_pictures {
+            connection.execute_batch(
+                "INSERT INTO giftPictures (giftId, picture, rank) SELECT id, picture, 0 FROM gifts WHERE picture IS NOT NULL",
+            )?;
+        }
         Ok(())
     }
 
@@ -105,6 +154,18 @@ impl WishlistManager {
                 "DELETE FROM joinUserAndCategory WHERE userId=? AND categoryId=?",
                 params![user_id, category_id],
             )?;
+            transaction.execute(
+                "DELETE FROM categoryEvents WHERE userId=? AND categoryId=?",
+                params![user_id, category_id],
+            )?;
+            transaction.execute(
+                "DELETE FROM giftEvents WHERE userId=? AND giftId IN (SELECT id FROM gifts WHERE categoryId=?)",
+                params![user_id, category_id],
+            )?;
+            transaction.execute(
+                "DELETE FROM categoryVisibility WHERE userId=? AND categoryId=?",
+                params![user_id, category_id],
+            )?;
         }
         transaction.commit()?;
 
@@ -142,12 +203,32 @@ impl WishlistManager {
             "DELETE FROM joinUserAndCategory WHERE userId=? AND categoryId=?",
             params![user_id, category_id],
         )?;
+        transaction.execute(
+            "DELETE FROM categoryEvents WHERE userId=? AND categoryId=?",
+            params![user_id, category_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM giftEvents WHERE userId=? AND giftId IN (SELECT id FROM gifts WHERE categoryId=?)",
+            params![user_id, category_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM categoryVisibility WHERE userId=? AND categoryId=?",
+            params![user_id, category_id],
+        )?;
         let count = transaction.query_row(
             "SELECT COUNT(userId) FROM joinUserAndCategory WHERE categoryId=?",
             params![category_id],
             |row| row.get::<_, i64>(0),
         )?;
         if count == 0 {
+            transaction.execute(
+                "DELETE FROM giftEvents WHERE giftId IN (SELECT id FROM gifts WHERE categoryId=?)",
+                params![category_id],
+            )?;
+            transaction.execute(
+                "DELETE FROM giftPictures WHERE giftId IN (SELECT id FROM gifts WHERE categoryId=?)",
+                params![category_id],
+            )?;
             transaction.execute("DELETE FROM gifts WHERE categoryId=?", params![category_id])?;
             transaction.execute("DELETE FROM categories WHERE id=?", params![category_id])?;
         }
@@ -156,6 +237,10 @@ impl WishlistManager {
         Ok(())
     }
 
+    /**
+    A gift added by a friend is secret, hidden from the owner of the list
+    **/
+    #[allow(clippy::too_many_arguments)]
     pub fn add_gift(
         &self,
         name: &str,
@@ -163,10 +248,11 @@ impl WishlistManager {
         price: Option<String>,
         where_to_buy: Option<String>,
         picture: Option<String>,
-        secret: bool,
+        added_by: Option<i64>,
         category_id: i64,
-    ) -> Result<(), WishlistManagerError> {
-        let connection = self.connection.lock().unwrap();
+    ) -> Result<i64, WishlistManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let secret = added_by.is_some();
 
         let sql = if secret {
             "SELECT MAX(rank) FROM gifts WHERE categoryId=?"
@@ -184,11 +270,21 @@ impl WishlistManager {
             rank = 100000;
         }
 
-        connection.execute("INSERT INTO gifts (name, description, price, whereToBuy, picture, rank, secret, heart, categoryId) VALUES (?,?,?,?,?,?,?,FALSE,?)", params![name, description, price, where_to_buy, picture, rank+1, secret, category_id])?;
+        let transaction = connection.transaction()?;
+        transaction.execute("INSERT INTO gifts (name, description, price, whereToBuy, rank, secret, heart, categoryId, addedBy) VALUES (?,?,?,?,?,?,FALSE,?,?)", params![name, description, price, where_to_buy, rank+1, secret, category_id, added_by])?;
+        let gift_id = transaction.last_insert_rowid();
+        if let Some(picture) = picture {
+            transaction.execute(
+                "INSERT INTO giftPictures (giftId, picture, rank) VALUES (?,?,0)",
+                params![gift_id, picture],
+            )?;
+        }
+        transaction.commit()?;
 
-        Ok(())
+        Ok(gift_id)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn edit_gift(
         &self,
         gift_id: i64,
@@ -198,13 +294,136 @@ impl WishlistManager {
         where_to_buy: Option<String>,
         picture: Option<String>,
         category_id: i64,
+    ) -> Result<(), WishlistManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute("UPDATE gifts SET name=?, description=?, price=?, whereToBuy=?, categoryId=? WHERE id=?", params![name, description, price, where_to_buy, category_id, gift_id])?;
+        transaction.execute(
+            "DELETE FROM giftEvents WHERE giftId=? AND userId NOT IN (SELECT userId FROM joinUserAndCategory WHERE categoryId=?)",
+            params![gift_id, category_id],
+        )?;
+        Self::set_cover(&transaction, gift_id, picture)?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /**
+    The cover is the first picture of the gallery, replaced by the given picture or removed.
+    When the given picture is already in the gallery, it becomes the cover instead.
+    **/
+    fn set_cover(
+        transaction: &Transaction,
+        gift_id: i64,
+        picture: Option<String>,
+    ) -> Result<(), WishlistManagerError> {
+        let cover = transaction
+            .query_row(
+                "SELECT picture, rank FROM giftPictures WHERE giftId=? ORDER BY rank LIMIT 1",
+                params![gift_id],
+                |row| <(String, i64)>::try_from(row),
+            )
+            .optional()?;
+        match (cover, picture) {
+            (Some((cover, _)), Some(picture)) if cover == picture => {}
+            (Some((cover, _)), None) => {
+                transaction.execute(
+                    "DELETE FROM giftPictures WHERE giftId=? AND picture=?",
+                    params![gift_id, cover],
+                )?;
+            }
+            (Some((cover, rank)), Some(picture)) => {
+                //A picture of the gallery is moved first, the former cover staying in the gallery
+                let moved = transaction.execute(
+                    "UPDATE giftPictures SET rank=? WHERE giftId=? AND picture=?",
+                    params![rank - 1, gift_id, picture],
+                )?;
+                if moved == 0 {
+                    transaction.execute(
+                        "UPDATE giftPictures SET picture=? WHERE giftId=? AND picture=? AND rank=?",
+                        params![picture, gift_id, cover, rank],
+                    )?;
+                }
+            }
+            (None, Some(picture)) => {
+                transaction.execute(
+                    "INSERT INTO giftPictures (giftId, picture, rank) VALUES (?,?,0)",
+                    params![gift_id, picture],
+                )?;
+            }
+            (None, None) => {}
+        }
+        Ok(())
+    }
+
+    /**
+    Add the picture at the end of the gallery, unless already there
+    **/
+    pub fn add_gift_picture(
+        &self,
+        gift_id: i64,
+        picture: &str,
+    ) -> Result<(), WishlistManagerError> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO giftPictures (giftId, picture, rank) \
+            SELECT ?1, ?2, COALESCE(MAX(rank)+1, 0) FROM giftPictures WHERE giftId=?1 \
+            ON CONFLICT DO NOTHING",
+            params![gift_id, picture],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_gift_picture(
+        &self,
+        gift_id: i64,
+        picture: &str,
     ) -> Result<(), WishlistManagerError> {
         let connection = self.connection.lock().unwrap();
-        connection.execute("UPDATE gifts SET name=?, description=?, price=?, whereToBuy=?, picture=?, categoryId=? WHERE id=?", params![name, description, price, where_to_buy, picture, category_id, gift_id])?;
+        connection.execute(
+            "DELETE FROM giftPictures WHERE giftId=? AND picture=?",
+            params![gift_id, picture],
+        )?;
+        Ok(())
+    }
+
+    /**
+    The given pictures must be all the pictures of the gift, in their new order
+    **/
+    pub fn reorder_gift_pictures(
+        &self,
+        gift_id: i64,
+        pictures: &[String],
+    ) -> Result<(), WishlistManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let current = Self::get_pictures(&transaction, gift_id)?;
+        if current.len() != pictures.len()
+            || current.iter().collect::<HashSet<_>>() != pictures.iter().collect::<HashSet<_>>()
+        {
+            return Err(WishlistManagerError::PicturesMismatch(gift_id));
+        }
+        for (index, picture) in pictures.iter().enumerate() {
+            transaction.execute(
+                "UPDATE giftPictures SET rank=? WHERE giftId=? AND picture=?",
+                params![index, gift_id, picture],
+            )?;
+        }
+        transaction.commit()?;
 
         Ok(())
     }
 
+    fn get_pictures(
+        connection: &Connection,
+        gift_id: i64,
+    ) -> Result<Vec<String>, WishlistManagerError> {
+        let mut statement = connection
+            .prepare_cached("SELECT picture FROM giftPictures WHERE giftId=? ORDER BY rank")?;
+        let rows = statement.query_map(params![gift_id], |row| row.get(0))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
     pub fn reorder_gifts(
         &self,
         starting_rank: usize,
@@ -225,10 +444,127 @@ impl WishlistManager {
 
     pub fn delete_gift(&self, gift_id: i64) -> Result<(), WishlistManagerError> {
         let connection = self.connection.lock().unwrap();
+        connection.execute("DELETE FROM giftEvents WHERE giftId=?", params![gift_id])?;
+        connection.execute("DELETE FROM giftPictures WHERE giftId=?", params![gift_id])?;
         connection.execute("DELETE FROM gifts WHERE id=?", params![gift_id])?;
         Ok(())
     }
 
+    pub fn set_category_event(
+        &self,
+        user_id: i64,
+        category_id: i64,
+        event: Option<EventKind>,
+    ) -> Result<(), WishlistManagerError> {
+        let connection = self.connection.lock().unwrap();
+        match event {
+            Some(event) => connection.execute(
+                "INSERT INTO categoryEvents(userId, categoryId, kind) VALUES (?,?,?) \
+                ON CONFLICT(userId, categoryId) DO UPDATE SET kind=excluded.kind",
+                params![user_id, category_id, event],
+            )?,
+            None => connection.execute(
+                "DELETE FROM categoryEvents WHERE userId=? AND categoryId=?",
+                params![user_id, category_id],
+            )?,
+        };
+        Ok(())
+    }
+
+    /**
+    Without visibility, the category is seen by all our friends
+    **/
+    pub fn set_category_visibility(
+        &self,
+        user_id: i64,
+        category_id: i64,
+        visibility: Option<Visibility>,
+    ) -> Result<(), WishlistManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "DELETE FROM categoryVisibility WHERE userId=? AND categoryId=?",
+            params![user_id, category_id],
+        )?;
+        if let Some(visibility) = visibility {
+            transaction.execute(
+                "INSERT INTO categoryVisibility (userId, categoryId) VALUES (?, ?)",
+                params![user_id, category_id],
+            )?;
+            for group_id in visibility.groups {
+                transaction.execute(
+                    "INSERT INTO categoryVisibility (userId, categoryId, groupId) VALUES (?, ?, ?)",
+                    params![user_id, category_id, group_id],
+                )?;
+            }
+            for friend_id in visibility.friends {
+                transaction.execute(
+                    "INSERT INTO categoryVisibility (userId, categoryId, friendId) VALUES (?, ?, ?)",
+                    params![user_id, category_id, friend_id],
+                )?;
+            }
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    pub fn remove_group_visibility(&self, group_id: i64) -> Result<(), WishlistManagerError> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "DELETE FROM categoryVisibility WHERE groupId=?",
+            params![group_id],
+        )?;
+        Ok(())
+    }
+
+    fn get_visibility(
+        connection: &Connection,
+        user_id: i64,
+        category_id: i64,
+    ) -> Result<Option<Visibility>, WishlistManagerError> {
+        let mut statement = connection.prepare(
+            "SELECT groupId, friendId FROM categoryVisibility WHERE userId=? AND categoryId=?",
+        )?;
+        let rows = statement.query_map(params![user_id, category_id], |row| {
+            <(Option<i64>, Option<i64>)>::try_from(row)
+        })?;
+
+        let mut visibility = None;
+        for row in rows {
+            let (group_id, friend_id) = row?;
+            let visibility = visibility.get_or_insert_with(Visibility::default);
+            visibility.groups.extend(group_id);
+            visibility.friends.extend(friend_id);
+        }
+        Ok(visibility)
+    }
+
+    /**
+    Like category events, each user of a shared category tags its gifts for themselves
+    **/
+    pub fn set_gift_event(
+        &self,
+        user_id: i64,
+        gift_id: i64,
+        event: Option<EventKind>,
+    ) -> Result<(), WishlistManagerError> {
+        let connection = self.connection.lock().unwrap();
+        match event {
+            Some(event) => connection.execute(
+                "INSERT INTO giftEvents(userId, giftId, kind) VALUES (?,?,?) \
+                ON CONFLICT(userId, giftId) DO UPDATE SET kind=excluded.kind",
+                params![user_id, gift_id, event],
+            )?,
+            None => connection.execute(
+                "DELETE FROM giftEvents WHERE userId=? AND giftId=?",
+                params![user_id, gift_id],
+            )?,
+        };
+        Ok(())
+    }
+
     pub fn change_heart_gift(&self, gift_id: i64) -> Result<(), WishlistManagerError> {
         let connection = self.connection.lock().unwrap();
 
@@ -255,6 +591,136 @@ impl WishlistManager {
         Ok(())
     }
 
+    /**
+    The friend leaves the categories we share, and their reservations on our wishlist are kept or
+    released. Done in the transaction ending the friendship.
+    **/
+    pub fn static_remove_friend(
+        transaction: &Transaction,
+        user_id: i64,
+        friend_id: i64,
+        reservations: ReservationPolicy,
+    ) -> Result<(), rusqlite::Error> {
+        if reservations == ReservationPolicy::Release {
+            transaction.execute(
+                "UPDATE gifts SET reservedBy=NULL WHERE reservedBy=? \
+                AND categoryId IN (SELECT categoryId FROM joinUserAndCategory WHERE userId=?)",
+                params![friend_id, user_id],
+            )?;
+        }
+        transaction.execute(
+            "DELETE FROM categoryEvents WHERE userId=? \
+            AND categoryId IN (SELECT categoryId FROM joinUserAndCategory WHERE userId=?)",
+            params![friend_id, user_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM giftEvents WHERE userId=? AND giftId IN (SELECT id FROM gifts \
+            WHERE categoryId IN (SELECT categoryId FROM joinUserAndCategory WHERE userId=?))",
+            params![friend_id, user_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM categoryVisibility WHERE userId=? \
+            AND categoryId IN (SELECT categoryId FROM joinUserAndCategory WHERE userId=?)",
+            params![friend_id, user_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM categoryVisibility WHERE (userId=?1 AND friendId=?2) OR (userId=?2 AND friendId=?1)",
+            params![friend_id, user_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM joinUserAndCategory WHERE userId=? \
+            AND categoryId IN (SELECT categoryId FROM joinUserAndCategory WHERE userId=?)",
+            params![friend_id, user_id],
+        )?;
+
+        Ok(())
+    }
+
+    /**
+    Remove the categories of a deleted user, the shared ones staying with the other users, and
+    release the gifts they reserved. Secret gifts they added to other lists are kept, only
+    forgetting who added them.
+    **/
+    pub fn delete_user(&self, user_id: i64) -> Result<(), WishlistManagerError> {
+        let mut connection = self.connection.lock().unwrap();
+
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "UPDATE gifts SET reservedBy=NULL WHERE reservedBy=?",
+            params![user_id],
+        )?;
+        transaction.execute(
+            "UPDATE gifts SET addedBy=NULL WHERE addedBy=?",
+            params![user_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM categoryEvents WHERE userId=?",
+            params![user_id],
+        )?;
+        transaction.execute("DELETE FROM giftEvents WHERE userId=?", params![user_id])?;
+        transaction.execute(
+            "DELETE FROM categoryVisibility WHERE userId=?1 OR friendId=?1",
+            params![user_id],
+        )?;
+        transaction.execute(
+            "DELETE FROM joinUserAndCategory WHERE userId=?",
+            params![user_id],
+        )?;
+        //The categories nobody has anymore
+        transaction.execute_batch(
+            "DELETE FROM giftEvents WHERE giftId IN (SELECT id FROM gifts WHERE categoryId NOT IN (SELECT categoryId FROM joinUserAndCategory)); \
+            DELETE FROM giftPictures WHERE giftId IN (SELECT id FROM gifts WHERE categoryId NOT IN (SELECT categoryId FROM joinUserAndCategory)); \
+            DELETE FROM gifts WHERE categoryId NOT IN (SELECT categoryId FROM joinUserAndCategory); \
+            DELETE FROM categories WHERE id NOT IN (SELECT categoryId FROM joinUserAndCategory);",
+        )?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /**
+    Whether the category of our friend is visible to us, or shared with us
+    **/
+    pub fn is_visible_category(
+        &self,
+        user_id: i64,
+        friend_id: i64,
+        category_id: i64,
+    ) -> Result<bool, WishlistManagerError> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement = connection.prepare(&format!(
+            "SELECT 1 FROM joinUserAndCategory j WHERE j.userId=?1 AND j.categoryId=?3 \
+            AND (j.categoryId IN (SELECT categoryId FROM joinUserAndCategory WHERE userId=?2) \
+            OR {VISIBLE_CATEGORY})"
+        ))?;
+        Ok(statement.exists(params![friend_id, user_id, category_id])?)
+    }
+
+    pub fn is_visible_gift(
+        &self,
+        user_id: i64,
+        friend_id: i64,
+        gift_id: i64,
+    ) -> Result<bool, WishlistManagerError> {
+        let category_id = {
+            let connection = self.connection.lock().unwrap();
+            let Some(category_id) = connection
+                .query_row(
+                    "SELECT categoryId FROM gifts WHERE id=?",
+                    params![gift_id],
+                    |row| row.get::<_, i64>(0),
+                )
+                .optional()?
+            else {
+                return Ok(false);
+            };
+            category_id
+        };
+
+        self.is_visible_category(user_id, friend_id, category_id)
+    }
+
     pub fn is_my_category(
         &self,
         user_id: i64,
@@ -267,17 +733,22 @@ impl WishlistManager {
         Ok(statement.exists(params![user_id, category_id])?)
     }
 
+    /**
+    Secret gifts are only handled by the friends of the user, and their other gifts only by the
+    user, so `secret` tells which ones are expected
+    **/
     pub fn is_my_gift(
         &self,
         user_id: i64,
         category_id: i64,
         gift_id: i64,
+        secret: bool,
     ) -> Result<bool, WishlistManagerError> {
         {
             let connection = self.connection.lock().unwrap();
-            let mut statement =
-                connection.prepare("SELECT categoryId FROM gifts WHERE id=? AND categoryId=?")?;
-            if !statement.exists(params![gift_id, category_id])? {
+            let mut statement = connection
+                .prepare("SELECT categoryId FROM gifts WHERE id=? AND categoryId=? AND secret=?")?;
+            if !statement.exists(params![gift_id, category_id, secret])? {
                 return Ok(false);
             }
         }
@@ -289,13 +760,14 @@ impl WishlistManager {
         &self,
         user_id: i64,
         gift_id: i64,
+        secret: bool,
     ) -> Result<bool, WishlistManagerError> {
         let old_category = {
             let connection = self.connection.lock().unwrap();
             let Some(old_category) = connection
                 .query_row(
-                    "SELECT categoryId FROM gifts WHERE id=?",
-                    params![gift_id],
+                    "SELECT categoryId FROM gifts WHERE id=? AND secret=?",
+                    params![gift_id, secret],
                     |row| row.get::<_, i64>(0),
                 )
                 .optional()?
@@ -332,12 +804,14 @@ impl WishlistManager {
     pub fn get_my_wishlist(&self, user_id: i64) -> Result<WishList, WishlistManagerError> {
         let connection = self.connection.lock().unwrap();
 
-        let mut statement = connection.prepare("SELECT id, name FROM joinUserAndCategory j LEFT JOIN categories c ON c.id=j.categoryId WHERE j.userId=? ORDER BY j.rank")?;
+        let mut statement = connection.prepare("SELECT c.id, c.name, e.kind FROM joinUserAndCategory j LEFT JOIN categories c ON c.id=j.categoryId \
+            LEFT JOIN categoryEvents e ON e.categoryId=j.categoryId AND e.userId=j.userId WHERE j.userId=? ORDER BY j.rank")?;
         let rows = statement.query_map(params![user_id], |row| <_>::try_from(row))?;
 
         let mut categories = Vec::new();
         for row in rows {
             let mut category: Category = row?;
+            category.visible_to = Self::get_visibility(&connection, user_id, category.id)?;
             let mut statement = connection.prepare(
                 "SELECT userId FROM joinUserAndCategory WHERE categoryId=? AND userId!=?",
             )?;
@@ -345,16 +819,67 @@ impl WishlistManager {
             for row in rows {
                 category.share_with.push(row?)
             }
-            let mut statement = connection.prepare("SELECT id, name, description, price, whereToBuy, picture, heart FROM gifts WHERE categoryId=? AND secret=FALSE ORDER BY rank")?;
-            let rows = statement.query_map(params![category.id], |row| <_>::try_from(row))?;
+            let mut statement = connection.prepare("SELECT id, name, description, price, whereToBuy, heart, e.kind FROM gifts g \
+                LEFT JOIN giftEvents e ON e.giftId=g.id AND e.userId=?2 WHERE categoryId=?1 AND secret=FALSE ORDER BY rank")?;
+            let rows =
+                statement.query_map(params![category.id, user_id], |row| <Gift>::try_from(row))?;
             for row in rows {
-                category.gifts.push(row?)
+                let mut gift = row?;
+                gift.pictures = Self::get_pictures(&connection, gift.id)?;
+                gift.picture = gift.pictures.first().cloned();
+                category.gifts.push(gift)
             }
             categories.push(category);
         }
         Ok(WishList { categories })
     }
 
+    /**
+    Gifts of other lists reserved by the user
+    **/
+    pub fn get_reservations(
+        &self,
+        user_id: i64,
+    ) -> Result<Vec<ExportedGift>, WishlistManagerError> {
+        let connection = self.connection.lock().unwrap();
+        Self::get_exported_gifts(&connection, "g.reservedBy=?", user_id)
+    }
+
+    /**
+    Secret gifts the user added to the lists of their friends
+    **/
+    pub fn get_added_secret_gifts(
+        &self,
+        user_id: i64,
+    ) -> Result<Vec<ExportedGift>, WishlistManagerError> {
+        let connection = self.connection.lock().unwrap();
+        Self::get_exported_gifts(&connection, "g.secret=TRUE AND g.addedBy=?", user_id)
+    }
+
+    fn get_exported_gifts(
+        connection: &Connection,
+        condition: &str,
+        user_id: i64,
+    ) -> Result<Vec<ExportedGift>, WishlistManagerError> {
+        let mut statement = connection.prepare(&format!(
+            "SELECT g.id, g.name, g.description, g.price, g.whereToBuy, g.reservedBy, c.id, c.name FROM gifts g \
+            JOIN categories c ON c.id=g.categoryId WHERE {condition} ORDER BY c.id, g.rank"
+        ))?;
+        let rows = statement.query_map(params![user_id], |row| <ExportedGift>::try_from(row))?;
+
+        let mut gifts = Vec::new();
+        for row in rows {
+            let mut gift = row?;
+            gift.pictures = Self::get_pictures(connection, gift.id)?;
+            let mut statement = connection
+                .prepare_cached("SELECT userId FROM joinUserAndCategory WHERE categoryId=?")?;
+            let rows = statement.query_map(params![gift.category_id], |row| row.get(0))?;
+            gift.owners = rows.collect::<Result<_, _>>()?;
+            gifts.push(gift);
+        }
+        Ok(gifts)
+    }
+
     /**
     This return friend wishlist without the shared categories but with secret gifts
     **/
@@ -365,17 +890,24 @@ impl WishlistManager {
     ) -> Result<FriendWishList, WishlistManagerError> {
         let connection = self.connection.lock().unwrap();
 
-        let mut statement = connection.prepare("SELECT id, name FROM joinUserAndCategory j LEFT JOIN categories c ON c.id=j.categoryId WHERE j.userId=? AND \
-            j.categoryId NOT IN (SELECT categoryId FROM joinUserAndCategory WHERE userId=?) ORDER BY j.rank")?;
+        let mut statement = connection.prepare(&format!("SELECT c.id, c.name, e.kind FROM joinUserAndCategory j LEFT JOIN categories c ON c.id=j.categoryId \
+            LEFT JOIN categoryEvents e ON e.categoryId=j.categoryId AND e.userId=j.userId WHERE j.userId=?1 AND \
+            j.categoryId NOT IN (SELECT categoryId FROM joinUserAndCategory WHERE userId=?2) AND {VISIBLE_CATEGORY} ORDER BY j.rank"))?;
         let rows = statement.query_map(params![friend_id, user_id], |row| <_>::try_from(row))?;
 
         let mut categories = Vec::new();
         for row in rows {
             let mut category: FriendCategory = row?;
-            let mut statement = connection.prepare("SELECT id, name, description, price, whereToBuy, picture, heart, secret, reservedBy FROM gifts WHERE categoryId=? ORDER BY rank")?;
-            let rows = statement.query_map(params![category.id], |row| <_>::try_from(row))?;
+            let mut statement = connection.prepare("SELECT id, name, description, price, whereToBuy, heart, secret, reservedBy, e.kind FROM gifts g \
+                LEFT JOIN giftEvents e ON e.giftId=g.id AND e.userId=?2 WHERE categoryId=?1 ORDER BY rank")?;
+            let rows = statement.query_map(params![category.id, friend_id], |row| {
+                <FriendGift>::try_from(row)
+            })?;
             for row in rows {
-                category.gifts.push(row?)
+                let mut gift = row?;
+                gift.pictures = Self::get_pictures(&connection, gift.id)?;
+                gift.picture = gift.pictures.first().cloned();
+                category.gifts.push(gift)
             }
             categories.push(category);
         }
@@ -383,17 +915,53 @@ impl WishlistManager {
     }
 }
 
+/**
+What happens to the gifts a former friend reserved on our wishlist
+**/
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReservationPolicy {
+    #[default]
+    Keep,
+    Release,
+}
+
+/**
+The groups and friends allowed to see a category, besides the users sharing it
+**/
+#[derive(Serialize, Deserialize, Default)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct Visibility {
+    pub groups: Vec<i64>,
+    pub friends: Vec<i64>,
+}
+
 #[derive(Serialize)]
 #[cfg_attr(test, derive(PartialEq, Eq, Debug))]
 pub struct WishList {
     pub categories: Vec<Category>,
 }
+impl WishList {
+    /**
+    Only keep the gifts for the given event, dropping the categories that end up empty
+    **/
+    pub fn retain_event(&mut self, event: EventKind) {
+        retain_event(
+            &mut self.categories,
+            event,
+            |category| (category.event, &mut category.gifts),
+            |gift| gift.event,
+        );
+    }
+}
 #[derive(Serialize)]
 #[cfg_attr(test, derive(PartialEq, Eq, Debug))]
 pub struct Category {
     id: i64,
     pub name: String,
     share_with: Vec<i64>,
+    visible_to: Option<Visibility>,
+    pub event: Option<EventKind>,
     pub gifts: Vec<Gift>,
 }
 #[derive(Serialize)]
@@ -404,8 +972,11 @@ pub struct Gift {
     pub description: Option<String>,
     pub price: Option<String>,
     pub where_to_buy: Option<String>,
+    /** The cover, first of the pictures **/
     pub picture: Option<String>,
+    pub pictures: Vec<String>,
     pub heart: bool,
+    pub event: Option<EventKind>,
 }
 impl<'a> TryFrom<&Row<'a>> for Category {
     type Error = rusqlite::Error;
@@ -415,6 +986,8 @@ impl<'a> TryFrom<&Row<'a>> for Category {
             id: row.get(0)?,
             name: row.get(1)?,
             share_with: Vec::new(),
+            visible_to: None,
+            event: row.get(2)?,
             gifts: Vec::new(),
         })
     }
@@ -429,8 +1002,47 @@ impl<'a> TryFrom<&Row<'a>> for Gift {
             description: row.get(2)?,
             price: row.get(3)?,
             where_to_buy: row.get(4)?,
-            picture: row.get(5)?,
-            heart: row.get(6)?,
+            picture: None,
+            pictures: Vec::new(),
+            heart: row.get(5)?,
+            event: row.get(6)?,
+        })
+    }
+}
+
+/**
+A gift of someone else's list, as it appears in the data export of a user
+**/
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct ExportedGift {
+    id: i64,
+    name: String,
+    description: Option<String>,
+    price: Option<String>,
+    where_to_buy: Option<String>,
+    pictures: Vec<String>,
+    reserved_by: Option<i64>,
+    category_id: i64,
+    category: String,
+    /** The users whose list it is **/
+    owners: Vec<i64>,
+}
+impl<'a> TryFrom<&Row<'a>> for ExportedGift {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'a>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            description: row.get(2)?,
+            price: row.get(3)?,
+            where_to_buy: row.get(4)?,
+            pictures: Vec::new(),
+            reserved_by: row.get(5)?,
+            category_id: row.get(6)?,
+            category: row.get(7)?,
+            owners: Vec::new(),
         })
     }
 }
@@ -438,27 +1050,60 @@ impl<'a> TryFrom<&Row<'a>> for Gift {
 #[derive(Serialize)]
 #[cfg_attr(test, derive(PartialEq, Eq, Debug))]
 pub struct FriendWishList {
-    categories: Vec<FriendCategory>,
+    pub categories: Vec<FriendCategory>,
+}
+impl FriendWishList {
+    /**
+    Only keep the gifts for the given event, dropping the categories that end up empty
+    **/
+    pub fn retain_event(&mut self, event: EventKind) {
+        retain_event(
+            &mut self.categories,
+            event,
+            |category| (category.event, &mut category.gifts),
+            |gift| gift.event,
+        );
+    }
+}
+
+/**
+Gifts without event follow the one of their category
+**/
+fn retain_event<C, G>(
+    categories: &mut Vec<C>,
+    event: EventKind,
+    gifts: impl Fn(&mut C) -> (Option<EventKind>, &mut Vec<G>),
+    gift_event: impl Fn(&G) -> Option<EventKind>,
+) {
+    categories.retain_mut(|category| {
+        let (category_event, gifts) = gifts(category);
+        gifts.retain(|gift| gift_event(gift).or(category_event) == Some(event));
+        !gifts.is_empty()
+    });
 }
 #[derive(Serialize)]
 #[cfg_attr(test, derive(PartialEq, Eq, Debug))]
 pub struct FriendCategory {
     id: i64,
-    name: String,
-    gifts: Vec<FriendGift>,
+    pub name: String,
+    pub event: Option<EventKind>,
+    pub gifts: Vec<FriendGift>,
 }
 #[derive(Serialize)]
 #[cfg_attr(test, derive(PartialEq, Eq, Debug))]
 pub struct FriendGift {
-    id: i64,
-    name: String,
-    description: Option<String>,
-    price: Option<String>,
-    where_to_buy: Option<String>,
-    picture: Option<String>,
-    heart: bool,
-    secret: bool,
-    reserved_by: Option<i64>,
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub price: Option<String>,
+    pub where_to_buy: Option<String>,
+    /** The cover, first of the pictures **/
+    pub picture: Option<String>,
+    pictures: Vec<String>,
+    pub heart: bool,
+    pub secret: bool,
+    pub reserved_by: Option<i64>,
+    pub event: Option<EventKind>,
 }
 impl<'a> TryFrom<&Row<'a>> for FriendCategory {
     type Error = rusqlite::Error;
@@ -467,6 +1112,7 @@ impl<'a> TryFrom<&Row<'a>> for FriendCategory {
         Ok(Self {
             id: row.get(0)?,
             name: row.get(1)?,
+            event: row.get(2)?,
             gifts: Vec::new(),
         })
     }
@@ -481,53 +1127,33 @@ impl<'a> TryFrom<&Row<'a>> for FriendGift {
             description: row.get(2)?,
             price: row.get(3)?,
             where_to_buy: row.get(4)?,
-            picture: row.get(5)?,
-            heart: row.get(6)?,
-            secret: row.get(7)?,
-            reserved_by: row.get(8)?,
+            picture: None,
+            pictures: Vec::new(),
+            heart: row.get(5)?,
+            secret: row.get(6)?,
+            reserved_by: row.get(7)?,
+            event: row.get(8)?,
         })
     }
 }
-impl Into<WishList> for FriendWishList {
-    fn into(self) -> WishList {
-        WishList {
-            categories: self.categories
-                .into_iter()
-                .map(|c| Category {
-                    id: c.id,
-                    name: c.name,
-                    share_with: Vec::new(),
-                    gifts: c.gifts
-                        .into_iter()
-                        .filter(|g| g.reserved_by.is_none())
-                        .map(|g| Gift {
-                            id: g.id,
-                            name: g.name,
-                            description: g.description,
-                            price: g.price,
-                            where_to_buy: g.where_to_buy,
-                            picture: g.picture,
-                            heart: g.heart,
-                        })
-                        .collect()
-                })
-                .collect()
-        }
-    }
-}
-
 #[cfg(test)]
 mod test {
+    use crate::clock::SystemClock;
+    use crate::managers::events_manager::EventKind;
+    use crate::managers::friends_manager::{FriendsManager, RequestStatus};
     use crate::managers::test_helper::create_test_database;
     use crate::managers::users_manager::UsersManager;
-    use crate::managers::wishlist_manager::{Category, Gift, WishList, WishlistManager};
+    use crate::managers::wishlist_manager::{
+        Category, ExportedGift, Gift, ReservationPolicy, Visibility, WishList, WishlistManager,
+        WishlistManagerError,
+    };
     use std::collections::HashSet;
     use std::sync::{Arc, Mutex};
 
     #[test]
     fn test_add_category() {
         let connection = Arc::new(Mutex::new(create_test_database("test_add_category")));
-        let users_manager = UsersManager::new(connection.clone()).unwrap();
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
         let one = users_manager.add_user("one", "pwd").unwrap();
         let two = users_manager.add_user("two", "pwd").unwrap();
 
@@ -554,12 +1180,16 @@ mod test {
                         id: 1,
                         name: "OneCategory".to_string(),
                         share_with: Vec::new(),
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     },
                     Category {
                         id: 4,
                         name: "SharedCategory".to_string(),
                         share_with: vec![two],
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     }
                 ]
@@ -574,18 +1204,24 @@ mod test {
                         id: 2,
                         name: "TwoCategory".to_string(),
                         share_with: Vec::new(),
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     },
                     Category {
                         id: 3,
                         name: "TwoCategory2".to_string(),
                         share_with: Vec::new(),
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     },
                     Category {
                         id: 4,
                         name: "SharedCategory".to_string(),
                         share_with: vec![one],
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     }
                 ]
@@ -596,7 +1232,7 @@ mod test {
     #[test]
     fn test_edit_category() {
         let connection = Arc::new(Mutex::new(create_test_database("test_edit_category")));
-        let users_manager = UsersManager::new(connection.clone()).unwrap();
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
         let one = users_manager.add_user("one", "pwd").unwrap();
         let two = users_manager.add_user("two", "pwd").unwrap();
         let three = users_manager.add_user("three", "pwd").unwrap();
@@ -614,6 +1250,8 @@ mod test {
                     id: 1,
                     name: "SharedCategory".to_string(),
                     share_with: vec![two],
+                    visible_to: None,
+                    event: None,
                     gifts: Vec::new()
                 }]
             }
@@ -626,6 +1264,8 @@ mod test {
                     id: 1,
                     name: "SharedCategory".to_string(),
                     share_with: vec![one],
+                    visible_to: None,
+                    event: None,
                     gifts: Vec::new()
                 }]
             }
@@ -644,6 +1284,8 @@ mod test {
                     id: 1,
                     name: "NewName".to_string(),
                     share_with: vec![three],
+                    visible_to: None,
+                    event: None,
                     gifts: Vec::new()
                 }]
             }
@@ -658,6 +1300,8 @@ mod test {
                     id: 1,
                     name: "NewName".to_string(),
                     share_with: vec![one],
+                    visible_to: None,
+                    event: None,
                     gifts: Vec::new()
                 }]
             }
@@ -667,7 +1311,7 @@ mod test {
     #[test]
     fn test_reorder_categories() {
         let connection = Arc::new(Mutex::new(create_test_database("test_reorder_categories")));
-        let users_manager = UsersManager::new(connection.clone()).unwrap();
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
         let one = users_manager.add_user("one", "pwd").unwrap();
         let two = users_manager.add_user("two", "pwd").unwrap();
 
@@ -698,18 +1342,24 @@ mod test {
                         id: 3,
                         name: "TwoCategory2".to_string(),
                         share_with: Vec::new(),
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     },
                     Category {
                         id: 2,
                         name: "TwoCategory".to_string(),
                         share_with: Vec::new(),
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     },
                     Category {
                         id: 4,
                         name: "SharedCategory".to_string(),
                         share_with: vec![one],
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     }
                 ]
@@ -729,18 +1379,24 @@ mod test {
                         id: 3,
                         name: "TwoCategory2".to_string(),
                         share_with: Vec::new(),
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     },
                     Category {
                         id: 4,
                         name: "SharedCategory".to_string(),
                         share_with: vec![one],
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     },
                     Category {
                         id: 2,
                         name: "TwoCategory".to_string(),
                         share_with: Vec::new(),
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     }
                 ]
@@ -755,12 +1411,16 @@ mod test {
                         id: 1,
                         name: "OneCategory".to_string(),
                         share_with: Vec::new(),
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     },
                     Category {
                         id: 4,
                         name: "SharedCategory".to_string(),
                         share_with: vec![two],
+                        visible_to: None,
+                        event: None,
                         gifts: Vec::new()
                     }
                 ]
@@ -771,7 +1431,7 @@ mod test {
     #[test]
     fn test_add_gift() {
         let connection = Arc::new(Mutex::new(create_test_database("test_add_gift")));
-        let users_manager = UsersManager::new(connection.clone()).unwrap();
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
         let one = users_manager.add_user("one", "pwd").unwrap();
         let two = users_manager.add_user("two", "pwd").unwrap();
 
@@ -784,7 +1444,7 @@ mod test {
             .unwrap();
 
         wishlist_manager
-            .add_gift("Gift", None, None, None, None, false, 1)
+            .add_gift("Gift", None, None, None, None, None, 1)
             .unwrap();
         wishlist_manager
             .add_gift(
@@ -793,7 +1453,7 @@ mod test {
                 Some("price".to_string()),
                 Some("wtb".to_string()),
                 Some("pic".to_string()),
-                false,
+                None,
                 2,
             )
             .unwrap();
@@ -806,6 +1466,8 @@ mod test {
                         id: 1,
                         name: "OneCategory".to_string(),
                         share_with: Vec::new(),
+                        visible_to: None,
+                        event: None,
                         gifts: vec![Gift {
                             id: 1,
                             name: "Gift".to_string(),
@@ -813,13 +1475,17 @@ mod test {
                             price: None,
                             where_to_buy: None,
                             picture: None,
-                            heart: false
+                            pictures: vec![],
+                            heart: false,
+                            event: None
                         }]
                     },
                     Category {
                         id: 2,
                         name: "SharedCategory".to_string(),
                         share_with: vec![two],
+                        visible_to: None,
+                        event: None,
                         gifts: vec![Gift {
                             id: 2,
                             name: "Gift2".to_string(),
@@ -827,7 +1493,9 @@ mod test {
                             price: Some("price".to_string()),
                             where_to_buy: Some("wtb".to_string()),
                             picture: Some("pic".to_string()),
-                            heart: false
+                            pictures: vec!["pic".to_string()],
+                            heart: false,
+                            event: None
                         }]
                     }
                 ]
@@ -841,6 +1509,8 @@ mod test {
                     id: 2,
                     name: "SharedCategory".to_string(),
                     share_with: vec![one],
+                    visible_to: None,
+                    event: None,
                     gifts: vec![Gift {
                         id: 2,
                         name: "Gift2".to_string(),
@@ -848,7 +1518,9 @@ mod test {
                         price: Some("price".to_string()),
                         where_to_buy: Some("wtb".to_string()),
                         picture: Some("pic".to_string()),
-                        heart: false
+                        pictures: vec!["pic".to_string()],
+                        heart: false,
+                        event: None
                     }]
                 }]
             }
@@ -858,7 +1530,7 @@ mod test {
     #[test]
     fn test_edit_gift() {
         let connection = Arc::new(Mutex::new(create_test_database("test_edit_gift")));
-        let users_manager = UsersManager::new(connection.clone()).unwrap();
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
         let one = users_manager.add_user("one", "pwd").unwrap();
 
         let wishlist_manager = WishlistManager::new(connection).unwrap();
@@ -867,7 +1539,7 @@ mod test {
             .unwrap();
 
         wishlist_manager
-            .add_gift("Gift", None, None, None, None, false, 1)
+            .add_gift("Gift", None, None, None, None, None, 1)
             .unwrap();
         let wishlist = wishlist_manager.get_my_wishlist(one).unwrap();
         assert_eq!(
@@ -877,6 +1549,8 @@ mod test {
                     id: 1,
                     name: "OneCategory".to_string(),
                     share_with: Vec::new(),
+                    visible_to: None,
+                    event: None,
                     gifts: vec![Gift {
                         id: 1,
                         name: "Gift".to_string(),
@@ -884,7 +1558,9 @@ mod test {
                         price: None,
                         where_to_buy: None,
                         picture: None,
-                        heart: false
+                        pictures: vec![],
+                        heart: false,
+                        event: None
                     }]
                 }]
             }
@@ -909,6 +1585,8 @@ mod test {
                     id: 1,
                     name: "OneCategory".to_string(),
                     share_with: Vec::new(),
+                    visible_to: None,
+                    event: None,
                     gifts: vec![Gift {
                         id: 1,
                         name: "NewName".to_string(),
@@ -916,18 +1594,108 @@ mod test {
                         price: Some("price".to_string()),
                         where_to_buy: Some("wtb".to_string()),
                         picture: Some("pic".to_string()),
-                        heart: false
+                        pictures: vec!["pic".to_string()],
+                        heart: false,
+                        event: None
                     }]
                 }]
             }
         );
     }
 
+    #[test]
+    fn test_gift_pictures() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_gift_pictures")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+
+        let wishlist_manager = WishlistManager::new(connection).unwrap();
+        wishlist_manager
+            .add_category("OneCategory", HashSet::from([one]))
+            .unwrap();
+        wishlist_manager
+            .add_gift("Gift", None, None, None, Some("a".to_string()), None, 1)
+            .unwrap();
+        let pictures = || {
+            let gift = &wishlist_manager.get_my_wishlist(one).unwrap().categories[0].gifts[0];
+            (gift.picture.clone(), gift.pictures.clone())
+        };
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        wishlist_manager.add_gift_picture(1, "b").unwrap();
+        wishlist_manager.add_gift_picture(1, "c").unwrap();
+        wishlist_manager.add_gift_picture(1, "b").unwrap();
+        assert_eq!(pictures(), (Some("a".to_string()), names(&["a", "b", "c"])));
+
+        wishlist_manager
+            .reorder_gift_pictures(1, &names(&["c", "a", "b"]))
+            .unwrap();
+        assert_eq!(pictures(), (Some("c".to_string()), names(&["c", "a", "b"])));
+        assert!(matches!(
+            wishlist_manager.reorder_gift_pictures(1, &names(&["c", "a"])),
+            Err(WishlistManagerError::PicturesMismatch(1))
+        ));
+        assert!(matches!(
+            wishlist_manager.reorder_gift_pictures(1, &names(&["c", "a", "d"])),
+            Err(WishlistManagerError::PicturesMismatch(1))
+        ));
+
+        wishlist_manager.delete_gift_picture(1, "a").unwrap();
+        assert_eq!(pictures(), (Some("c".to_string()), names(&["c", "b"])));
+
+        //Editing the gift replaces its cover
+        wishlist_manager
+            .edit_gift(1, "Gift", None, None, None, Some("d".to_string()), 1)
+            .unwrap();
+        assert_eq!(pictures(), (Some("d".to_string()), names(&["d", "b"])));
+        wishlist_manager
+            .edit_gift(1, "Gift", None, None, None, Some("b".to_string()), 1)
+            .unwrap();
+        assert_eq!(pictures(), (Some("b".to_string()), names(&["b", "d"])));
+        wishlist_manager
+            .edit_gift(1, "Gift", None, None, None, None, 1)
+            .unwrap();
+        assert_eq!(pictures(), (Some("d".to_string()), names(&["d"])));
+        wishlist_manager
+            .edit_gift(1, "Gift", None, None, None, None, 1)
+            .unwrap();
+        assert_eq!(pictures(), (None, names(&[])));
+    }
+
+    #[test]
+    fn test_migrate_gift_pictures() {
+        let connection = create_test_database("test_migrate_gift_pictures");
+        connection
+            .execute_batch(
+                "CREATE TABLE gifts (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, description TEXT, price TEXT, \
+                whereToBuy TEXT, picture TEXT, secret INTEGER NOT NULL, heart INTEGER NOT NULL, rank INTEGER NOT NULL, reservedBy INTEGER, categoryId INTEGER NOT NULL); \
+                INSERT INTO gifts (name, picture, secret, heart, rank, categoryId) VALUES ('Gift', 'pic', FALSE, FALSE, 0, 1); \
+                INSERT INTO gifts (name, picture, secret, heart, rank, categoryId) VALUES ('Gift2', NULL, FALSE, FALSE, 1, 1);",
+            )
+            .unwrap();
+        let connection = Arc::new(Mutex::new(connection));
+        WishlistManager::new(connection.clone()).unwrap();
+        //Only migrated once
+        WishlistManager::new(connection.clone()).unwrap();
+
+        let connection = connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT giftId, picture, rank FROM giftPictures")
+            .unwrap();
+        let pictures = statement
+            .query_map([], |row| <(i64, String, i64)>::try_from(row))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(pictures, vec![(1, "pic".to_string(), 0)]);
+    }
+
     #[test]
     fn test_reorder_gifts() {
         let connection = Arc::new(Mutex::new(create_test_database("test_reorder_gifts")));
-        let users_manager = UsersManager::new(connection.clone()).unwrap();
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
         let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
 
         let wishlist_manager = WishlistManager::new(connection).unwrap();
         wishlist_manager
@@ -935,13 +1703,13 @@ mod test {
             .unwrap();
 
         wishlist_manager
-            .add_gift("Gift", None, None, None, None, false, 1)
+            .add_gift("Gift", None, None, None, None, None, 1)
             .unwrap();
         wishlist_manager
-            .add_gift("Secret", None, None, None, None, true, 1)
+            .add_gift("Secret", None, None, None, None, Some(two), 1)
             .unwrap();
         wishlist_manager
-            .add_gift("Gift2", None, None, None, None, false, 1)
+            .add_gift("Gift2", None, None, None, None, None, 1)
             .unwrap();
 
         //Reorder from the user that can only see gift 1 and 3
@@ -954,6 +1722,8 @@ mod test {
                     id: 1,
                     name: "OneCategory".to_string(),
                     share_with: Vec::new(),
+                    visible_to: None,
+                    event: None,
                     gifts: vec![
                         Gift {
                             id: 3,
@@ -962,7 +1732,9 @@ mod test {
                             price: None,
                             where_to_buy: None,
                             picture: None,
-                            heart: false
+                            pictures: vec![],
+                            heart: false,
+                            event: None
                         },
                         Gift {
                             id: 1,
@@ -971,7 +1743,9 @@ mod test {
                             price: None,
                             where_to_buy: None,
                             picture: None,
-                            heart: false
+                            pictures: vec![],
+                            heart: false,
+                            event: None
                         }
                     ]
                 }]
@@ -979,10 +1753,207 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_visibility() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_visibility")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let three = users_manager.add_user("three", "pwd").unwrap();
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+        friends_manager
+            .add_group(one, "Family", HashSet::from([two]))
+            .unwrap();
+
+        let wishlist_manager = WishlistManager::new(connection).unwrap();
+        for name in ["All", "Family", "Three", "Nobody"] {
+            wishlist_manager
+                .add_category(name, HashSet::from([one]))
+                .unwrap();
+        }
+        let visibility = |groups: Vec<i64>, friends: Vec<i64>| Visibility { groups, friends };
+        wishlist_manager
+            .set_category_visibility(one, 2, Some(visibility(vec![1], vec![])))
+            .unwrap();
+        wishlist_manager
+            .set_category_visibility(one, 3, Some(visibility(vec![], vec![three])))
+            .unwrap();
+        wishlist_manager
+            .set_category_visibility(one, 4, Some(visibility(vec![], vec![])))
+            .unwrap();
+
+        let visible_to = |wishlist: WishList| {
+            wishlist
+                .categories
+                .into_iter()
+                .map(|category| category.visible_to)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            visible_to(wishlist_manager.get_my_wishlist(one).unwrap()),
+            vec![
+                None,
+                Some(visibility(vec![1], vec![])),
+                Some(visibility(vec![], vec![three])),
+                Some(visibility(vec![], vec![])),
+            ]
+        );
+
+        let names = |user_id| {
+            wishlist_manager
+                .get_friend_wishlist(user_id, one)
+                .unwrap()
+                .categories
+                .into_iter()
+                .map(|category| category.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(two), vec!["All", "Family"]);
+        assert_eq!(names(three), vec!["All", "Three"]);
+        assert!(wishlist_manager.is_visible_category(two, one, 2).unwrap());
+        assert!(!wishlist_manager.is_visible_category(two, one, 3).unwrap());
+        assert!(!wishlist_manager.is_visible_category(three, one, 4).unwrap());
+
+        //Without its group, the category stays restricted
+        wishlist_manager.remove_group_visibility(1).unwrap();
+        friends_manager.delete_group(1).unwrap();
+        assert_eq!(names(two), vec!["All"]);
+
+        wishlist_manager
+            .set_category_visibility(one, 4, None)
+            .unwrap();
+        assert_eq!(names(two), vec!["All", "Nobody"]);
+    }
+
+    #[test]
+    fn test_remove_friend() {
+        let connection = Arc::new(Mutex::new(create_test_database(
+            "test_wishlist_remove_friend",
+        )));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+        let three = users_manager.add_user("three", "pwd").unwrap();
+
+        let friends_manager = FriendsManager::new(connection.clone()).unwrap();
+        for (request_id, name, friend) in [(1, "two", two), (2, "three", three)] {
+            friends_manager.create_friend_request(one, name).unwrap();
+            friends_manager
+                .update_received_request(request_id, friend, RequestStatus::Accepted)
+                .unwrap();
+        }
+
+        let wishlist_manager = WishlistManager::new(connection).unwrap();
+        wishlist_manager
+            .add_category("OneCategory", HashSet::from([one]))
+            .unwrap();
+        wishlist_manager
+            .add_category("SharedCategory", HashSet::from([one, two, three]))
+            .unwrap();
+        wishlist_manager
+            .add_category("TwoCategory", HashSet::from([two]))
+            .unwrap();
+        for (name, category) in [("Gift", 1), ("Gift2", 1), ("Gift3", 3)] {
+            wishlist_manager
+                .add_gift(name, None, None, None, None, None, category)
+                .unwrap();
+        }
+        wishlist_manager.reserve_gift(1, Some(two)).unwrap();
+        wishlist_manager.reserve_gift(2, Some(three)).unwrap();
+        wishlist_manager.reserve_gift(3, Some(one)).unwrap();
+
+        friends_manager
+            .remove_friend(one, two, ReservationPolicy::Release)
+            .unwrap();
+        friends_manager
+            .remove_friend(one, three, ReservationPolicy::Keep)
+            .unwrap();
+
+        assert!(!wishlist_manager.is_gift_reserved_by_me(1, two).unwrap());
+        assert!(wishlist_manager.is_gift_reserved_by_me(2, three).unwrap());
+        //Our reservations on their wishlist are left untouched
+        assert!(wishlist_manager.is_gift_reserved_by_me(3, one).unwrap());
+
+        assert!(wishlist_manager.is_my_category(one, 2).unwrap());
+        assert!(!wishlist_manager.is_my_category(two, 2).unwrap());
+        assert!(!wishlist_manager.is_my_category(three, 2).unwrap());
+        assert!(wishlist_manager.is_my_category(two, 3).unwrap());
+    }
+
+    #[test]
+    fn test_exported_gifts() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_exported_gifts")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+
+        let wishlist_manager = WishlistManager::new(connection).unwrap();
+        wishlist_manager
+            .add_category("TwoCategory", HashSet::from([two]))
+            .unwrap();
+        wishlist_manager
+            .add_gift("Gift", None, None, None, Some("a.png".to_string()), None, 1)
+            .unwrap();
+        let secret = wishlist_manager
+            .add_gift(
+                "Secret",
+                None,
+                Some("10".to_string()),
+                None,
+                None,
+                Some(one),
+                1,
+            )
+            .unwrap();
+        wishlist_manager.reserve_gift(1, Some(one)).unwrap();
+
+        assert_eq!(
+            wishlist_manager.get_reservations(one).unwrap(),
+            vec![ExportedGift {
+                id: 1,
+                name: "Gift".to_string(),
+                description: None,
+                price: None,
+                where_to_buy: None,
+                pictures: vec!["a.png".to_string()],
+                reserved_by: Some(one),
+                category_id: 1,
+                category: "TwoCategory".to_string(),
+                owners: vec![two],
+            }]
+        );
+        assert_eq!(
+            wishlist_manager.get_added_secret_gifts(one).unwrap(),
+            vec![ExportedGift {
+                id: secret,
+                name: "Secret".to_string(),
+                description: None,
+                price: Some("10".to_string()),
+                where_to_buy: None,
+                pictures: vec![],
+                reserved_by: None,
+                category_id: 1,
+                category: "TwoCategory".to_string(),
+                owners: vec![two],
+            }]
+        );
+        assert!(wishlist_manager
+            .get_added_secret_gifts(two)
+            .unwrap()
+            .is_empty());
+
+        wishlist_manager.delete_user(one).unwrap();
+        assert!(wishlist_manager.get_reservations(one).unwrap().is_empty());
+        assert!(wishlist_manager
+            .get_added_secret_gifts(one)
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_change_heart() {
         let connection = Arc::new(Mutex::new(create_test_database("test_change_heart")));
-        let users_manager = UsersManager::new(connection.clone()).unwrap();
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
         let one = users_manager.add_user("one", "pwd").unwrap();
 
         let wishlist_manager = WishlistManager::new(connection).unwrap();
@@ -991,7 +1962,7 @@ mod test {
             .unwrap();
 
         wishlist_manager
-            .add_gift("Gift", None, None, None, None, false, 1)
+            .add_gift("Gift", None, None, None, None, None, 1)
             .unwrap();
         let wishlist = wishlist_manager.get_my_wishlist(one).unwrap();
         assert_eq!(
@@ -1001,6 +1972,8 @@ mod test {
                     id: 1,
                     name: "OneCategory".to_string(),
                     share_with: Vec::new(),
+                    visible_to: None,
+                    event: None,
                     gifts: vec![Gift {
                         id: 1,
                         name: "Gift".to_string(),
@@ -1008,7 +1981,9 @@ mod test {
                         price: None,
                         where_to_buy: None,
                         picture: None,
-                        heart: false
+                        pictures: vec![],
+                        heart: false,
+                        event: None
                     }]
                 }]
             }
@@ -1023,6 +1998,8 @@ mod test {
                     id: 1,
                     name: "OneCategory".to_string(),
                     share_with: Vec::new(),
+                    visible_to: None,
+                    event: None,
                     gifts: vec![Gift {
                         id: 1,
                         name: "Gift".to_string(),
@@ -1030,7 +2007,9 @@ mod test {
                         price: None,
                         where_to_buy: None,
                         picture: None,
-                        heart: true
+                        pictures: vec![],
+                        heart: true,
+                        event: None
                     }]
                 }]
             }
@@ -1045,6 +2024,8 @@ mod test {
                     id: 1,
                     name: "OneCategory".to_string(),
                     share_with: Vec::new(),
+                    visible_to: None,
+                    event: None,
                     gifts: vec![Gift {
                         id: 1,
                         name: "Gift".to_string(),
@@ -1052,10 +2033,138 @@ mod test {
                         price: None,
                         where_to_buy: None,
                         picture: None,
-                        heart: false
+                        pictures: vec![],
+                        heart: false,
+                        event: None
                     }]
                 }]
             }
         );
     }
+
+    #[test]
+    fn test_event() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_event")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+
+        let wishlist_manager = WishlistManager::new(connection).unwrap();
+        wishlist_manager
+            .add_category("OneCategory", HashSet::from([one]))
+            .unwrap();
+        wishlist_manager
+            .add_category("TwoCategory", HashSet::from([one]))
+            .unwrap();
+        wishlist_manager
+            .add_gift("Gift", None, None, None, None, None, 1)
+            .unwrap();
+        wishlist_manager
+            .add_gift("Gift2", None, None, None, None, None, 1)
+            .unwrap();
+        wishlist_manager
+            .add_gift("Gift3", None, None, None, None, None, 2)
+            .unwrap();
+
+        wishlist_manager
+            .set_category_event(one, 1, Some(EventKind::Birthday))
+            .unwrap();
+        wishlist_manager
+            .set_gift_event(one, 2, Some(EventKind::Christmas))
+            .unwrap();
+        wishlist_manager
+            .set_gift_event(one, 3, Some(EventKind::Birthday))
+            .unwrap();
+
+        let mut wishlist = wishlist_manager.get_my_wishlist(one).unwrap();
+        wishlist.retain_event(EventKind::Birthday);
+        assert_eq!(
+            wishlist,
+            WishList {
+                categories: vec![
+                    Category {
+                        id: 1,
+                        name: "OneCategory".to_string(),
+                        share_with: Vec::new(),
+                        visible_to: None,
+                        event: Some(EventKind::Birthday),
+                        gifts: vec![Gift {
+                            id: 1,
+                            name: "Gift".to_string(),
+                            description: None,
+                            price: None,
+                            where_to_buy: None,
+                            picture: None,
+                            pictures: vec![],
+                            heart: false,
+                            event: None
+                        }]
+                    },
+                    Category {
+                        id: 2,
+                        name: "TwoCategory".to_string(),
+                        share_with: Vec::new(),
+                        visible_to: None,
+                        event: None,
+                        gifts: vec![Gift {
+                            id: 3,
+                            name: "Gift3".to_string(),
+                            description: None,
+                            price: None,
+                            where_to_buy: None,
+                            picture: None,
+                            pictures: vec![],
+                            heart: false,
+                            event: Some(EventKind::Birthday)
+                        }]
+                    }
+                ]
+            }
+        );
+
+        wishlist_manager.set_category_event(one, 1, None).unwrap();
+        wishlist_manager.delete_gift(3).unwrap();
+        let mut wishlist = wishlist_manager.get_my_wishlist(one).unwrap();
+        wishlist.retain_event(EventKind::Birthday);
+        assert_eq!(wishlist, WishList { categories: vec![] });
+    }
+
+    #[test]
+    fn test_shared_gift_event() {
+        let connection = Arc::new(Mutex::new(create_test_database("test_shared_gift_event")));
+        let users_manager = UsersManager::new(connection.clone(), Arc::new(SystemClock)).unwrap();
+        let one = users_manager.add_user("one", "pwd").unwrap();
+        let two = users_manager.add_user("two", "pwd").unwrap();
+
+        let wishlist_manager = WishlistManager::new(connection).unwrap();
+        wishlist_manager
+            .add_category("Shared", HashSet::from([one, two]))
+            .unwrap();
+        wishlist_manager
+            .add_gift("Gift", None, None, None, None, None, 1)
+            .unwrap();
+        wishlist_manager
+            .set_gift_event(one, 1, Some(EventKind::Christmas))
+            .unwrap();
+        wishlist_manager
+            .set_gift_event(two, 1, Some(EventKind::Birthday))
+            .unwrap();
+
+        let mut wishlist = wishlist_manager.get_my_wishlist(one).unwrap();
+        wishlist.retain_event(EventKind::Christmas);
+        assert_eq!(wishlist.categories.len(), 1);
+        let mut wishlist = wishlist_manager.get_my_wishlist(two).unwrap();
+        wishlist.retain_event(EventKind::Christmas);
+        assert!(wishlist.categories.is_empty());
+
+        //Leaving the category forgets our events on its gifts
+        wishlist_manager
+            .edit_category(1, "Shared", HashSet::from([one]))
+            .unwrap();
+        wishlist_manager
+            .edit_category(1, "Shared", HashSet::from([one, two]))
+            .unwrap();
+        let mut wishlist = wishlist_manager.get_my_wishlist(two).unwrap();
+        wishlist.retain_event(EventKind::Birthday);
+        assert!(wishlist.categories.is_empty());
+    }
 }
@@ -0,0 +1,71 @@
+use crate::clock::Clock;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/**
+Allow each user `max_requests` requests per window, the window starting with their first request
+**/
+#[derive(Clone)]
+pub struct RateLimiter {
+    windows: Arc<Mutex<HashMap<i64, Window>>>,
+    max_requests: u32,
+    window: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+struct Window {
+    start: DateTime<Utc>,
+    count: u32,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests: u32, window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            windows: Arc::new(Mutex::new(HashMap::new())),
+            max_requests,
+            window,
+            clock,
+        }
+    }
+
+    pub fn check(&self, user_id: i64) -> bool {
+        let now = self.clock.now();
+        let mut windows = self.windows.lock().unwrap();
+        windows.retain(|_, window| now - window.start < self.window);
+        let window = windows.entry(user_id).or_insert(Window {
+            start: now,
+            count: 0,
+        });
+        if window.count >= self.max_requests {
+            return false;
+        }
+        window.count += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::TestClock;
+    use crate::rate_limiter::RateLimiter;
+    use chrono::{Duration, TimeZone, Utc};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_rate_limiter() {
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ));
+        let rate_limiter = RateLimiter::new(2, Duration::minutes(1), clock.clone());
+
+        assert!(rate_limiter.check(1));
+        clock.advance(Duration::seconds(30));
+        assert!(rate_limiter.check(1));
+        assert!(!rate_limiter.check(1));
+        assert!(rate_limiter.check(2));
+
+        clock.advance(Duration::seconds(30));
+        assert!(rate_limiter.check(1));
+    }
+}
@@ -1,30 +1,66 @@
 use crate::auth_middleware::AuthLayer;
+use crate::clock::Clock;
 use crate::configuration::Configuration;
 use crate::managers::events_manager::EventsManager;
+use crate::managers::files_manager::FilesManager;
 use crate::managers::friends_manager::FriendsManager;
+use crate::managers::invitations_manager::InvitationsManager;
 use crate::managers::jwt_manager::JwtManager;
+use crate::managers::notifications_manager::NotificationsManager;
 use crate::managers::session_manager::SessionManager;
 use crate::managers::users_manager::UsersManager;
 use crate::managers::wishlist_manager::WishlistManager;
-use crate::routes::connection::{change_account, login, logout, reset_password};
-use crate::routes::events::get_events;
+use crate::rate_limiter::RateLimiter;
+use crate::routes::connection::{
+    get_linked_accounts, link_account, login, logout, reset_password, switch_account,
+    unlink_account,
+};
+use crate::routes::dependents::{
+    add_dependent, add_guardian, cancel_dependent_deletion, get_dependent_deletion, get_dependents,
+    get_guardians, remove_guardian, request_dependent_deletion, switch_to_dependent,
+    switch_to_guardian,
+};
+use crate::routes::events::{
+    create_calendar_token, get_calendar, get_events, revoke_calendar_token,
+};
+use crate::routes::export::get_export;
 use crate::routes::files::{get_file, upload_file};
 use crate::routes::friends::{
-    accept_request, add_friend, cancel_request, decline_request, get_friend_id, get_friends,
-    get_requests,
+    accept_request, add_friend, add_group, block_user, cancel_request, decline_request,
+    delete_group, dismiss_suggestion, edit_group, get_blocked_users, get_friend_id,
+    get_friend_profile, get_friends, get_groups, get_requests, get_suggestions, remove_friend,
+    unblock_user,
+};
+use crate::routes::invitations::{
+    create_invitation, get_invitations, redeem_invitation, revoke_invitation,
 };
-use crate::routes::users::{create_user, edit_user};
-use crate::routes::wishlist::{add_category, add_gift, add_secret_gift, change_heart_gift, delete_category, delete_gift, delete_secret_gift, edit_category, edit_gift, edit_secret_gift, get_friend_wishlist, get_my_wishlist, get_wishlist_pdf, reorder_categories, reorder_gifts, reserve_gift, unreserve_gift};
-use axum::extract::FromRef;
+use crate::routes::notifications::{delete_notification, get_notifications};
+use crate::routes::users::{
+    cancel_deletion, change_password, create_user, edit_profile, edit_settings, edit_user,
+    get_deletion, get_profile, get_settings, request_deletion, search_users,
+};
+use crate::routes::wishlist::{
+    add_category, add_gift, add_gift_picture, add_secret_gift, add_secret_gift_picture,
+    change_heart_gift, delete_category, delete_gift, delete_gift_picture, delete_secret_gift,
+    delete_secret_gift_picture, edit_category, edit_gift, edit_secret_gift, get_friend_wishlist,
+    get_my_wishlist, get_wishlist_pdf, reorder_categories, reorder_gift_pictures, reorder_gifts,
+    reorder_secret_gift_pictures, reserve_gift, set_category_event, set_category_visibility,
+    set_gift_event, unreserve_gift,
+};
+use axum::extract::{DefaultBodyLimit, FromRef};
 use axum::routing::{delete, get, patch, post, put};
 use axum::Router;
 use std::sync::Arc;
 use tower_cookies::CookieManagerLayer;
 
 mod connection;
+mod dependents;
 mod events;
+mod export;
 pub mod files;
 mod friends;
+mod invitations;
+mod notifications;
 mod users;
 mod wishlist;
 
@@ -35,9 +71,14 @@ pub(crate) struct AppState {
     pub(crate) session_manager: SessionManager,
     pub(crate) events_manager: EventsManager,
     pub(crate) friends_manager: FriendsManager,
+    pub(crate) invitations_manager: InvitationsManager,
     pub(crate) wishlist_manager: WishlistManager,
+    pub(crate) notifications_manager: NotificationsManager,
+    pub(crate) files_manager: FilesManager,
+    pub(crate) search_rate_limiter: RateLimiter,
 
     pub(crate) configuration: Arc<Configuration>,
+    pub(crate) clock: Arc<dyn Clock>,
 }
 
 impl FromRef<AppState> for UsersManager {
@@ -64,24 +105,54 @@ impl FromRef<AppState> for EventsManager {
     }
 }
 
+impl FromRef<AppState> for NotificationsManager {
+    fn from_ref(app_state: &AppState) -> NotificationsManager {
+        app_state.notifications_manager.clone()
+    }
+}
+
+impl FromRef<AppState> for FilesManager {
+    fn from_ref(app_state: &AppState) -> FilesManager {
+        app_state.files_manager.clone()
+    }
+}
+
 impl FromRef<AppState> for FriendsManager {
     fn from_ref(app_state: &AppState) -> FriendsManager {
         app_state.friends_manager.clone()
     }
 }
 
+impl FromRef<AppState> for InvitationsManager {
+    fn from_ref(app_state: &AppState) -> InvitationsManager {
+        app_state.invitations_manager.clone()
+    }
+}
+
 impl FromRef<AppState> for WishlistManager {
     fn from_ref(app_state: &AppState) -> WishlistManager {
         app_state.wishlist_manager.clone()
     }
 }
 
+impl FromRef<AppState> for RateLimiter {
+    fn from_ref(app_state: &AppState) -> RateLimiter {
+        app_state.search_rate_limiter.clone()
+    }
+}
+
 impl FromRef<AppState> for Arc<Configuration> {
     fn from_ref(app_state: &AppState) -> Arc<Configuration> {
         app_state.configuration.clone()
     }
 }
 
+impl FromRef<AppState> for Arc<dyn Clock> {
+    fn from_ref(app_state: &AppState) -> Arc<dyn Clock> {
+        app_state.clock.clone()
+    }
+}
+
 pub(crate) fn create_api_routes(
     session_manager: SessionManager,
     jwt_manager: Arc<JwtManager>,
@@ -89,12 +160,75 @@ pub(crate) fn create_api_routes(
 ) -> Router<AppState> {
     Router::new()
         .route("/events", get(get_events))
-        .route("/users/change-account", post(change_account))
+        .route("/events/calendar", post(create_calendar_token))
+        .route("/events/calendar", delete(revoke_calendar_token))
+        .route("/users/links", get(get_linked_accounts))
+        .route("/users/links", post(link_account))
+        .route("/users/links/{user_id}", delete(unlink_account))
+        .route("/users/links/{user_id}/switch", post(switch_account))
         .route("/users/logout", get(logout))
         .route("/users", patch(edit_user))
+        .route("/users/settings", get(get_settings))
+        .route("/users/settings", patch(edit_settings))
+        .route("/users/search", get(search_users))
+        .route("/users/profile", get(get_profile))
+        .route("/users/password", post(change_password))
+        .route("/users/deletion", get(get_deletion))
+        .route("/users/deletion", post(request_deletion))
+        .route("/users/deletion", delete(cancel_deletion))
+        .route("/users/export", get(get_export))
+        .route("/users/profile", patch(edit_profile))
+        .route("/users/dependents", get(get_dependents))
+        .route("/users/dependents", post(add_dependent))
+        .route(
+            "/users/dependents/{dependent_id}/guardians",
+            get(get_guardians),
+        )
+        .route(
+            "/users/dependents/{dependent_id}/guardians",
+            post(add_guardian),
+        )
+        .route(
+            "/users/dependents/{dependent_id}/guardians/{guardian_id}",
+            delete(remove_guardian),
+        )
+        .route(
+            "/users/dependents/{dependent_id}/deletion",
+            get(get_dependent_deletion),
+        )
+        .route(
+            "/users/dependents/{dependent_id}/deletion",
+            post(request_dependent_deletion),
+        )
+        .route(
+            "/users/dependents/{dependent_id}/deletion",
+            delete(cancel_dependent_deletion),
+        )
+        .route(
+            "/users/dependents/{dependent_id}/switch",
+            post(switch_to_dependent),
+        )
+        .route(
+            "/users/guardians/{guardian_id}/switch",
+            post(switch_to_guardian),
+        )
         .route("/friends", post(add_friend))
         .route("/friends", get(get_friends))
-        .route("/friends/{friend_name}", get(get_friend_id))
+        .route("/friends/names/{friend_name}", get(get_friend_id))
+        .route("/friends/{friend_id}", delete(remove_friend))
+        .route("/friends/{friend_id}/profile", get(get_friend_profile))
+        .route("/friends/blocked", get(get_blocked_users))
+        .route("/friends/blocked", post(block_user))
+        .route("/friends/blocked/{user_id}", delete(unblock_user))
+        .route("/friends/groups", get(get_groups))
+        .route("/friends/groups", post(add_group))
+        .route("/friends/groups/{group_id}", patch(edit_group))
+        .route("/friends/groups/{group_id}", delete(delete_group))
+        .route("/friends/suggestions", get(get_suggestions))
+        .route(
+            "/friends/suggestions/{user_id}",
+            delete(dismiss_suggestion),
+        )
         .route("/friends/requests", get(get_requests))
         .route("/friends/requests/{request_id}/accept", get(accept_request))
         .route(
@@ -102,6 +236,10 @@ pub(crate) fn create_api_routes(
             get(decline_request),
         )
         .route("/friends/requests/{request_id}", delete(cancel_request))
+        .route("/invitations", get(get_invitations))
+        .route("/invitations", post(create_invitation))
+        .route("/invitations/{token}", delete(revoke_invitation))
+        .route("/invitations/{token}/redeem", post(redeem_invitation))
         .route("/wishlist", get(get_my_wishlist))
         .route("/wishlist/categories", post(add_category))
         .route("/wishlist/categories/reorder", patch(reorder_categories))
@@ -114,6 +252,14 @@ pub(crate) fn create_api_routes(
             "/wishlist/categories/{category_id}",
             delete(delete_category),
         )
+        .route(
+            "/wishlist/categories/{category_id}/event",
+            patch(set_category_event),
+        )
+        .route(
+            "/wishlist/categories/{category_id}/visibility",
+            patch(set_category_visibility),
+        )
         .route("/wishlist/categories/{category_id}/gifts", post(add_gift))
         .route(
             "/wishlist/categories/{category_id}/gifts/{gift_id}",
@@ -123,10 +269,26 @@ pub(crate) fn create_api_routes(
             "/wishlist/categories/{category_id}/gifts/{gift_id}",
             delete(delete_gift),
         )
+        .route(
+            "/wishlist/categories/{category_id}/gifts/{gift_id}/pictures",
+            post(add_gift_picture),
+        )
+        .route(
+            "/wishlist/categories/{category_id}/gifts/{gift_id}/pictures/reorder",
+            patch(reorder_gift_pictures),
+        )
+        .route(
+            "/wishlist/categories/{category_id}/gifts/{gift_id}/pictures/{picture}",
+            delete(delete_gift_picture),
+        )
         .route(
             "/wishlist/categories/{category_id}/gifts/{gift_id}/change_like",
             get(change_heart_gift),
         )
+        .route(
+            "/wishlist/categories/{category_id}/gifts/{gift_id}/event",
+            patch(set_gift_event),
+        )
         .route("/wishlist/friend/{friend_id}", get(get_friend_wishlist))
         .route(
             "/wishlist/friend/{friend_id}/categories/{category_id}/gifts",
@@ -140,6 +302,18 @@ pub(crate) fn create_api_routes(
             "/wishlist/friend/{friend_id}/categories/{category_id}/gifts/{gift_id}",
             delete(delete_secret_gift),
         )
+        .route(
+            "/wishlist/friend/{friend_id}/categories/{category_id}/gifts/{gift_id}/pictures",
+            post(add_secret_gift_picture),
+        )
+        .route(
+            "/wishlist/friend/{friend_id}/categories/{category_id}/gifts/{gift_id}/pictures/reorder",
+            patch(reorder_secret_gift_pictures),
+        )
+        .route(
+            "/wishlist/friend/{friend_id}/categories/{category_id}/gifts/{gift_id}/pictures/{picture}",
+            delete(delete_secret_gift_picture),
+        )
         .route(
             "/wishlist/friend/{friend_id}/gifts/{gift_id}",
             post(reserve_gift),
@@ -150,7 +324,15 @@ pub(crate) fn create_api_routes(
         )
         .route("/wishlist/{user_id}/pdf", get(get_wishlist_pdf))
         .route("/files/{file_name}", get(get_file))
-        .route("/files", post(upload_file))
+        .route(
+            "/files",
+            post(upload_file).layer(DefaultBodyLimit::max(configuration.uploads.max_size)),
+        )
+        .route("/notifications", get(get_notifications))
+        .route(
+            "/notifications/{notification_id}",
+            delete(delete_notification),
+        )
         .layer(AuthLayer {
             session_manager,
             jwt_manager,
@@ -159,5 +341,6 @@ pub(crate) fn create_api_routes(
         .route("/users/connect", post(login))
         .route("/users/password-reset", post(reset_password))
         .route("/users", put(create_user))
+        .route("/events/calendar/{token}", get(get_calendar))
         .layer(CookieManagerLayer::new())
 }
@@ -2,15 +2,14 @@ use crate::auth_middleware::AuthUser;
 use crate::error_catcher::AppError;
 use crate::managers::jwt_manager::JwtManager;
 use crate::managers::session_manager::SessionManager;
-use crate::managers::users_manager::UsersManager;
-use axum::extract::State;
+use crate::managers::users_manager::{LinkedAccounts, UsersManager};
+use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::Json;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tower_cookies::cookie::SameSite;
 use tower_cookies::{Cookie, Cookies};
-use tracing::log::error;
 
 #[derive(Deserialize)]
 pub(crate) struct LoginDetail {
@@ -30,12 +29,15 @@ pub(crate) struct LoginResponse {
 pub(crate) async fn login(
     State(users_manager): State<UsersManager>,
     State(jwt_manager): State<Arc<JwtManager>>,
-    State(mut session_manager): State<SessionManager>,
+    State(session_manager): State<SessionManager>,
     cookies: Cookies,
     login_detail: Json<LoginDetail>,
 ) -> Result<(StatusCode, Json<LoginResponse>), AppError> {
     let clean_user = users_manager.check_password(&login_detail.name, &login_detail.password)?;
-    let session = session_manager.generate_session(clean_user.id);
+    let session = session_manager.sign_in(
+        cookies.get("SESSION").as_ref().map(Cookie::value),
+        clean_user.id,
+    );
     cookies.add(
         Cookie::build(("SESSION", session))
             .secure(true)
@@ -57,42 +59,85 @@ pub(crate) async fn login(
     ))
 }
 
-pub(crate) async fn change_account(
+pub(crate) async fn logout(
+    State(session_manager): State<SessionManager>,
+    auth_user: AuthUser,
+) -> Result<StatusCode, AppError> {
+    session_manager.sign_out(&auth_user.session, auth_user.id);
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub(crate) async fn get_linked_accounts(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<LinkedAccounts>), AppError> {
+    let accounts = users_manager.get_linked_accounts(auth_user.id)?;
+    Ok((StatusCode::OK, Json(accounts)))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct LinkAccount {
+    name: String,
+}
+
+pub(crate) async fn link_account(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+    Json(link_account): Json<LinkAccount>,
+) -> Result<StatusCode, AppError> {
+    users_manager.link_account(auth_user.id, &link_account.name)?;
+    Ok(StatusCode::OK)
+}
+
+/**
+Both accounts are signed out of the browser sessions they entered by switching from the other one
+**/
+pub(crate) async fn unlink_account(
+    State(users_manager): State<UsersManager>,
+    State(session_manager): State<SessionManager>,
+    auth_user: AuthUser,
+    Path(user_id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    users_manager.unlink_account(auth_user.id, user_id)?;
+    session_manager.sign_out_switched(auth_user.id, user_id);
+    session_manager.sign_out_switched(user_id, auth_user.id);
+    Ok(StatusCode::OK)
+}
+
+/**
+Sign in a linked account in the same browser session, each account keeping its own token
+**/
+pub(crate) async fn switch_account(
     State(users_manager): State<UsersManager>,
     State(jwt_manager): State<Arc<JwtManager>>,
-    State(mut session_manager): State<SessionManager>,
+    State(session_manager): State<SessionManager>,
     auth_user: AuthUser,
-    login_detail: Json<LoginDetail>,
+    Path(user_id): Path<i64>,
 ) -> Result<(StatusCode, Json<LoginResponse>), AppError> {
-    let clean_user = users_manager.check_password(&login_detail.name, &login_detail.password)?;
-    if session_manager
-        .set_common_session(clean_user.id, auth_user.id)
-        .is_none()
-    {
-        error!("Try to change account from another user that does not have a session");
+    let Some(linked) = users_manager
+        .get_linked_accounts(auth_user.id)?
+        .linked
+        .into_iter()
+        .find(|linked| linked.id == user_id)
+    else {
         return Err(AppError::Unauthorized);
     };
-    let jwt = jwt_manager.generate_jwt(clean_user.id)?;
+    if !session_manager.switch(&auth_user.session, auth_user.id, linked.id) {
+        return Err(AppError::Unauthorized);
+    }
+    let jwt = jwt_manager.generate_jwt(linked.id)?;
     Ok((
         StatusCode::OK,
         Json(LoginResponse {
-            id: clean_user.id,
-            name: clean_user.name,
+            id: linked.id,
+            name: linked.name,
             token: jwt,
-            picture: clean_user.picture,
-            date_of_birth: clean_user.date_of_birth,
+            picture: linked.picture,
+            date_of_birth: linked.date_of_birth,
         }),
     ))
 }
 
-pub(crate) async fn logout(
-    State(session_manager): State<SessionManager>,
-    auth_user: AuthUser,
-) -> Result<StatusCode, AppError> {
-    session_manager.delete_session(auth_user.id);
-    Ok(StatusCode::ACCEPTED)
-}
-
 #[derive(Deserialize)]
 pub(crate) struct PasswordReset {
     name: String,
@@ -0,0 +1,209 @@
+use crate::auth_middleware::AuthUser;
+use crate::error_catcher::AppError;
+use crate::managers::jwt_manager::JwtManager;
+use crate::managers::session_manager::SessionManager;
+use crate::managers::users_manager::{CleanUser, UsersManager};
+use crate::routes::connection::LoginResponse;
+use crate::routes::users::{Deletion, RequestDeletion};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::log::error;
+
+#[derive(Deserialize)]
+pub(crate) struct AddDependent {
+    name: String,
+    date_of_birth: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Dependent {
+    id: i64,
+}
+
+pub async fn add_dependent(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+    Json(add_dependent): Json<AddDependent>,
+) -> Result<(StatusCode, Json<Dependent>), AppError> {
+    let id = users_manager.add_dependent(
+        auth_user.id,
+        &add_dependent.name,
+        add_dependent.date_of_birth,
+    )?;
+    Ok((StatusCode::OK, Json(Dependent { id })))
+}
+
+#[derive(Serialize)]
+pub(crate) struct Users {
+    users: Vec<CleanUser>,
+}
+
+pub async fn get_dependents(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<Users>), AppError> {
+    let users = users_manager.get_dependents(auth_user.id)?;
+    Ok((StatusCode::OK, Json(Users { users })))
+}
+
+pub async fn get_guardians(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+    Path(dependent_id): Path<i64>,
+) -> Result<(StatusCode, Json<Users>), AppError> {
+    if !users_manager.is_guardian(auth_user.id, dependent_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    let users = users_manager.get_guardians(dependent_id)?;
+    Ok((StatusCode::OK, Json(Users { users })))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AddGuardian {
+    name: String,
+}
+
+pub async fn add_guardian(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+    Path(dependent_id): Path<i64>,
+    Json(add_guardian): Json<AddGuardian>,
+) -> Result<StatusCode, AppError> {
+    if !users_manager.is_guardian(auth_user.id, dependent_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    users_manager.add_guardian(dependent_id, &add_guardian.name)?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn get_dependent_deletion(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+    Path(dependent_id): Path<i64>,
+) -> Result<(StatusCode, Json<Deletion>), AppError> {
+    if !users_manager.is_guardian(auth_user.id, dependent_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    let deletion_at = users_manager.get_deletion(dependent_id)?;
+    Ok((StatusCode::OK, Json(Deletion { deletion_at })))
+}
+
+/**
+Dependents have no password, so a guardian confirms the deletion with their own.
+Any guardian can cancel it during the grace period.
+**/
+pub async fn request_dependent_deletion(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+    Path(dependent_id): Path<i64>,
+    Json(request_deletion): Json<RequestDeletion>,
+) -> Result<(StatusCode, Json<Deletion>), AppError> {
+    if !users_manager.is_guardian(auth_user.id, dependent_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    let name = users_manager.get_name(auth_user.id)?;
+    users_manager.check_password(&name, &request_deletion.password)?;
+    let deletion_at = users_manager.request_deletion(dependent_id)?;
+    Ok((
+        StatusCode::OK,
+        Json(Deletion {
+            deletion_at: Some(deletion_at),
+        }),
+    ))
+}
+
+pub async fn cancel_dependent_deletion(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+    Path(dependent_id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    if !users_manager.is_guardian(auth_user.id, dependent_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    users_manager.cancel_deletion(dependent_id)?;
+    Ok(StatusCode::OK)
+}
+
+/**
+The former guardian is signed out of the dependent account in their browser sessions
+**/
+pub async fn remove_guardian(
+    State(users_manager): State<UsersManager>,
+    State(session_manager): State<SessionManager>,
+    auth_user: AuthUser,
+    Path((dependent_id, guardian_id)): Path<(i64, i64)>,
+) -> Result<StatusCode, AppError> {
+    if !users_manager.is_guardian(auth_user.id, dependent_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    users_manager.remove_guardian(dependent_id, guardian_id)?;
+    session_manager.sign_out_switched(guardian_id, dependent_id);
+    Ok(StatusCode::OK)
+}
+
+/**
+A guardian signs in the dependent account without password, in the same browser session
+**/
+pub async fn switch_to_dependent(
+    State(users_manager): State<UsersManager>,
+    State(jwt_manager): State<Arc<JwtManager>>,
+    State(session_manager): State<SessionManager>,
+    auth_user: AuthUser,
+    Path(dependent_id): Path<i64>,
+) -> Result<(StatusCode, Json<LoginResponse>), AppError> {
+    let Some(dependent) = users_manager
+        .get_dependents(auth_user.id)?
+        .into_iter()
+        .find(|dependent| dependent.id == dependent_id)
+    else {
+        return Err(AppError::Unauthorized);
+    };
+    if !session_manager.switch(&auth_user.session, auth_user.id, dependent.id) {
+        return Err(AppError::Unauthorized);
+    }
+    switch_response(&jwt_manager, dependent)
+}
+
+/**
+Go back to a guardian signed in the same browser session
+**/
+pub async fn switch_to_guardian(
+    State(users_manager): State<UsersManager>,
+    State(jwt_manager): State<Arc<JwtManager>>,
+    State(session_manager): State<SessionManager>,
+    auth_user: AuthUser,
+    Path(guardian_id): Path<i64>,
+) -> Result<(StatusCode, Json<LoginResponse>), AppError> {
+    let Some(guardian) = users_manager
+        .get_guardians(auth_user.id)?
+        .into_iter()
+        .find(|guardian| guardian.id == guardian_id)
+    else {
+        return Err(AppError::Unauthorized);
+    };
+    if !session_manager.is_signed_in(&auth_user.session, guardian.id) {
+        error!("Try to switch back to guardian {guardian_id} from another session");
+        return Err(AppError::Unauthorized);
+    }
+    switch_response(&jwt_manager, guardian)
+}
+
+fn switch_response(
+    jwt_manager: &JwtManager,
+    user: CleanUser,
+) -> Result<(StatusCode, Json<LoginResponse>), AppError> {
+    let jwt = jwt_manager.generate_jwt(user.id)?;
+    Ok((
+        StatusCode::OK,
+        Json(LoginResponse {
+            id: user.id,
+            name: user.name,
+            token: jwt,
+            picture: user.picture,
+            date_of_birth: user.date_of_birth,
+        }),
+    ))
+}
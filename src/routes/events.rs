@@ -1,16 +1,66 @@
 use crate::auth_middleware::AuthUser;
 use crate::error_catcher::AppError;
 use crate::managers::events_manager::{Event, EventsManager};
-use axum::extract::State;
-use axum::http::StatusCode;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
 use axum::Json;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_HORIZON_MONTHS: u32 = 6;
+const MAX_HORIZON_MONTHS: u32 = 24;
+
+#[derive(Deserialize)]
+pub(crate) struct EventsQuery {
+    months: Option<u32>,
+}
 
 pub(crate) async fn get_events(
     State(events_manager): State<EventsManager>,
     auth_user: AuthUser,
+    Query(events_query): Query<EventsQuery>,
 ) -> Result<(StatusCode, Json<Vec<Event>>), AppError> {
+    let months = events_query.months.unwrap_or(DEFAULT_HORIZON_MONTHS);
+    if months == 0 || months > MAX_HORIZON_MONTHS {
+        return Err(AppError::BadRequest);
+    }
     Ok((
         StatusCode::OK,
-        Json(events_manager.get_events(auth_user.id)?),
+        Json(events_manager.get_events(auth_user.id, months)?),
     ))
 }
+
+#[derive(Serialize)]
+pub(crate) struct CalendarToken {
+    token: String,
+}
+
+pub(crate) async fn create_calendar_token(
+    State(events_manager): State<EventsManager>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<CalendarToken>), AppError> {
+    let token = events_manager.create_calendar_token(auth_user.id)?;
+    Ok((StatusCode::OK, Json(CalendarToken { token })))
+}
+
+pub(crate) async fn revoke_calendar_token(
+    State(events_manager): State<EventsManager>,
+    auth_user: AuthUser,
+) -> Result<StatusCode, AppError> {
+    events_manager.revoke_calendar_token(auth_user.id)?;
+    Ok(StatusCode::OK)
+}
+
+/**
+Not behind the authentication as calendar applications cannot send the session cookie,
+the token is the secret.
+**/
+pub(crate) async fn get_calendar(
+    State(events_manager): State<EventsManager>,
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id = events_manager.get_calendar_user(&token)?;
+    let calendar = events_manager.get_calendar(user_id)?;
+    let headers = [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")];
+    Ok((headers, calendar))
+}
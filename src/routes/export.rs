@@ -0,0 +1,86 @@
+use crate::auth_middleware::AuthUser;
+use crate::clock::Clock;
+use crate::error_catcher::AppError;
+use crate::managers::files_manager::{FilesManager, OwnedFile};
+use crate::managers::friends_manager::{FriendGroup, FriendsManager, Friendship};
+use crate::managers::invitations_manager::{Invitation, InvitationsManager};
+use crate::managers::notifications_manager::{Notification, NotificationsManager};
+use crate::managers::session_manager::{SessionInfo, SessionManager};
+use crate::managers::users_manager::{
+    Account, CleanUser, LinkedAccounts, Profile, UserSettings, UsersManager,
+};
+use crate::managers::wishlist_manager::{ExportedGift, WishList, WishlistManager};
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Serialize;
+use std::sync::Arc;
+
+/**
+Everything stored about a user. Passwords, tokens of other users and session ids are left out.
+Files are only listed, their content can be downloaded from `/files/{name}`.
+**/
+#[derive(Serialize)]
+pub(crate) struct DataExport {
+    exported_at: i64,
+    account: Account,
+    settings: UserSettings,
+    profile: Profile,
+    friendships: Vec<Friendship>,
+    friends: Vec<CleanUser>,
+    blocked_users: Vec<CleanUser>,
+    groups: Vec<FriendGroup>,
+    linked_accounts: LinkedAccounts,
+    guardians: Vec<CleanUser>,
+    dependents: Vec<CleanUser>,
+    wishlist: WishList,
+    reservations: Vec<ExportedGift>,
+    secret_gifts: Vec<ExportedGift>,
+    files: Vec<OwnedFile>,
+    invitations: Vec<Invitation>,
+    notifications: Vec<Notification>,
+    /** Browsers the user is currently signed in **/
+    sessions: Vec<SessionInfo>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn get_export(
+    State(users_manager): State<UsersManager>,
+    State(friends_manager): State<FriendsManager>,
+    State(wishlist_manager): State<WishlistManager>,
+    State(files_manager): State<FilesManager>,
+    State(invitations_manager): State<InvitationsManager>,
+    State(notifications_manager): State<NotificationsManager>,
+    State(session_manager): State<SessionManager>,
+    State(clock): State<Arc<dyn Clock>>,
+    auth_user: AuthUser,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id = auth_user.id;
+    let export = DataExport {
+        exported_at: clock.now().timestamp(),
+        account: users_manager.get_account(user_id)?,
+        settings: users_manager.get_settings(user_id)?,
+        profile: users_manager.get_profile(user_id, false)?,
+        friendships: friends_manager.get_friendships(user_id)?,
+        friends: friends_manager.get_friends(user_id)?,
+        blocked_users: friends_manager.get_blocked_users(user_id)?,
+        groups: friends_manager.get_groups(user_id)?,
+        linked_accounts: users_manager.get_linked_accounts(user_id)?,
+        guardians: users_manager.get_guardians(user_id)?,
+        dependents: users_manager.get_dependents(user_id)?,
+        wishlist: wishlist_manager.get_my_wishlist(user_id)?,
+        reservations: wishlist_manager.get_reservations(user_id)?,
+        secret_gifts: wishlist_manager.get_added_secret_gifts(user_id)?,
+        files: files_manager.get_owned_files(user_id)?,
+        invitations: invitations_manager.get_invitations(user_id)?,
+        notifications: notifications_manager.get_notifications(user_id)?,
+        sessions: session_manager.get_sessions(user_id),
+    };
+
+    let headers = [(
+        header::CONTENT_DISPOSITION,
+        "attachment; filename=\"mygift-export.json\"",
+    )];
+    Ok((headers, Json(export)))
+}
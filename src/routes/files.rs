@@ -1,38 +1,103 @@
+use crate::auth_middleware::AuthUser;
 use crate::configuration::Configuration;
 use crate::error_catcher::AppError;
+use crate::managers::files_manager::{is_valid_name, FilesManager, ImageSize, VariantFormat};
 use axum::body::Body;
-use axum::extract::{Multipart, Path, State};
-use axum::http::{header, StatusCode};
-use axum::response::IntoResponse;
+use axum::extract::{Multipart, Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::Json;
-use image::imageops::FilterType;
-use image::ImageReader;
-use serde::Serialize;
-use std::fs;
-use std::io::Cursor;
-use std::path::PathBuf;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use std::time::SystemTime;
-use tracing::log::debug;
-use tracing::warn;
 
+#[derive(Deserialize)]
+pub(crate) struct FileQuery {
+    size: Option<ImageSize>,
+}
+
+/**
+A variant never changes once created, its name being used as ETag. The original picture is
+sent while the variant is being created, without letting the client cache it.
+**/
 pub(crate) async fn get_file(
-    State(configuration): State<Arc<Configuration>>,
+    State(files_manager): State<FilesManager>,
+    auth_user: AuthUser,
     Path(file_name): Path<String>,
-) -> impl IntoResponse {
-    //Could be async
-    let root_path = PathBuf::from(&configuration.upload_file_storage);
-    let root_output_path = root_path.parent().unwrap().to_path_buf().join("tmp_rs");
-    let original_file = root_path.join(file_name);
-    let Some(output_file) = resize_file(&original_file, &root_output_path) else {
-        return Err(StatusCode::NOT_FOUND);
+    Query(file_query): Query<FileQuery>,
+    request_headers: HeaderMap,
+) -> Result<Response, AppError> {
+    if !is_valid_name(&file_name) {
+        return Err(AppError::BadRequest);
+    }
+    //Not telling whether the file exists
+    if !files_manager.can_access(auth_user.id, &file_name)? {
+        return Err(AppError::NotFound);
+    }
+    let accept = request_headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok());
+    let format = VariantFormat::from_accept(accept);
+    let Some(variant) = files_manager
+        .get_variant(&file_name, file_query.size.unwrap_or_default(), format)
+        .await?
+    else {
+        return Err(AppError::NotFound);
     };
-    let Ok(content) = fs::read(output_file) else {
-        return Err(StatusCode::NOT_FOUND);
+    if variant.pending {
+        let headers = [
+            (header::CONTENT_TYPE, variant.mime_type),
+            (header::CACHE_CONTROL, "no-cache"),
+        ];
+        return Ok((headers, Body::from(variant.content)).into_response());
+    }
+
+    let etag = format!("\"{}\"", variant.name);
+    let last_modified = variant
+        .created_at
+        .and_then(|created_at| DateTime::from_timestamp(created_at, 0));
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("private, max-age=31536000, immutable"),
+    );
+    headers.insert(header::VARY, HeaderValue::from_static("Accept"));
+    if let Some(last_modified) = last_modified {
+        let last_modified = last_modified
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        headers.insert(
+            header::LAST_MODIFIED,
+            HeaderValue::from_str(&last_modified).unwrap(),
+        );
+    }
+
+    let not_modified = if let Some(if_none_match) = request_headers.get(header::IF_NONE_MATCH) {
+        if_none_match
+            .to_str()
+            .unwrap_or_default()
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == etag || tag == "*")
+    } else if let Some(if_modified_since) = request_headers.get(header::IF_MODIFIED_SINCE) {
+        let if_modified_since = if_modified_since
+            .to_str()
+            .ok()
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok());
+        matches!((last_modified, if_modified_since), (Some(modified), Some(since)) if modified <= since)
+    } else {
+        false
     };
-    let body = Body::from(content);
-    let headers = [(header::CONTENT_TYPE, "image")];
-    Ok((headers, body))
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(variant.mime_type),
+    );
+    Ok((headers, Body::from(variant.content)).into_response())
 }
 
 #[derive(Serialize)]
@@ -41,96 +106,21 @@ pub(crate) struct FileName {
 }
 
 pub(crate) async fn upload_file(
+    State(files_manager): State<FilesManager>,
     State(configuration): State<Arc<Configuration>>,
+    auth_user: AuthUser,
     mut multipart: Multipart,
 ) -> Result<(StatusCode, Json<FileName>), AppError> {
-    //Could be async
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        let name = field.name().unwrap().to_string();
-        let data = field.bytes().await.unwrap();
-
-        if name == "file" {
-            let Ok(data) = ImageReader::new(Cursor::new(data)).with_guessed_format() else {
-                return Err(AppError::Conflict);
-            };
-            let Ok(image) = data.decode() else {
-                return Err(AppError::Conflict);
-            };
-            let image = image.resize(300, 300, FilterType::Triangle);
-            let file_name = format!(
-                "upload-{}.png",
-                SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis()
-            );
-            let output_file = PathBuf::from(&configuration.upload_file_storage).join(&file_name);
-            if let Err(error) = image.save(&output_file) {
-                debug!("Could not save file upload to {output_file:?}: {error}");
-                return Err(AppError::Conflict);
-            }
-
-            return Ok((StatusCode::OK, Json(FileName { name: file_name })));
-        }
-    }
-
-    Err(AppError::Conflict)
-}
-
-pub fn resize_file(original_file: &PathBuf, tmp_folder: &PathBuf) -> Option<PathBuf> {
-    let Some(file_stem) = original_file.file_stem() else {
-        debug!("Could not read stem of {original_file:?}");
-        return None;
-    };
-
-    let file_name_oss = file_stem.to_ascii_lowercase();
-    let file_name = file_name_oss.to_string_lossy();
-    let Some(extension) = original_file.extension() else {
-        debug!("Could not read extension of {original_file:?}");
-        return None;
-    };
-    let extension_oss = extension.to_ascii_lowercase();
-    let extension = extension_oss.to_string_lossy();
-    if extension != "jpeg" && extension != "jpg" && extension != "png" && extension != "webp" {
-        let output_file = tmp_folder.join(format!("{file_name}.{extension}"));
-        if output_file.exists() {
-            return Some(output_file);
-        }
-        warn!("Format not supported {file_name} - copy the file over as is");
-        if fs::copy(original_file, &output_file).is_err() {
-            return None;
-        }
-        return Some(output_file);
-    }
-    let mut output_file = tmp_folder.clone();
-    if extension == "jpeg" || extension == "jpg" {
-        output_file.push(format!("{file_name}.jpg"));
-    } else {
-        output_file.push(format!("{file_name}.webp"));
-    }
-    if output_file.exists() {
-        return Some(output_file);
-    }
-
-    let image_reader = match ImageReader::open(original_file) {
-        Ok(ir) => ir,
-        Err(error) => {
-            debug!("Could not read {original_file:?}: {error}");
-            return None;
-        }
-    };
-    let img = match image_reader.decode() {
-        Ok(img) => img,
-        Err(error) => {
-            debug!("Could not decode {original_file:?}: {error}");
-            return None;
+    while let Some(field) = multipart.next_field().await? {
+        if field.name() != Some("file") {
+            continue;
         }
-    };
-    let img = img.resize(300, 300, FilterType::Triangle);
-    if let Err(error) = img.save(&output_file) {
-        debug!("Could not save file {original_file:?} to {output_file:?}: {error}");
-        return None;
+        let content = field.bytes().await?;
+        let file_name = files_manager
+            .store_picture(auth_user.id, content.into(), &configuration.uploads)
+            .await?;
+        return Ok((StatusCode::OK, Json(FileName { name: file_name })));
     }
 
-    Some(output_file)
+    Err(AppError::BadRequest)
 }
@@ -1,11 +1,15 @@
 use crate::auth_middleware::AuthUser;
 use crate::error_catcher::AppError;
-use crate::managers::friends_manager::{FriendsManager, RequestStatus, Requests};
-use crate::managers::users_manager::CleanUser;
-use axum::extract::{Path, State};
+use crate::managers::friends_manager::{
+    FriendGroup, FriendsManager, FriendsManagerError, RequestStatus, Requests, Suggestion,
+};
+use crate::managers::users_manager::{CleanUser, Profile, UsersManager};
+use crate::managers::wishlist_manager::{ReservationPolicy, WishlistManager};
+use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
 use axum::Json;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 #[derive(Deserialize)]
 pub(crate) struct AddFriend {
@@ -33,17 +37,20 @@ pub(crate) struct Friend {
     date_of_birth: Option<i64>,
 }
 
+impl From<CleanUser> for Friend {
+    fn from(u: CleanUser) -> Self {
+        Friend {
+            id: u.id,
+            name: u.name,
+            picture: u.picture,
+            date_of_birth: u.date_of_birth,
+        }
+    }
+}
+
 impl From<Vec<CleanUser>> for Friends {
     fn from(value: Vec<CleanUser>) -> Self {
-        let friends = value
-            .into_iter()
-            .map(|u| Friend {
-                id: u.id,
-                name: u.name,
-                picture: u.picture,
-                date_of_birth: u.date_of_birth,
-            })
-            .collect();
+        let friends = value.into_iter().map(Friend::from).collect();
         Friends { friends }
     }
 }
@@ -69,6 +76,19 @@ pub async fn get_friend_id(
     Ok((StatusCode::OK, Json(FriendId { id: friend_id })))
 }
 
+pub async fn get_friend_profile(
+    State(friends_manager): State<FriendsManager>,
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+    Path(friend_id): Path<i64>,
+) -> Result<(StatusCode, Json<Profile>), AppError> {
+    if !friends_manager.is_my_friend(auth_user.id, friend_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    let profile = users_manager.get_profile(friend_id, true)?;
+    Ok((StatusCode::OK, Json(profile)))
+}
+
 pub async fn get_requests(
     State(friends_manager): State<FriendsManager>,
     auth_user: AuthUser,
@@ -103,3 +123,147 @@ pub async fn cancel_request(
     friends_manager.cancel_sent_request(request_id, auth_user.id)?;
     Ok(StatusCode::OK)
 }
+
+#[derive(Deserialize)]
+pub(crate) struct RemoveFriendQuery {
+    #[serde(default)]
+    reservations: ReservationPolicy,
+}
+
+pub async fn remove_friend(
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+    Path(friend_id): Path<i64>,
+    Query(query): Query<RemoveFriendQuery>,
+) -> Result<StatusCode, AppError> {
+    friends_manager.remove_friend(auth_user.id, friend_id, query.reservations)?;
+    Ok(StatusCode::OK)
+}
+
+#[derive(Serialize)]
+pub(crate) struct BlockedUsers {
+    users: Vec<Friend>,
+}
+
+pub async fn get_blocked_users(
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<BlockedUsers>), AppError> {
+    let users = friends_manager.get_blocked_users(auth_user.id)?;
+    let users = users.into_iter().map(Friend::from).collect();
+    Ok((StatusCode::OK, Json(BlockedUsers { users })))
+}
+
+pub async fn block_user(
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+    Json(block_user): Json<AddFriend>,
+) -> Result<StatusCode, AppError> {
+    friends_manager.block_user(auth_user.id, &block_user.name)?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn unblock_user(
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+    Path(user_id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    friends_manager.unblock_user(auth_user.id, user_id)?;
+    Ok(StatusCode::OK)
+}
+
+#[derive(Serialize)]
+pub(crate) struct FriendGroups {
+    groups: Vec<FriendGroup>,
+}
+
+pub async fn get_groups(
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<FriendGroups>), AppError> {
+    let groups = friends_manager.get_groups(auth_user.id)?;
+    Ok((StatusCode::OK, Json(FriendGroups { groups })))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AddGroup {
+    name: String,
+    members: HashSet<i64>,
+}
+
+pub async fn add_group(
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+    Json(add_group): Json<AddGroup>,
+) -> Result<StatusCode, AppError> {
+    check_members(auth_user.id, &add_group.members, &friends_manager)?;
+    friends_manager.add_group(auth_user.id, &add_group.name, add_group.members)?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn edit_group(
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+    Path(group_id): Path<i64>,
+    Json(edit_group): Json<AddGroup>,
+) -> Result<StatusCode, AppError> {
+    if !friends_manager.is_my_group(auth_user.id, group_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    check_members(auth_user.id, &edit_group.members, &friends_manager)?;
+    friends_manager.edit_group(group_id, &edit_group.name, edit_group.members)?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn delete_group(
+    State(friends_manager): State<FriendsManager>,
+    State(wishlist_manager): State<WishlistManager>,
+    auth_user: AuthUser,
+    Path(group_id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    if !friends_manager.is_my_group(auth_user.id, group_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    wishlist_manager.remove_group_visibility(group_id)?;
+    friends_manager.delete_group(group_id)?;
+    Ok(StatusCode::OK)
+}
+
+fn check_members(
+    user_id: i64,
+    members: &HashSet<i64>,
+    friends_manager: &FriendsManager,
+) -> Result<(), AppError> {
+    for member in members {
+        if !friends_manager.is_my_friend(user_id, *member)? {
+            return Err(AppError::FriendsManager(FriendsManagerError::UnknownUser(
+                member.to_string(),
+            )));
+        }
+    }
+    Ok(())
+}
+
+const MAX_SUGGESTIONS: usize = 20;
+
+#[derive(Serialize)]
+pub(crate) struct Suggestions {
+    suggestions: Vec<Suggestion>,
+}
+
+pub async fn get_suggestions(
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<Suggestions>), AppError> {
+    let suggestions = friends_manager.get_suggestions(auth_user.id, MAX_SUGGESTIONS)?;
+    Ok((StatusCode::OK, Json(Suggestions { suggestions })))
+}
+
+pub async fn dismiss_suggestion(
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+    Path(user_id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    friends_manager.dismiss_suggestion(auth_user.id, user_id)?;
+    Ok(StatusCode::OK)
+}
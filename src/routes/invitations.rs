@@ -0,0 +1,67 @@
+use crate::auth_middleware::AuthUser;
+use crate::error_catcher::AppError;
+use crate::managers::invitations_manager::{Invitation, InvitationsManager};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_VALID_DAYS: u32 = 7;
+const MAX_VALID_DAYS: u32 = 90;
+
+#[derive(Deserialize)]
+pub(crate) struct CreateInvitation {
+    valid_days: Option<u32>,
+    max_uses: Option<u32>,
+}
+
+pub async fn create_invitation(
+    State(invitations_manager): State<InvitationsManager>,
+    auth_user: AuthUser,
+    Json(create_invitation): Json<CreateInvitation>,
+) -> Result<(StatusCode, Json<Invitation>), AppError> {
+    let valid_days = create_invitation
+        .valid_days
+        .unwrap_or(DEFAULT_VALID_DAYS)
+        .clamp(1, MAX_VALID_DAYS);
+    if create_invitation.max_uses == Some(0) {
+        return Err(AppError::BadRequest);
+    }
+    let invitation = invitations_manager.create_invitation(
+        auth_user.id,
+        valid_days,
+        create_invitation.max_uses,
+    )?;
+    Ok((StatusCode::OK, Json(invitation)))
+}
+
+#[derive(Serialize)]
+pub(crate) struct Invitations {
+    invitations: Vec<Invitation>,
+}
+
+pub async fn get_invitations(
+    State(invitations_manager): State<InvitationsManager>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<Invitations>), AppError> {
+    let invitations = invitations_manager.get_invitations(auth_user.id)?;
+    Ok((StatusCode::OK, Json(Invitations { invitations })))
+}
+
+pub async fn revoke_invitation(
+    State(invitations_manager): State<InvitationsManager>,
+    auth_user: AuthUser,
+    Path(token): Path<String>,
+) -> Result<StatusCode, AppError> {
+    invitations_manager.revoke_invitation(auth_user.id, &token)?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn redeem_invitation(
+    State(invitations_manager): State<InvitationsManager>,
+    auth_user: AuthUser,
+    Path(token): Path<String>,
+) -> Result<StatusCode, AppError> {
+    invitations_manager.redeem_invitation(&token, auth_user.id)?;
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,25 @@
+use crate::auth_middleware::AuthUser;
+use crate::error_catcher::AppError;
+use crate::managers::notifications_manager::{Notification, NotificationsManager};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+
+pub(crate) async fn get_notifications(
+    State(notifications_manager): State<NotificationsManager>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<Vec<Notification>>), AppError> {
+    Ok((
+        StatusCode::OK,
+        Json(notifications_manager.get_notifications(auth_user.id)?),
+    ))
+}
+
+pub(crate) async fn delete_notification(
+    State(notifications_manager): State<NotificationsManager>,
+    auth_user: AuthUser,
+    Path(notification_id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    notifications_manager.delete_notification(auth_user.id, notification_id)?;
+    Ok(StatusCode::OK)
+}
@@ -1,13 +1,20 @@
 use crate::auth_middleware::AuthUser;
+use crate::configuration::Configuration;
 use crate::error_catcher::AppError;
+use crate::managers::files_manager::FilesManager;
+use crate::managers::friends_manager::{FoundUser, FriendsManager};
+use crate::managers::invitations_manager::InvitationsManager;
 use crate::managers::jwt_manager::JwtManager;
 use crate::managers::session_manager::SessionManager;
-use crate::managers::users_manager::UsersManager;
+use crate::managers::users_manager::{
+    EditProfile, EditSettings, EditUser, Profile, UserSettings, UsersManager,
+};
+use crate::rate_limiter::RateLimiter;
 use crate::routes::connection::LoginResponse;
-use axum::extract::State;
+use axum::extract::{Query, State};
 use axum::http::StatusCode;
 use axum::Json;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tower_cookies::cookie::SameSite;
 use tower_cookies::{Cookie, Cookies};
@@ -16,17 +23,33 @@ use tower_cookies::{Cookie, Cookies};
 pub(crate) struct CreateUser {
     name: String,
     password: String,
+    /** Token of an invitation, making the new user a friend of the one who shared it **/
+    invitation: Option<String>,
 }
 
+/**
+In invite-only mode, users can only sign up with an invitation
+**/
 pub(crate) async fn create_user(
     State(users_manager): State<UsersManager>,
+    State(invitations_manager): State<InvitationsManager>,
     State(jwt_manager): State<Arc<JwtManager>>,
-    State(mut session_manager): State<SessionManager>,
+    State(session_manager): State<SessionManager>,
+    State(configuration): State<Arc<Configuration>>,
     cookies: Cookies,
     create_user: Json<CreateUser>,
 ) -> Result<(StatusCode, Json<LoginResponse>), AppError> {
-    let user_id = users_manager.add_user(&create_user.name, &create_user.password)?;
-    let session = session_manager.generate_session(user_id);
+    let user_id = match &create_user.invitation {
+        Some(invitation) => invitations_manager.sign_up_with(
+            invitation,
+            &create_user.name,
+            &create_user.password,
+        )?,
+        None if configuration.invite_only => return Err(AppError::Unauthorized),
+        None => users_manager.add_user(&create_user.name, &create_user.password)?,
+    };
+    let session =
+        session_manager.sign_in(cookies.get("SESSION").as_ref().map(Cookie::value), user_id);
     cookies.add(
         Cookie::build(("SESSION", session))
             .http_only(true)
@@ -47,23 +70,145 @@ pub(crate) async fn create_user(
     ))
 }
 
+pub(crate) async fn edit_user(
+    State(users_manager): State<UsersManager>,
+    State(files_manager): State<FilesManager>,
+    auth_user: AuthUser,
+    Json(edit_user): Json<EditUser>,
+) -> Result<StatusCode, AppError> {
+    users_manager.edit_user(auth_user.id, &edit_user)?;
+    files_manager.delete_released().await?;
+    Ok(StatusCode::OK)
+}
+
+pub(crate) async fn get_settings(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<UserSettings>), AppError> {
+    let settings = users_manager.get_settings(auth_user.id)?;
+    Ok((StatusCode::OK, Json(settings)))
+}
+
+pub(crate) async fn edit_settings(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+    edit_settings: Json<EditSettings>,
+) -> Result<StatusCode, AppError> {
+    users_manager.edit_settings(auth_user.id, &edit_settings)?;
+    Ok(StatusCode::OK)
+}
+
 #[derive(Deserialize)]
-pub(crate) struct EditUser {
-    name: String,
-    picture: Option<String>,
-    date_of_birth: Option<i64>,
+pub(crate) struct ChangePassword {
+    current_password: String,
+    new_password: String,
 }
 
-pub(crate) async fn edit_user(
+/**
+The other browser sessions of the user are signed out
+**/
+pub(crate) async fn change_password(
     State(users_manager): State<UsersManager>,
+    State(session_manager): State<SessionManager>,
     auth_user: AuthUser,
-    edit_user: Json<EditUser>,
+    Json(change_password): Json<ChangePassword>,
 ) -> Result<StatusCode, AppError> {
-    users_manager.edit_user(
+    users_manager.change_password(
         auth_user.id,
-        &edit_user.name,
-        &edit_user.picture,
-        &edit_user.date_of_birth,
+        &change_password.current_password,
+        &change_password.new_password,
     )?;
+    session_manager.sign_out_elsewhere(&auth_user.session, auth_user.id);
     Ok(StatusCode::OK)
 }
+
+#[derive(Serialize)]
+pub(crate) struct Deletion {
+    pub(crate) deletion_at: Option<i64>,
+}
+
+pub(crate) async fn get_deletion(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<Deletion>), AppError> {
+    let deletion_at = users_manager.get_deletion(auth_user.id)?;
+    Ok((StatusCode::OK, Json(Deletion { deletion_at })))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RequestDeletion {
+    pub(crate) password: String,
+}
+
+/**
+The account stays usable until it is deleted, so the deletion can be cancelled
+**/
+pub(crate) async fn request_deletion(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+    Json(request_deletion): Json<RequestDeletion>,
+) -> Result<(StatusCode, Json<Deletion>), AppError> {
+    let name = users_manager.get_name(auth_user.id)?;
+    users_manager.check_password(&name, &request_deletion.password)?;
+    let deletion_at = users_manager.request_deletion(auth_user.id)?;
+    Ok((
+        StatusCode::OK,
+        Json(Deletion {
+            deletion_at: Some(deletion_at),
+        }),
+    ))
+}
+
+pub(crate) async fn cancel_deletion(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+) -> Result<StatusCode, AppError> {
+    users_manager.cancel_deletion(auth_user.id)?;
+    Ok(StatusCode::OK)
+}
+
+pub(crate) async fn get_profile(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<Profile>), AppError> {
+    let profile = users_manager.get_profile(auth_user.id, false)?;
+    Ok((StatusCode::OK, Json(profile)))
+}
+
+pub(crate) async fn edit_profile(
+    State(users_manager): State<UsersManager>,
+    auth_user: AuthUser,
+    edit_profile: Json<EditProfile>,
+) -> Result<StatusCode, AppError> {
+    users_manager.edit_profile(auth_user.id, &edit_profile)?;
+    Ok(StatusCode::OK)
+}
+
+const MIN_SEARCH_LENGTH: usize = 2;
+const MAX_SEARCH_RESULTS: usize = 20;
+
+#[derive(Deserialize)]
+pub(crate) struct SearchQuery {
+    query: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct FoundUsers {
+    users: Vec<FoundUser>,
+}
+
+pub(crate) async fn search_users(
+    State(friends_manager): State<FriendsManager>,
+    State(search_rate_limiter): State<RateLimiter>,
+    auth_user: AuthUser,
+    Query(search): Query<SearchQuery>,
+) -> Result<(StatusCode, Json<FoundUsers>), AppError> {
+    if search.query.trim().chars().count() < MIN_SEARCH_LENGTH {
+        return Err(AppError::BadRequest);
+    }
+    if !search_rate_limiter.check(auth_user.id) {
+        return Err(AppError::TooManyRequests);
+    }
+    let users = friends_manager.search_users(auth_user.id, &search.query, MAX_SEARCH_RESULTS)?;
+    Ok((StatusCode::OK, Json(FoundUsers { users })))
+}
@@ -1,19 +1,22 @@
 use crate::auth_middleware::AuthUser;
 use crate::error_catcher::AppError;
+use crate::managers::events_manager::EventKind;
+use crate::managers::files_manager::{FilesManager, ImageSize};
 use crate::managers::friends_manager::FriendsManager;
+use crate::managers::pdf_generator::{
+    get_pdf, Language, Layout, LayoutStyle, Orientation, PaperSize, PdfOptions, PdfWishList, Theme,
+};
+use crate::managers::users_manager::UsersManager;
 use crate::managers::wishlist_manager::{
-    FriendWishList, WishList, WishlistManager, WishlistManagerError,
+    FriendWishList, Visibility, WishList, WishlistManager, WishlistManagerError,
 };
-use axum::extract::{Path, State};
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
 use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
 use axum::Json;
 use serde::Deserialize;
-use std::collections::HashSet;
-use std::sync::Arc;
-use axum::body::Body;
-use axum::response::IntoResponse;
-use crate::configuration::Configuration;
-use crate::managers::pdf_generator::get_pdf;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Deserialize)]
 pub(crate) struct AddCategory {
@@ -76,6 +79,7 @@ pub async fn reorder_categories(
 
 pub async fn delete_category(
     State(wishlist_manager): State<WishlistManager>,
+    State(files_manager): State<FilesManager>,
     auth_user: AuthUser,
     Path(category_id): Path<i64>,
 ) -> Result<StatusCode, AppError> {
@@ -83,6 +87,7 @@ pub async fn delete_category(
         return Err(AppError::Unauthorized);
     }
     wishlist_manager.delete_category(auth_user.id, category_id)?;
+    files_manager.delete_released().await?;
     Ok(StatusCode::OK)
 }
 
@@ -112,6 +117,10 @@ fn build_category_user_list(
     Ok(all_users)
 }
 
+/**
+On edit, the picture replaces the cover of the gift, the other pictures of the gallery being
+managed on their own
+**/
 #[derive(Deserialize)]
 pub(crate) struct AddGift {
     name: String,
@@ -121,8 +130,25 @@ pub(crate) struct AddGift {
     picture: Option<String>,
 }
 
+/**
+A gift can only show pictures its author is allowed to see, as its readers will be allowed too
+**/
+fn check_pictures<'a>(
+    files_manager: &FilesManager,
+    user_id: i64,
+    pictures: impl IntoIterator<Item = &'a String>,
+) -> Result<(), AppError> {
+    for picture in pictures {
+        if !files_manager.can_access(user_id, picture)? {
+            return Err(AppError::Unauthorized);
+        }
+    }
+    Ok(())
+}
+
 pub async fn add_gift(
     State(wishlist_manager): State<WishlistManager>,
+    State(files_manager): State<FilesManager>,
     auth_user: AuthUser,
     Path(category_id): Path<i64>,
     Json(add_gift): Json<AddGift>,
@@ -130,13 +156,14 @@ pub async fn add_gift(
     if !wishlist_manager.is_my_category(auth_user.id, category_id)? {
         return Err(AppError::Unauthorized);
     }
+    check_pictures(&files_manager, auth_user.id, &add_gift.picture)?;
     wishlist_manager.add_gift(
         &add_gift.name,
         add_gift.description,
         add_gift.price,
         add_gift.where_to_buy,
         add_gift.picture,
-        false,
+        None,
         category_id,
     )?;
     Ok(StatusCode::OK)
@@ -144,6 +171,7 @@ pub async fn add_gift(
 
 pub async fn add_secret_gift(
     State(wishlist_manager): State<WishlistManager>,
+    State(files_manager): State<FilesManager>,
     State(friends_manager): State<FriendsManager>,
     auth_user: AuthUser,
     Path((friend_id, category_id)): Path<(i64, i64)>,
@@ -152,16 +180,17 @@ pub async fn add_secret_gift(
     if !friends_manager.is_my_friend(auth_user.id, friend_id)? {
         return Err(AppError::Unauthorized);
     }
-    if !wishlist_manager.is_my_category(friend_id, category_id)? {
+    if !wishlist_manager.is_visible_category(auth_user.id, friend_id, category_id)? {
         return Err(AppError::Unauthorized);
     }
+    check_pictures(&files_manager, auth_user.id, &add_gift.picture)?;
     wishlist_manager.add_gift(
         &add_gift.name,
         add_gift.description,
         add_gift.price,
         add_gift.where_to_buy,
         add_gift.picture,
-        true,
+        Some(auth_user.id),
         category_id,
     )?;
     Ok(StatusCode::OK)
@@ -169,16 +198,18 @@ pub async fn add_secret_gift(
 
 pub async fn edit_gift(
     State(wishlist_manager): State<WishlistManager>,
+    State(files_manager): State<FilesManager>,
     auth_user: AuthUser,
     Path((category_id, gift_id)): Path<(i64, i64)>,
     Json(add_gift): Json<AddGift>,
 ) -> Result<StatusCode, AppError> {
-    if !wishlist_manager.is_my_gift_for_edit(auth_user.id, gift_id)? {
+    if !wishlist_manager.is_my_gift_for_edit(auth_user.id, gift_id, false)? {
         return Err(AppError::Unauthorized);
     }
     if !wishlist_manager.is_my_category(auth_user.id, category_id)? {
         return Err(AppError::Unauthorized);
     }
+    check_pictures(&files_manager, auth_user.id, &add_gift.picture)?;
     wishlist_manager.edit_gift(
         gift_id,
         &add_gift.name,
@@ -188,6 +219,7 @@ pub async fn edit_gift(
         add_gift.picture,
         category_id,
     )?;
+    files_manager.delete_released().await?;
     Ok(StatusCode::OK)
 }
 
@@ -206,7 +238,7 @@ pub async fn reorder_gifts(
         return Err(AppError::Unauthorized);
     }
     for gift in &reorder_gifts.gifts {
-        if !wishlist_manager.is_my_gift(auth_user.id, category_id, *gift)? {
+        if !wishlist_manager.is_my_gift(auth_user.id, category_id, *gift, false)? {
             return Err(AppError::Unauthorized);
         }
     }
@@ -217,6 +249,7 @@ pub async fn reorder_gifts(
 
 pub async fn edit_secret_gift(
     State(wishlist_manager): State<WishlistManager>,
+    State(files_manager): State<FilesManager>,
     State(friends_manager): State<FriendsManager>,
     auth_user: AuthUser,
     Path((friend_id, category_id, gift_id)): Path<(i64, i64, i64)>,
@@ -225,12 +258,13 @@ pub async fn edit_secret_gift(
     if !friends_manager.is_my_friend(auth_user.id, friend_id)? {
         return Err(AppError::Unauthorized);
     }
-    if !wishlist_manager.is_my_gift_for_edit(friend_id, gift_id)? {
+    if !wishlist_manager.is_my_gift_for_edit(friend_id, gift_id, true)? {
         return Err(AppError::Unauthorized);
     }
-    if !wishlist_manager.is_my_category(friend_id, category_id)? {
+    if !wishlist_manager.is_visible_category(auth_user.id, friend_id, category_id)? {
         return Err(AppError::Unauthorized);
     }
+    check_pictures(&files_manager, auth_user.id, &add_gift.picture)?;
     wishlist_manager.edit_gift(
         gift_id,
         &add_gift.name,
@@ -240,23 +274,27 @@ pub async fn edit_secret_gift(
         add_gift.picture,
         category_id,
     )?;
+    files_manager.delete_released().await?;
     Ok(StatusCode::OK)
 }
 
 pub async fn delete_gift(
     State(wishlist_manager): State<WishlistManager>,
+    State(files_manager): State<FilesManager>,
     auth_user: AuthUser,
     Path((category_id, gift_id)): Path<(i64, i64)>,
 ) -> Result<StatusCode, AppError> {
-    if !wishlist_manager.is_my_gift(auth_user.id, category_id, gift_id)? {
+    if !wishlist_manager.is_my_gift(auth_user.id, category_id, gift_id, false)? {
         return Err(AppError::Unauthorized);
     }
     wishlist_manager.delete_gift(gift_id)?;
+    files_manager.delete_released().await?;
     Ok(StatusCode::OK)
 }
 
 pub async fn delete_secret_gift(
     State(wishlist_manager): State<WishlistManager>,
+    State(files_manager): State<FilesManager>,
     State(friends_manager): State<FriendsManager>,
     auth_user: AuthUser,
     Path((friend_id, category_id, gift_id)): Path<(i64, i64, i64)>,
@@ -264,10 +302,193 @@ pub async fn delete_secret_gift(
     if !friends_manager.is_my_friend(auth_user.id, friend_id)? {
         return Err(AppError::Unauthorized);
     }
-    if !wishlist_manager.is_my_gift(friend_id, category_id, gift_id)? {
+    if !wishlist_manager.is_my_gift(friend_id, category_id, gift_id, true)?
+        || !wishlist_manager.is_visible_category(auth_user.id, friend_id, category_id)?
+    {
         return Err(AppError::Unauthorized);
     }
     wishlist_manager.delete_gift(gift_id)?;
+    files_manager.delete_released().await?;
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AddGiftPicture {
+    picture: String,
+}
+
+pub async fn add_gift_picture(
+    State(wishlist_manager): State<WishlistManager>,
+    State(files_manager): State<FilesManager>,
+    auth_user: AuthUser,
+    Path((category_id, gift_id)): Path<(i64, i64)>,
+    Json(add_gift_picture): Json<AddGiftPicture>,
+) -> Result<StatusCode, AppError> {
+    if !wishlist_manager.is_my_gift(auth_user.id, category_id, gift_id, false)? {
+        return Err(AppError::Unauthorized);
+    }
+    check_pictures(&files_manager, auth_user.id, [&add_gift_picture.picture])?;
+    wishlist_manager.add_gift_picture(gift_id, &add_gift_picture.picture)?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn add_secret_gift_picture(
+    State(wishlist_manager): State<WishlistManager>,
+    State(files_manager): State<FilesManager>,
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+    Path((friend_id, category_id, gift_id)): Path<(i64, i64, i64)>,
+    Json(add_gift_picture): Json<AddGiftPicture>,
+) -> Result<StatusCode, AppError> {
+    if !friends_manager.is_my_friend(auth_user.id, friend_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    if !wishlist_manager.is_my_gift(friend_id, category_id, gift_id, true)?
+        || !wishlist_manager.is_visible_category(auth_user.id, friend_id, category_id)?
+    {
+        return Err(AppError::Unauthorized);
+    }
+    check_pictures(&files_manager, auth_user.id, [&add_gift_picture.picture])?;
+    wishlist_manager.add_gift_picture(gift_id, &add_gift_picture.picture)?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn delete_gift_picture(
+    State(wishlist_manager): State<WishlistManager>,
+    State(files_manager): State<FilesManager>,
+    auth_user: AuthUser,
+    Path((category_id, gift_id, picture)): Path<(i64, i64, String)>,
+) -> Result<StatusCode, AppError> {
+    if !wishlist_manager.is_my_gift(auth_user.id, category_id, gift_id, false)? {
+        return Err(AppError::Unauthorized);
+    }
+    wishlist_manager.delete_gift_picture(gift_id, &picture)?;
+    files_manager.delete_released().await?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn delete_secret_gift_picture(
+    State(wishlist_manager): State<WishlistManager>,
+    State(files_manager): State<FilesManager>,
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+    Path((friend_id, category_id, gift_id, picture)): Path<(i64, i64, i64, String)>,
+) -> Result<StatusCode, AppError> {
+    if !friends_manager.is_my_friend(auth_user.id, friend_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    if !wishlist_manager.is_my_gift(friend_id, category_id, gift_id, true)?
+        || !wishlist_manager.is_visible_category(auth_user.id, friend_id, category_id)?
+    {
+        return Err(AppError::Unauthorized);
+    }
+    wishlist_manager.delete_gift_picture(gift_id, &picture)?;
+    files_manager.delete_released().await?;
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ReorderGiftPictures {
+    pictures: Vec<String>,
+}
+
+pub async fn reorder_gift_pictures(
+    State(wishlist_manager): State<WishlistManager>,
+    auth_user: AuthUser,
+    Path((category_id, gift_id)): Path<(i64, i64)>,
+    Json(reorder_gift_pictures): Json<ReorderGiftPictures>,
+) -> Result<StatusCode, AppError> {
+    if !wishlist_manager.is_my_gift(auth_user.id, category_id, gift_id, false)? {
+        return Err(AppError::Unauthorized);
+    }
+    wishlist_manager.reorder_gift_pictures(gift_id, &reorder_gift_pictures.pictures)?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn reorder_secret_gift_pictures(
+    State(wishlist_manager): State<WishlistManager>,
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+    Path((friend_id, category_id, gift_id)): Path<(i64, i64, i64)>,
+    Json(reorder_gift_pictures): Json<ReorderGiftPictures>,
+) -> Result<StatusCode, AppError> {
+    if !friends_manager.is_my_friend(auth_user.id, friend_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    if !wishlist_manager.is_my_gift(friend_id, category_id, gift_id, true)?
+        || !wishlist_manager.is_visible_category(auth_user.id, friend_id, category_id)?
+    {
+        return Err(AppError::Unauthorized);
+    }
+    wishlist_manager.reorder_gift_pictures(gift_id, &reorder_gift_pictures.pictures)?;
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SetEvent {
+    event: Option<EventKind>,
+}
+
+pub async fn set_category_event(
+    State(wishlist_manager): State<WishlistManager>,
+    auth_user: AuthUser,
+    Path(category_id): Path<i64>,
+    Json(set_event): Json<SetEvent>,
+) -> Result<StatusCode, AppError> {
+    if !wishlist_manager.is_my_category(auth_user.id, category_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    wishlist_manager.set_category_event(auth_user.id, category_id, set_event.event)?;
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SetVisibility {
+    visible_to: Option<Visibility>,
+}
+
+pub async fn set_category_visibility(
+    State(wishlist_manager): State<WishlistManager>,
+    State(friends_manager): State<FriendsManager>,
+    auth_user: AuthUser,
+    Path(category_id): Path<i64>,
+    Json(set_visibility): Json<SetVisibility>,
+) -> Result<StatusCode, AppError> {
+    if !wishlist_manager.is_my_category(auth_user.id, category_id)? {
+        return Err(AppError::Unauthorized);
+    }
+    if let Some(visibility) = &set_visibility.visible_to {
+        for group_id in &visibility.groups {
+            if !friends_manager.is_my_group(auth_user.id, *group_id)? {
+                return Err(AppError::Unauthorized);
+            }
+        }
+        for friend_id in &visibility.friends {
+            if !friends_manager.is_my_friend(auth_user.id, *friend_id)? {
+                return Err(AppError::WishlistManager(
+                    WishlistManagerError::UnknownUser(*friend_id),
+                ));
+            }
+        }
+    }
+    wishlist_manager.set_category_visibility(
+        auth_user.id,
+        category_id,
+        set_visibility.visible_to,
+    )?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn set_gift_event(
+    State(wishlist_manager): State<WishlistManager>,
+    auth_user: AuthUser,
+    Path((category_id, gift_id)): Path<(i64, i64)>,
+    Json(set_event): Json<SetEvent>,
+) -> Result<StatusCode, AppError> {
+    if !wishlist_manager.is_my_gift(auth_user.id, category_id, gift_id, false)? {
+        return Err(AppError::Unauthorized);
+    }
+    wishlist_manager.set_gift_event(auth_user.id, gift_id, set_event.event)?;
     Ok(StatusCode::OK)
 }
 
@@ -276,7 +497,7 @@ pub async fn change_heart_gift(
     auth_user: AuthUser,
     Path((category_id, gift_id)): Path<(i64, i64)>,
 ) -> Result<StatusCode, AppError> {
-    if !wishlist_manager.is_my_gift(auth_user.id, category_id, gift_id)? {
+    if !wishlist_manager.is_my_gift(auth_user.id, category_id, gift_id, false)? {
         return Err(AppError::Unauthorized);
     }
     wishlist_manager.change_heart_gift(gift_id)?;
@@ -289,7 +510,9 @@ pub async fn reserve_gift(
     auth_user: AuthUser,
     Path((friend_id, gift_id)): Path<(i64, i64)>,
 ) -> Result<StatusCode, AppError> {
-    if !friends_manager.is_my_friend(auth_user.id, friend_id)? {
+    if !friends_manager.is_my_friend(auth_user.id, friend_id)?
+        || !wishlist_manager.is_visible_gift(auth_user.id, friend_id, gift_id)?
+    {
         return Err(AppError::Unauthorized);
     }
     if wishlist_manager.is_gift_reserved(gift_id)? {
@@ -338,23 +561,74 @@ pub async fn get_friend_wishlist(
     Ok((StatusCode::OK, Json(wishlist)))
 }
 
+#[derive(Deserialize)]
+pub(crate) struct PdfQuery {
+    event: Option<EventKind>,
+    paper: Option<PaperSize>,
+    orientation: Option<Orientation>,
+    style: Option<LayoutStyle>,
+    columns: Option<u32>,
+    rows: Option<u32>,
+    theme: Option<Theme>,
+    title_page: Option<bool>,
+    language: Option<Language>,
+}
+
 pub async fn get_wishlist_pdf(
+    State(users_manager): State<UsersManager>,
     State(wishlist_manager): State<WishlistManager>,
     State(friends_manager): State<FriendsManager>,
-    State(configuration): State<Arc<Configuration>>,
+    State(files_manager): State<FilesManager>,
     auth_user: AuthUser,
     Path(user_id): Path<i64>,
+    Query(pdf_query): Query<PdfQuery>,
 ) -> impl IntoResponse {
-    let pdf = if auth_user.id == user_id {
-        let wishlist = wishlist_manager.get_my_wishlist(auth_user.id)?;
-        get_pdf(wishlist, configuration)
-    } else if friends_manager.is_my_friend(auth_user.id, user_id)? { 
-        let wishlist = wishlist_manager.get_friend_wishlist(auth_user.id, user_id)?.into();
-        get_pdf(wishlist, configuration)
+    let wishlist = if auth_user.id == user_id {
+        let mut wishlist = wishlist_manager.get_my_wishlist(auth_user.id)?;
+        if let Some(event) = pdf_query.event {
+            wishlist.retain_event(event);
+        }
+        PdfWishList::from(wishlist)
+    } else if friends_manager.is_my_friend(auth_user.id, user_id)? {
+        let mut wishlist = wishlist_manager.get_friend_wishlist(auth_user.id, user_id)?;
+        if let Some(event) = pdf_query.event {
+            wishlist.retain_event(event);
+        }
+        PdfWishList::from_friend_wishlist(wishlist, auth_user.id)
     } else {
         return Err(AppError::Unauthorized);
     };
-    
+
+    let theme = pdf_query.theme.unwrap_or_default();
+    let options = PdfOptions {
+        title: users_manager.get_name(user_id)?,
+        event: pdf_query.event,
+        layout: Layout::new(
+            pdf_query.paper,
+            pdf_query.orientation,
+            pdf_query.style,
+            pdf_query.columns,
+            pdf_query.rows,
+        ),
+        theme,
+        title_page: pdf_query.title_page.unwrap_or(theme.title_page()),
+        language: pdf_query.language.unwrap_or_default(),
+    };
+    //The list layout has no picture
+    let with_pictures = options.layout.style == LayoutStyle::Grid;
+    let mut pictures = HashMap::new();
+    for gift in wishlist.categories.iter().flat_map(|c| &c.gifts) {
+        if let Some(picture) = gift.picture.as_ref().filter(|_| with_pictures) {
+            if let Some(content) = files_manager
+                .create_variant(picture, ImageSize::Print)
+                .await?
+            {
+                pictures.insert(picture.clone(), content);
+            }
+        }
+    }
+    let pdf = get_pdf(wishlist, &pictures, &options)?;
+
     let body = Body::from(pdf);
     let headers = [(header::CONTENT_TYPE, "application/pdf")];
     Ok((headers, body))
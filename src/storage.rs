@@ -0,0 +1,289 @@
+use crate::configuration::{Configuration, StorageConfiguration};
+use futures_util::future::BoxFuture;
+use futures_util::{FutureExt, TryStreamExt};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, PutPayload};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/**
+Flat set of named files, uploads and resized pictures being stored in two different ones
+**/
+pub trait Storage: Send + Sync {
+    fn get<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, StorageError>>;
+
+    fn exists<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<bool, StorageError>>;
+
+    fn put<'a>(
+        &'a self,
+        name: &'a str,
+        content: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), StorageError>>;
+
+    /**
+    Deleting a file that does not exist is not an error
+    **/
+    fn delete<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<(), StorageError>>;
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, StorageError>>;
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub(crate) enum StorageError {
+    Io(#[from] std::io::Error),
+    ObjectStore(#[from] object_store::Error),
+}
+
+#[derive(Clone)]
+pub struct Storages {
+    pub uploads: Arc<dyn Storage>,
+    /** Resized versions of the uploads **/
+    pub cache: Arc<dyn Storage>,
+}
+
+pub fn create_storages(configuration: &Configuration) -> Result<Storages, StorageError> {
+    match &configuration.storage {
+        None | Some(StorageConfiguration::Local) => {
+            let uploads = PathBuf::from(&configuration.upload_file_storage);
+            let cache = uploads
+                .parent()
+                .map(|p| p.join("tmp_rs"))
+                .unwrap_or_else(|| PathBuf::from("tmp_rs"));
+            fs::create_dir_all(&cache)?;
+            Ok(Storages {
+                uploads: Arc::new(LocalStorage::new(uploads)),
+                cache: Arc::new(LocalStorage::new(cache)),
+            })
+        }
+        Some(StorageConfiguration::S3 {
+            endpoint,
+            region,
+            bucket,
+            access_key_id,
+            secret_access_key,
+            allow_http,
+        }) => {
+            let store: Arc<dyn ObjectStore> = Arc::new(
+                AmazonS3Builder::new()
+                    .with_endpoint(endpoint)
+                    .with_region(region)
+                    .with_bucket_name(bucket)
+                    .with_access_key_id(access_key_id)
+                    .with_secret_access_key(secret_access_key)
+                    .with_allow_http(*allow_http)
+                    .build()?,
+            );
+            Ok(Storages {
+                uploads: Arc::new(S3Storage::new(store.clone(), "uploads")),
+                cache: Arc::new(S3Storage::new(store, "tmp_rs")),
+            })
+        }
+    }
+}
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+impl Storage for LocalStorage {
+    fn get<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, StorageError>> {
+        async move {
+            match tokio::fs::read(self.root.join(name)).await {
+                Ok(content) => Ok(Some(content)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+        .boxed()
+    }
+
+    fn exists<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<bool, StorageError>> {
+        async move { Ok(tokio::fs::try_exists(self.root.join(name)).await?) }.boxed()
+    }
+
+    fn put<'a>(
+        &'a self,
+        name: &'a str,
+        content: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move {
+            tokio::fs::write(self.root.join(name), content).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move {
+            match tokio::fs::remove_file(self.root.join(name)).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            }
+        }
+        .boxed()
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, StorageError>> {
+        async move {
+            let mut names = Vec::new();
+            let mut entries = tokio::fs::read_dir(&self.root).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_file() {
+                    names.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+            Ok(names)
+        }
+        .boxed()
+    }
+}
+
+/**
+Any S3 compatible object storage, files being under a prefix of the bucket
+**/
+pub struct S3Storage {
+    store: Arc<dyn ObjectStore>,
+    prefix: String,
+}
+
+impl S3Storage {
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: &str) -> Self {
+        Self {
+            store,
+            prefix: prefix.to_string(),
+        }
+    }
+
+    fn path(&self, name: &str) -> ObjectPath {
+        ObjectPath::from(self.prefix.as_str()).child(name)
+    }
+}
+
+impl Storage for S3Storage {
+    fn get<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, StorageError>> {
+        async move {
+            match self.store.get(&self.path(name)).await {
+                Ok(result) => Ok(Some(result.bytes().await?.to_vec())),
+                Err(object_store::Error::NotFound { .. }) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+        .boxed()
+    }
+
+    fn exists<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<bool, StorageError>> {
+        async move {
+            match self.store.head(&self.path(name)).await {
+                Ok(_) => Ok(true),
+                Err(object_store::Error::NotFound { .. }) => Ok(false),
+                Err(e) => Err(e.into()),
+            }
+        }
+        .boxed()
+    }
+
+    fn put<'a>(
+        &'a self,
+        name: &'a str,
+        content: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move {
+            self.store
+                .put(&self.path(name), PutPayload::from(content))
+                .await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move {
+            match self.store.delete(&self.path(name)).await {
+                Err(e) if !matches!(e, object_store::Error::NotFound { .. }) => Err(e.into()),
+                _ => Ok(()),
+            }
+        }
+        .boxed()
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, StorageError>> {
+        async move {
+            let prefix = ObjectPath::from(self.prefix.as_str());
+            let objects: Vec<_> = self.store.list(Some(&prefix)).try_collect().await?;
+            Ok(objects
+                .into_iter()
+                .filter_map(|o| o.location.filename().map(|f| f.to_string()))
+                .collect())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::managers::test_helper::create_test_storage;
+    use crate::storage::{LocalStorage, S3Storage, Storage};
+    use object_store::memory::InMemory;
+    use object_store::path::Path;
+    use object_store::ObjectStore;
+    use std::sync::Arc;
+
+    async fn check_storage(storage: &dyn Storage) {
+        assert_eq!(storage.get("file.png").await.unwrap(), None);
+        storage.put("file.png", b"content".to_vec()).await.unwrap();
+        storage.put("other.png", b"other".to_vec()).await.unwrap();
+        assert_eq!(
+            storage.get("file.png").await.unwrap(),
+            Some(b"content".to_vec())
+        );
+        let mut names = storage.list().await.unwrap();
+        names.sort();
+        assert_eq!(names, vec!["file.png", "other.png"]);
+
+        storage.delete("file.png").await.unwrap();
+        storage.delete("file.png").await.unwrap();
+        assert_eq!(storage.get("file.png").await.unwrap(), None);
+        assert_eq!(storage.list().await.unwrap(), vec!["other.png"]);
+    }
+
+    #[tokio::test]
+    async fn test_local_storage() {
+        let root = create_test_storage("test_local_storage");
+        let storage = LocalStorage::new(root.clone());
+        check_storage(&storage).await;
+        assert!(root.join("other.png").exists());
+    }
+
+    #[tokio::test]
+    async fn test_s3_storage() {
+        let store = Arc::new(InMemory::new());
+        let storage = S3Storage::new(store.clone(), "uploads");
+        check_storage(&storage).await;
+        S3Storage::new(store.clone(), "tmp_rs")
+            .put("other.png", b"resized".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .get(&Path::from("uploads/other.png"))
+                .await
+                .unwrap()
+                .bytes()
+                .await
+                .unwrap()
+                .as_ref(),
+            b"other"
+        );
+        assert_eq!(storage.list().await.unwrap(), vec!["other.png"]);
+    }
+}